@@ -0,0 +1,28 @@
+// cargo run --example mytool > mytool.json
+// completist import clap mytool.json --output mytool.ct
+
+use clap::{Arg, ArgAction, Command, ValueHint};
+
+fn cli() -> Command {
+    Command::new("mytool")
+        .about("Manages remote mirrors")
+        .version("1.2.0")
+        .arg(Arg::new("verbose").short('v').long("verbose").action(ArgAction::Count).global(true)
+            .help("Print more output"))
+        .arg(Arg::new("config").short('c').long("config").visible_alias("conf")
+            .value_hint(ValueHint::FilePath).help("Read settings from this file"))
+        .arg(Arg::new("debug-dump").long("debug-dump").hide(true).action(ArgAction::SetTrue))
+        .subcommand(Command::new("sync").visible_alias("s").about("Copy changes to every mirror")
+            .arg(Arg::new("host").long("host").value_hint(ValueHint::Hostname).help("Only sync this host"))
+            .arg(Arg::new("mode").long("mode").value_parser(["fast", "safe"]).help("How to copy"))
+            .arg(Arg::new("paths").num_args(1..).value_hint(ValueHint::AnyPath)))
+        .subcommand(Command::new("remote").about("Manage the list of mirrors")
+            .subcommand(Command::new("add")
+                .arg(Arg::new("url").required(true).value_hint(ValueHint::Url)))
+            .subcommand(Command::new("remove").arg(Arg::new("name").required(true))))
+        .subcommand(Command::new("internal").hide(true))
+}
+
+fn main() {
+    println!("{}", clap_dump::dump(cli()));
+}
@@ -0,0 +1,58 @@
+// Describes a clap `Command` tree in the JSON that `completist import clap`
+// reads. Add a hidden flag or a small binary to your CLI that prints it:
+//
+//     println!("{}", clap_dump::dump(Cli::command()));
+
+use clap::{Arg, ArgAction, Command};
+use serde_json::{json, Value};
+
+pub fn dump(mut command: Command) -> String {
+    // Building adds the generated help and version flags, and propagates
+    // settings down to subcommands.
+    command.build();
+    serde_json::to_string_pretty(&describe(&command)).unwrap()
+}
+
+fn describe(command: &Command) -> Value {
+    json!({
+        "name": command.get_name(),
+        "about": command.get_about().map(|about| about.to_string()),
+        "version": command.get_version(),
+        "aliases": command.get_visible_aliases().collect::<Vec<_>>(),
+        "hidden": command.is_hide_set(),
+        "args": command.get_arguments().map(describe_arg).collect::<Vec<_>>(),
+        "subcommands": command.get_subcommands().map(describe).collect::<Vec<_>>(),
+    })
+}
+
+fn describe_arg(arg: &Arg) -> Value {
+    let multiple = matches!(arg.get_action(), ArgAction::Append)
+        || arg.get_num_args().map_or(false, |range| range.max_values() > 1);
+    let possible_values: Vec<Value> = arg.get_possible_values().iter()
+        .map(|value| json!({
+            "name": value.get_name(),
+            "help": value.get_help().map(|help| help.to_string()),
+            "hidden": value.is_hide_set(),
+        }))
+        .collect();
+
+    json!({
+        "id": arg.get_id().as_str(),
+        "long": arg.get_long(),
+        "short": arg.get_short().map(|short| short.to_string()),
+        "aliases": arg.get_visible_aliases().unwrap_or_default(),
+        "short_aliases": arg.get_visible_short_aliases().unwrap_or_default()
+            .iter().map(|short| short.to_string()).collect::<Vec<_>>(),
+        "help": arg.get_help().map(|help| help.to_string()),
+        "positional": arg.is_positional(),
+        "takes_value": arg.get_action().takes_values(),
+        "multiple": multiple,
+        "value_names": arg.get_value_names().unwrap_or_default()
+            .iter().map(|name| name.as_str()).collect::<Vec<_>>(),
+        "value_hint": format!("{:?}", arg.get_value_hint()),
+        "possible_values": possible_values,
+        "required": arg.is_required_set(),
+        "global": arg.is_global_set(),
+        "hidden": arg.is_hide_set(),
+    })
+}
@@ -0,0 +1,47 @@
+# bash completion for svc
+
+_svc_units() {
+    systemctl list-units --plain --no-legend | cut -d' ' -f1
+}
+
+_svc() {
+    local cur prev subcommand opts
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    subcommand="${COMP_WORDS[1]}"
+    opts="-h --help -v --verbose -c --config --log-level"
+
+    case "$prev" in
+        -c|--config)
+            _filedir
+            return 0
+            ;;
+        --log-level)
+            COMPREPLY=( $(compgen -W "debug info warn" -- "$cur") )
+            return 0
+            ;;
+    esac
+
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=( $(compgen -W "${opts}" -- "$cur") )
+        return 0
+    fi
+
+    if [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=( $(compgen -W "start stop status logs" -- "$cur") )
+        return 0
+    fi
+
+    case "$subcommand" in
+        start|stop|status)
+            COMPREPLY=( $(compgen -W "$(_svc_units)" -- "$cur") )
+            ;;
+        logs)
+            COMPREPLY=( $(compgen -W "--follow -f today yesterday" -- "$cur") )
+            ;;
+        *)
+            _known_hosts_real "$cur"
+            ;;
+    esac
+}
+complete -F _svc svc svcctl
@@ -0,0 +1,9 @@
+# Completions for the deploy scripts.
+have svcctl &&
+complete -W "start stop status" svcctl
+complete -f -X '!*.conf' confcheck
+complete -d -o nospace goto
+complete -A user -A group chown-ish
+complete -A alias unalias-ish
+complete -C 'deploy --list-targets' deploy
+complete -F _missing nothere
@@ -0,0 +1,24 @@
+"""Back up directories to a remote store.
+
+Usage:
+  backup [options] <source>... <dest>
+  backup restore [--to <dir>] <snapshot>
+  backup list
+  backup prune (daily | weekly | all) [--keep=N]
+  backup --version
+
+Options:
+  -v, --verbose         Print every file as it is copied.
+  -x PATTERN --exclude=PATTERN
+                        Skip files matching PATTERN, can be
+                        given more than once.
+  -c FILE               Read settings from FILE.
+  --to <dir>            Directory to restore into.
+  --keep=N              Number of snapshots to keep [default: 7].
+  --version             Show the version and exit.
+
+"""
+from docopt import docopt
+
+if __name__ == "__main__":
+    arguments = docopt(__doc__, version="backup 2.1")
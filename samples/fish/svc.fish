@@ -0,0 +1,28 @@
+# Completions for svc, written by hand before we had specs.
+
+function __svc_units
+    systemctl list-units --plain --no-legend | string replace -r ' .*' ''
+end
+
+complete -c svc -f
+complete -c svc -s h -l help -d 'Show help and exit'
+complete -c svc -s v -l verbose -d 'Say more about what is happening'
+complete -c svc -s c -l config -r -d 'Read settings from a file'
+complete -c svc -l log-level -x -a 'debug info warn' -d 'How much to log'
+complete -c svc -l color -x -a "(printf '%s\n' auto always never)"
+
+# Subcommands
+complete -c svc -n __fish_use_subcommand -a start -d 'Start a service'
+complete -c svc -n __fish_use_subcommand -a stop -d 'Stop a service'
+complete -c svc -n __fish_use_subcommand -a "logs\t'Show the log of a service'"
+
+complete -c svc -n '__fish_seen_subcommand_from start stop' -a '(__svc_units)'
+complete -c svc -n '__fish_seen_subcommand_from start' -l wait -d 'Wait until it is up'
+complete -c svc -n '__fish_seen_subcommand_from logs; and __fish_contains_opt follow' -s n -x \
+    -d 'Number of lines'
+complete -c svc -n '__fish_seen_subcommand_from logs' -s f -l follow -d 'Keep printing new lines'
+complete -c svc -n '__fish_seen_subcommand_from logs' -a 'today yesterday' -d 'When from'
+complete -c svc -n '__fish_seen_subcommand_from logs' -a '-'
+
+complete -c svc-ctl -w svc
+set -l svc_cache ~/.cache/svc
@@ -0,0 +1,4 @@
+#!/bin/sh
+# Parses its arguments with util-linux getopt.
+args=$(getopt -n backup -o 'vx:c::W;' --long verbose,exclude:,compress::,dry-run -- "$@") || exit 1
+eval set -- "$args"
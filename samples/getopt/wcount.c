@@ -0,0 +1,40 @@
+#include <getopt.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+enum { OPT_FILES0_FROM = 256, OPT_TOTAL };
+
+static int print_version;
+
+static const char short_options[] = "clmw" "L:z::";
+
+static struct option const long_options[] = {
+    {"bytes", no_argument, NULL, 'c'},
+    {"lines", no_argument, NULL, 'l'},
+    {"chars", no_argument, NULL, 'm'},
+    {"words", no_argument, NULL, 'w'},
+    {"max-line-length", required_argument, NULL, 'L'},
+    {"files0-from", required_argument, NULL, OPT_FILES0_FROM},
+    {"total", optional_argument, NULL, OPT_TOTAL},
+    {"version", no_argument, &print_version, 1},
+    LONG_OPTION_HELP,
+    {NULL, 0, NULL, 0}
+};
+
+static void usage(void)
+{
+    fprintf(stderr, "Usage: wcount [OPTION]... [FILE]...\n");
+    exit(EXIT_FAILURE);
+}
+
+int main(int argc, char **argv)
+{
+    int c;
+    while ((c = getopt_long(argc, argv, short_options, long_options, NULL)) != -1) {
+        switch (c) {
+        default:
+            usage();
+        }
+    }
+    return 0;
+}
@@ -0,0 +1,425 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+extern crate rustc_serialize;
+
+use self::rustc_serialize::json::Json;
+
+use completist::program::{Program, Command, Argument, Opt, OptKind};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ArgvError {
+    UnknownOption(String),
+    MissingValue(String),
+    UnexpectedValue(String, String),
+    InvalidChoice(String, String, Vec<String>),
+    UnknownCommand(String),
+    UnexpectedArgument(String),
+    MissingArgument(String),
+    // The option given, and the one given earlier that it excludes.
+    Conflict(String, String),
+    Repeated(String),
+    // The subcommands that could have been given.
+    MissingCommand(Vec<String>),
+}
+
+impl ArgvError {
+    // A stable name for scripts to match on.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            ArgvError::UnknownOption(..) => "unknown-option",
+            ArgvError::MissingValue(..) => "missing-value",
+            ArgvError::UnexpectedValue(..) => "unexpected-value",
+            ArgvError::InvalidChoice(..) => "invalid-choice",
+            ArgvError::UnknownCommand(..) => "unknown-command",
+            ArgvError::UnexpectedArgument(..) => "unexpected-argument",
+            ArgvError::MissingArgument(..) => "missing-argument",
+            ArgvError::Conflict(..) => "conflict",
+            ArgvError::Repeated(..) => "repeated-option",
+            ArgvError::MissingCommand(..) => "missing-command",
+        }
+    }
+}
+
+impl fmt::Display for ArgvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArgvError::UnknownOption(ref opt) => write!(f, "unknown option `{}`", opt),
+            ArgvError::MissingValue(ref opt) => write!(f, "`{}` expects a value", opt),
+            ArgvError::UnexpectedValue(ref opt, ref value) =>
+                write!(f, "`{}` takes no value, but was given `{}`", opt, value),
+            ArgvError::InvalidChoice(ref what, ref value, ref choices) =>
+                write!(f, "invalid value `{}` for `{}`, expected one of {}", value, what, choices.join(", ")),
+            ArgvError::UnknownCommand(ref word) => write!(f, "unknown subcommand `{}`", word),
+            ArgvError::UnexpectedArgument(ref word) => write!(f, "unexpected argument `{}`", word),
+            ArgvError::MissingArgument(ref name) => write!(f, "missing argument {}", name),
+            ArgvError::Conflict(ref opt, ref other) => write!(f, "`{}` can't be used with `{}`", opt, other),
+            ArgvError::Repeated(ref opt) => write!(f, "`{}` can only be given once", opt),
+            ArgvError::MissingCommand(ref commands) =>
+                write!(f, "missing subcommand, expected one of {}", commands.join(", ")),
+        }
+    }
+}
+
+// A parse failure, along with how far the parser got.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Failure {
+    pub error: ArgvError,
+    pub path: Vec<String>,
+    // The index of the offending word, if there is one.
+    pub index: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Matches {
+    pub path: Vec<String>,
+    // Options by `Opt::name`, with one entry for each time they were given.
+    // Flags have `None` as their value.
+    pub options: Vec<(String, Option<String>)>,
+    // Arguments by name, with the words they matched.
+    pub arguments: Vec<(String, Vec<String>)>,
+}
+
+impl Matches {
+    pub fn count(&self, opt: &str) -> usize {
+        self.options.iter().filter(|&&(ref name, _)| name == opt).count()
+    }
+
+    pub fn values_of(&self, opt: &str) -> Vec<&str> {
+        self.options.iter()
+            .filter(|&&(ref name, _)| name == opt)
+            .filter_map(|&(_, ref value)| value.as_ref().map(|v| &v[..]))
+            .collect()
+    }
+
+    pub fn argument(&self, name: &str) -> Option<&[String]> {
+        self.arguments.iter()
+            .find(|&&(ref arg, _)| arg == name)
+            .map(|&(_, ref values)| &values[..])
+    }
+
+    // Flags map to the number of times they were given, options with values
+    // to the list of values, and arguments to the words they matched.
+    pub fn to_json(&self) -> Json {
+        let mut options = BTreeMap::new();
+        for &(ref name, ref value) in &self.options {
+            match *value {
+                Some(ref value) => {
+                    let entry = options.entry(name.clone()).or_insert_with(|| Json::Array(Vec::new()));
+                    if let Json::Array(ref mut values) = *entry {
+                        values.push(Json::String(value.clone()));
+                    }
+                },
+                None => {
+                    let count = options.get(name).and_then(|c| c.as_u64()).unwrap_or(0);
+                    options.insert(name.clone(), Json::U64(count + 1));
+                },
+            }
+        }
+
+        let mut arguments = BTreeMap::new();
+        for &(ref name, ref values) in &self.arguments {
+            arguments.insert(name.clone(), strings(values));
+        }
+
+        let mut json = BTreeMap::new();
+        json.insert("ok".to_string(), Json::Boolean(true));
+        json.insert("command".to_string(), strings(&self.path));
+        json.insert("options".to_string(), Json::Object(options));
+        json.insert("arguments".to_string(), Json::Object(arguments));
+        Json::Object(json)
+    }
+}
+
+impl Failure {
+    pub fn to_json(&self) -> Json {
+        let mut json = BTreeMap::new();
+        json.insert("ok".to_string(), Json::Boolean(false));
+        json.insert("error".to_string(), Json::String(self.error.kind().to_string()));
+        json.insert("message".to_string(), Json::String(self.error.to_string()));
+        json.insert("command".to_string(), strings(&self.path));
+        json.insert("index".to_string(), self.index.map_or(Json::Null, |i| Json::U64(i as u64)));
+        Json::Object(json)
+    }
+}
+
+fn strings(values: &[String]) -> Json {
+    Json::Array(values.iter().map(|v| Json::String(v.clone())).collect())
+}
+
+fn check_choice(kind: &OptKind, what: &str, value: &str) -> Result<(), ArgvError> {
+    match *kind {
+        OptKind::Choices(ref choices) if !choices.iter().any(|c| c == value) =>
+            Err(ArgvError::InvalidChoice(what.to_string(), value.to_string(), choices.clone())),
+        _ => Ok(()),
+    }
+}
+
+// Follows the same rules as the completion engine: options belong to the
+// subcommand they follow, a subcommand can only be the first positional, and
+// `--` ends option parsing.
+struct Parser<'a> {
+    command: &'a Command,
+    matches: Matches,
+    positionals: usize,
+    separated: bool,
+    // The options given so far, as they were spelled.
+    used: Vec<(&'a Opt, String)>,
+    // Whether an option was given since the last subcommand.
+    options_here: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn find_long(&self, long: &str) -> Option<&'a Opt> {
+        let command = self.command;
+        command.options.iter().find(|opt| opt.longs.iter().any(|l| l == long))
+    }
+
+    fn find_short(&self, short: char) -> Option<&'a Opt> {
+        let command = self.command;
+        command.options.iter().find(|opt| opt.shorts.iter()
+            .any(|s| s.len() == 1 + short.len_utf8() && s.ends_with(short)))
+    }
+
+    fn check_use(&self, opt: &Opt, given: &str) -> Result<(), ArgvError> {
+        let names = |o: &Opt| o.longs.iter().chain(o.shorts.iter()).cloned().collect::<Vec<String>>();
+        for &(earlier, ref earlier_given) in &self.used {
+            if earlier as *const Opt == opt as *const Opt {
+                if !opt.repeatable {
+                    return Err(ArgvError::Repeated(given.to_string()));
+                }
+            } else if opt.excludes.iter().any(|e| names(earlier).contains(e))
+                    || earlier.excludes.iter().any(|e| names(opt).contains(e)) {
+                return Err(ArgvError::Conflict(given.to_string(), earlier_given.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn use_opt(&mut self, opt: &'a Opt, given: &str, value: Option<String>) -> Result<(), ArgvError> {
+        try!(self.check_use(opt, given));
+        self.used.push((opt, given.to_string()));
+        self.options_here = true;
+        match (&opt.argkind, value) {
+            (&Some(ref kind), Some(value)) => {
+                try!(check_choice(kind, given, &value));
+                self.matches.options.push((opt.name(), Some(value)));
+            },
+            (&Some(_), None) => return Err(ArgvError::MissingValue(given.to_string())),
+            (&None, Some(value)) => return Err(ArgvError::UnexpectedValue(given.to_string(), value)),
+            (&None, None) => self.matches.options.push((opt.name(), None)),
+        }
+        Ok(())
+    }
+
+    fn argument(&self) -> Option<&'a Argument> {
+        let index = self.positionals;
+        let command = self.command;
+        command.arguments.iter().enumerate()
+            .find(|&(i, arg)| i == index || (i < index && arg.kind == OptKind::FilePlus))
+            .map(|(_, arg)| arg)
+    }
+
+    fn positional(&mut self, word: &str) -> Result<(), ArgvError> {
+        if !self.separated && self.positionals == 0 {
+            let command = self.command;
+            if let Some(sub) = command.commands.iter().find(|c| c.name == word) {
+                self.command = sub;
+                self.matches.path.push(word.to_string());
+                self.options_here = false;
+                return Ok(());
+            }
+        }
+
+        let arg = match self.argument() {
+            Some(arg) => arg,
+            None if !self.separated && self.positionals == 0 && !self.command.commands.is_empty() =>
+                return Err(ArgvError::UnknownCommand(word.to_string())),
+            None => return Err(ArgvError::UnexpectedArgument(word.to_string())),
+        };
+        try!(check_choice(&arg.kind, &arg.name, word));
+        self.positionals += 1;
+        match self.matches.arguments.iter_mut().find(|&&mut (ref name, _)| *name == arg.name) {
+            Some(&mut (_, ref mut values)) => values.push(word.to_string()),
+            None => self.matches.arguments.push((arg.name.clone(), vec![word.to_string()])),
+        }
+        Ok(())
+    }
+
+    // Returns whether the next word was taken as a value.
+    fn word(&mut self, word: &str, next: Option<&String>) -> Result<bool, ArgvError> {
+        if self.separated || word == "-" || !word.starts_with('-') {
+            try!(self.positional(word));
+        } else if word == "--" {
+            self.separated = true;
+        } else if word.starts_with("--") {
+            let (long, value) = match word.find('=') {
+                Some(i) => (&word[..i], Some(word[i + 1..].to_string())),
+                None => (word, None),
+            };
+            let opt = try!(self.find_long(long).ok_or_else(|| ArgvError::UnknownOption(long.to_string())));
+            if opt.argkind.is_some() && value.is_none() {
+                if let Some(next) = next {
+                    try!(self.use_opt(opt, long, Some(next.clone())));
+                    return Ok(true);
+                }
+            }
+            try!(self.use_opt(opt, long, value));
+        } else {
+            let cluster = &word[1..];
+            for (i, c) in cluster.char_indices() {
+                let given = format!("-{}", c);
+                let opt = try!(self.find_short(c).ok_or_else(|| ArgvError::UnknownOption(given.clone())));
+                if opt.argkind.is_none() {
+                    try!(self.use_opt(opt, &given, None));
+                    continue;
+                }
+
+                let rest = &cluster[i + c.len_utf8()..];
+                if !rest.is_empty() {
+                    try!(self.use_opt(opt, &given, Some(rest.to_string())));
+                    return Ok(false);
+                }
+                try!(self.use_opt(opt, &given, next.cloned()));
+                return Ok(next.is_some());
+            }
+        }
+        Ok(false)
+    }
+}
+
+// `words` are the arguments after the program name.
+pub fn parse(prog: &Program, words: &[String]) -> Result<Matches, Failure> {
+    let mut parser = Parser {
+        command: &prog.base_command,
+        matches: Matches { path: Vec::new(), options: Vec::new(), arguments: Vec::new() },
+        positionals: 0,
+        separated: false,
+        used: Vec::new(),
+        options_here: false,
+    };
+
+    let mut i = 0;
+    while i < words.len() {
+        match parser.word(&words[i], words.get(i + 1)) {
+            Ok(true) => i += 2,
+            Ok(false) => i += 1,
+            Err(error) => return Err(Failure { error: error, path: parser.matches.path, index: Some(i) }),
+        }
+    }
+
+    // A command that only groups subcommands needs one, unless it was given
+    // an option such as `--version`.
+    let command = parser.command;
+    if !command.commands.is_empty() && command.arguments.is_empty() && !parser.options_here {
+        return Err(Failure {
+            error: ArgvError::MissingCommand(command.commands.iter().map(|c| c.name.clone()).collect()),
+            path: parser.matches.path,
+            index: None,
+        });
+    }
+    for arg in &command.arguments {
+        if !arg.optional && parser.matches.argument(&arg.name).is_none() {
+            return Err(Failure {
+                error: ArgvError::MissingArgument(arg.name.clone()),
+                path: parser.matches.path,
+                index: None,
+            });
+        }
+    }
+    Ok(parser.matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+
+    const SPEC: &'static str = "
+        name = 'svc'
+        [[option]]
+        long = '--verbose'
+        short = '-v'
+        description = 'say more'
+        [[option]]
+        longs = ['--config', '--conf']
+        short = '-c'
+        argkind = 'file'
+        description = 'settings'
+        [[command]]
+        name = 'start'
+        [[command.argument]]
+        name = 'MODE'
+        kind = 'choice(fast|slow)'
+        [[command.argument]]
+        name = 'UNITS'
+        kind = 'file+'
+        optional = true
+        [[command.option]]
+        long = '--wait'
+        short = '-w'
+        argkind = 'choice(yes|no)'
+        description = 'wait'
+        [[command.option]]
+        long = '--now'
+        description = 'now'
+        excludes = ['--wait', '-w']
+        repeatable = false
+    ";
+
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|w| w.to_string()).collect()
+    }
+
+    fn fail(prog: &Program, line: &str) -> (ArgvError, Option<usize>) {
+        let failure = parse(prog, &words(line)).unwrap_err();
+        (failure.error, failure.index)
+    }
+
+    #[test]
+    fn matches() {
+        let prog = parse_program(SPEC).unwrap();
+        let matches = parse(&prog, &words("-vv --conf=a.toml start -wno fast x -- -y")).unwrap();
+        assert_eq!(matches.path, vec!["start"]);
+        assert_eq!(matches.count("verbose"), 2);
+        assert_eq!(matches.values_of("config"), vec!["a.toml"]);
+        assert_eq!(matches.values_of("wait"), vec!["no"]);
+        assert_eq!(matches.argument("MODE"), Some(&["fast".to_string()][..]));
+        assert_eq!(matches.argument("UNITS"), Some(&["x".to_string(), "-y".to_string()][..]));
+
+        let matches = parse(&prog, &words("-vc a.toml -c b.toml")).unwrap();
+        assert_eq!(matches.values_of("config"), vec!["a.toml", "b.toml"]);
+        assert!(matches.path.is_empty());
+        assert_eq!(matches.to_json().to_string(),
+            r#"{"arguments":{},"command":[],"ok":true,"options":{"config":["a.toml","b.toml"],"verbose":1}}"#);
+    }
+
+    #[test]
+    fn errors() {
+        let prog = parse_program(SPEC).unwrap();
+        assert_eq!(fail(&prog, "--quiet"), (ArgvError::UnknownOption("--quiet".to_string()), Some(0)));
+        assert_eq!(fail(&prog, "-vx"), (ArgvError::UnknownOption("-x".to_string()), Some(0)));
+        assert_eq!(fail(&prog, "start fast --verbose"), (ArgvError::UnknownOption("--verbose".to_string()), Some(2)));
+        assert_eq!(fail(&prog, "-v --config"), (ArgvError::MissingValue("--config".to_string()), Some(1)));
+        assert_eq!(fail(&prog, "--verbose=yes"),
+            (ArgvError::UnexpectedValue("--verbose".to_string(), "yes".to_string()), Some(0)));
+        assert_eq!(fail(&prog, "start -w maybe"),
+            (ArgvError::InvalidChoice("-w".to_string(), "maybe".to_string(), words("yes no")), Some(1)));
+        assert_eq!(fail(&prog, "start medium"),
+            (ArgvError::InvalidChoice("MODE".to_string(), "medium".to_string(), words("fast slow")), Some(1)));
+        assert_eq!(fail(&prog, "stop"), (ArgvError::UnknownCommand("stop".to_string()), Some(0)));
+        assert_eq!(fail(&prog, "-v -- stop"), (ArgvError::UnexpectedArgument("stop".to_string()), Some(2)));
+        assert_eq!(fail(&prog, "start -w yes"), (ArgvError::MissingArgument("MODE".to_string()), None));
+        assert_eq!(fail(&prog, "start -w yes --now fast"),
+            (ArgvError::Conflict("--now".to_string(), "-w".to_string()), Some(3)));
+        assert_eq!(fail(&prog, "start --now --wait=no fast"),
+            (ArgvError::Conflict("--wait".to_string(), "--now".to_string()), Some(2)));
+        assert_eq!(fail(&prog, "start --now fast --now"), (ArgvError::Repeated("--now".to_string()), Some(3)));
+        assert_eq!(fail(&prog, ""), (ArgvError::MissingCommand(words("start")), None));
+        assert!(parse(&prog, &words("-v")).is_ok());
+
+        let failure = parse(&prog, &words("start --wait=maybe")).unwrap_err();
+        assert_eq!(failure.to_json().to_string(),
+            r#"{"command":["start"],"error":"invalid-choice","index":1,"message":"invalid value `maybe` for `--wait`, expected one of yes, no","ok":false}"#);
+    }
+}
@@ -0,0 +1,137 @@
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{capture, Output, Write};
+use completist::program::Program;
+use super::ksh::{self, Dialect};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("bash");
+    builder.exts(&[".bash"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    let ident = ksh::identifier(&prog.name);
+    let mut comments = Vec::new();
+    let candidates = try!(capture(|out| ksh::write_candidates(out, Dialect::Bash, prog, &mut comments)));
+
+    try!(out.write_fmt(format_args!("# completions for {}, generated by completist\n", prog.name)));
+    try!(out.write_all(b"# short options can't be grouped, as in -abc\n"));
+    if !comments.is_empty() {
+        try!(out.write_all(b"#\n# not expressible in bash:\n"));
+        for comment in &comments {
+            try!(out.write_fmt(format_args!("#   {}\n", comment)));
+        }
+    }
+    try!(out.write_all(b"\n"));
+    try!(out.write_all(candidates.as_bytes()));
+
+    // The line is split on whitespace alone rather than COMP_WORDS, which
+    // also breaks at `=` and `:`. The part of the current word before bash's
+    // own current word is taken off the matches again.
+    try!(out.write_fmt(format_args!("\n_completist_{}() {{\n", ident)));
+    try!(out.write_all(br#"    local IFS=$'\n' line="${COMP_LINE:0:COMP_POINT}" current= candidates candidate
+    local -a words matches
+    IFS=$' \t' read -ra words <<< "$line"
+    if [[ $line != *[[:space:]] ]]; then
+        current=${words[${#words[@]}-1]}
+        unset "words[${#words[@]}-1]"
+    fi
+"#));
+    try!(out.write_fmt(format_args!(
+        "    candidates=$(_completist_{}_candidates \"$current\" \"${{words[@]}}\")\n", ident)));
+    try!(out.write_all(br#"    case $? in
+        0) ;;
+        2)
+            compopt -o filenames 2> /dev/null
+            COMPREPLY=( $(compgen -f -- "$current") )
+            return 0 ;;
+        *) return 0 ;;
+    esac
+    while IFS= read -r candidate; do
+        [[ -n $candidate && $candidate == "$current"* ]] && matches+=( "$candidate" )
+    done <<< "$candidates"
+    local before="${current%"${COMP_WORDS[COMP_CWORD]}"}"
+    COMPREPLY=( "${matches[@]#"$before"}" )
+}
+"#));
+    try!(out.write_fmt(format_args!("complete -F _completist_{} {}\n", ident, ksh::quote(&prog.name))));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn write_completion() {
+        let prog = parse_program("
+            name = 'my-svc'
+            [[option]]
+            long = '--verbose'
+            short = '-v'
+            description = 'say more'
+            [[command]]
+            name = 'start'
+            [[command.argument]]
+            name = 'MODE'
+            kind = 'choice(fast|really slow)'
+            [[command.option]]
+            long = '--unit'
+            argkind = 'command(ls /etc/init.d)'
+            description = 'unit'
+            cache = 10
+        ").unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+
+        assert!(!out.contains("not expressible"));
+        assert!(out.contains("function _completist_my_svc_cached {\n    typeset run=sh"));
+        assert!(!out.contains("print -r"));
+        assert!(out.contains("function _completist_my_svc_candidates {\n    typeset current=$1"));
+        assert!(out.contains("    'my-svc start/argument MODE') printf '%s\\n' 'fast' 'really slow' ;;\n"));
+        assert!(out.contains("    'my-svc start/COMPLETIST_OPT_UNIT') _completist_my_svc_cached 10 1 'ls /etc/init.d' ;;\n"));
+        assert!(out.contains("    candidates=$(_completist_my_svc_candidates \"$current\" \"${words[@]}\")\n"));
+        assert!(out.ends_with("}\ncomplete -F _completist_my_svc 'my-svc'\n"));
+    }
+
+    #[test]
+    fn cache_command_output() {
+        let dir = env::temp_dir().join(format!("completist-bash-{}", process::id()));
+        let prog = parse_program("
+            name = 'svc'
+            [[option]]
+            long = '--unit'
+            argkind = 'command(echo run >> runs; echo $COMPLETIST_OPT_HOST-unit)'
+            description = 'unit'
+            cache = 60
+            [[option]]
+            long = '--host'
+            argkind = 'command(echo run >> runs; echo host; exit 1)'
+            description = 'host'
+            cache = 60
+        ").unwrap();
+        let script = format!("{}
+            export XDG_CACHE_HOME=cache
+            _completist_svc_candidates '' svc --unit
+            _completist_svc_candidates '' svc --unit
+            _completist_svc_candidates '' svc --host a --unit
+            _completist_svc_candidates '' svc --host
+            _completist_svc_candidates '' svc --host
+            wc -l < runs
+        ", capture(|out| write_program(out, &prog)).unwrap());
+        fs::create_dir_all(&dir).unwrap();
+        let output = process::Command::new("bash").arg("-c").arg(&script).current_dir(&dir).output();
+        fs::remove_dir_all(&dir).ok();
+
+        let output = match output {
+            Ok(output) => output,
+            Err(_) => return,
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().map(|l| l.trim()).collect();
+        assert_eq!(lines, vec!["-unit", "-unit", "a-unit", "host", "host", "4"]);
+    }
+}
@@ -0,0 +1,239 @@
+use std::io::Result;
+
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{capture, Output, Write};
+use completist::program::{Program, Command, Opt, OptKind};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("carapace");
+    builder.exts(&[".yaml", ".yml"]).writer(write_program).checker(warnings);
+    builder.build().unwrap()
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n").replace("\t", "\\t"))
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth)
+}
+
+// The action list for a kind, as carapace macros:
+//   file, file+    -> $files
+//   command(cmd)   -> $(cmd), run by carapace through sh
+//   choice(a|b)    -> the values themselves
+//   function(...)  -> nothing, carapace has no shell to run it in
+fn action(kind: &OptKind, what: &str, warnings: &mut Vec<String>) -> Option<String> {
+    match *kind {
+        OptKind::File | OptKind::FilePlus => Some(quote("$files")),
+        OptKind::Command(ref cmd) => Some(quote(&format!("$({})", cmd))),
+        OptKind::Choices(ref choices) => {
+            let choices: Vec<String> = choices.iter().map(|c| quote(c)).collect();
+            Some(choices.join(", "))
+        },
+        OptKind::Function(ref func) => {
+            warnings.push(format!("{}: function `{}` cannot be run by carapace", what, func));
+            None
+        },
+    }
+}
+
+// Carapace flags have at most one shorthand and one longhand; a longhand with
+// a single dash is carapace's non-posix style.
+fn flag_name(opt: &Opt, what: &str, warnings: &mut Vec<String>) -> (String, String) {
+    if opt.longs.len() > 1 || opt.shorts.len() > 1 {
+        warnings.push(format!("{}: only the first short and long name are kept", what));
+    }
+
+    let mut names = Vec::new();
+    if let Some(short) = opt.shorts.first() {
+        names.push(short.clone());
+    }
+    if let Some(long) = opt.longs.first() {
+        names.push(long.clone());
+    }
+
+    let mut name = names.join(", ");
+    if opt.argkind.is_some() {
+        name.push('=');
+    }
+    (name, opt.name())
+}
+
+fn write_command(out: &mut Output, depth: usize, path: &[&str], command: &Command,
+                 warnings: &mut Vec<String>) -> Result<()> {
+    let pad = indent(depth);
+    let what = path.join(" ");
+
+    let mut flags = Vec::new();
+    let mut flag_actions = Vec::new();
+    for opt in &command.options {
+        let what = format!("{} {}", what, opt.longs.first().or(opt.shorts.first()).unwrap());
+        let (name, key) = flag_name(opt, &what, warnings);
+        flags.push((name, &opt.description));
+        if let Some(ref kind) = opt.argkind {
+            if opt.cache.is_some() {
+                warnings.push(format!("{}: cache settings are dropped", what));
+            }
+            if let Some(action) = action(kind, &what, warnings) {
+                flag_actions.push((key, action));
+            }
+        }
+    }
+
+    let mut positionals = Vec::new();
+    let mut any = None;
+    for arg in &command.arguments {
+        let what = format!("{} {}", what, arg.name);
+        if arg.cache.is_some() {
+            warnings.push(format!("{}: cache settings are dropped", what));
+        }
+        if any.is_some() {
+            warnings.push(format!("{}: comes after a variadic argument and is dropped", what));
+            continue;
+        }
+        let action = action(&arg.kind, &what, warnings).unwrap_or(String::new());
+        if arg.kind == OptKind::FilePlus {
+            any = Some(action);
+        } else {
+            positionals.push(action);
+        }
+    }
+
+    if !flags.is_empty() {
+        try!(out.write_fmt(format_args!("{}flags:\n", pad)));
+        for (name, description) in flags {
+            try!(out.write_fmt(format_args!("{}  {}: {}\n", pad, quote(&name), quote(description))));
+        }
+    }
+
+    if !flag_actions.is_empty() || !positionals.is_empty() || any.is_some() {
+        try!(out.write_fmt(format_args!("{}completion:\n", pad)));
+        if !flag_actions.is_empty() {
+            try!(out.write_fmt(format_args!("{}  flag:\n", pad)));
+            for (key, action) in flag_actions {
+                try!(out.write_fmt(format_args!("{}    {}: [{}]\n", pad, quote(&key), action)));
+            }
+        }
+        if !positionals.is_empty() {
+            try!(out.write_fmt(format_args!("{}  positional:\n", pad)));
+            for action in positionals {
+                try!(out.write_fmt(format_args!("{}    - [{}]\n", pad, action)));
+            }
+        }
+        if let Some(action) = any {
+            try!(out.write_fmt(format_args!("{}  positionalany: [{}]\n", pad, action)));
+        }
+    }
+
+    if !command.commands.is_empty() {
+        try!(out.write_fmt(format_args!("{}commands:\n", pad)));
+        for sub in &command.commands {
+            let mut subpath = path.to_vec();
+            subpath.push(&sub.name);
+            try!(out.write_fmt(format_args!("{}  - name: {}\n", pad, quote(&sub.name))));
+            try!(write_command(out, depth + 2, &subpath, sub, warnings));
+        }
+    }
+    Ok(())
+}
+
+fn translate(prog: &Program) -> Result<(String, Vec<String>)> {
+    let mut warnings = Vec::new();
+    let spec = try!(capture(|out| {
+        try!(out.write_fmt(format_args!("name: {}\n", quote(&prog.name))));
+        write_command(out, 0, &[&prog.name], &prog.base_command, &mut warnings)
+    }));
+
+    let commands = prog.walk().iter().any(|&(_, command)| {
+        command.options.iter().filter_map(|o| o.argkind.as_ref())
+            .chain(command.arguments.iter().map(|a| &a.kind))
+            .any(|kind| match *kind { OptKind::Command(_) => true, _ => false })
+    });
+    if commands {
+        warnings.push("commands see carapace's C_* variables instead of COMPLETIST_*".to_string());
+    }
+    Ok((spec, warnings))
+}
+
+pub fn warnings(prog: &Program) -> Vec<String> {
+    translate(prog).map(|(_, warnings)| warnings).unwrap_or(Vec::new())
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    let (spec, warnings) = try!(translate(prog));
+    try!(out.write_fmt(format_args!("# completions for {}, generated by completist\n", prog.name)));
+    for warning in &warnings {
+        try!(out.write_fmt(format_args!("# warning: {}\n", warning)));
+    }
+    try!(out.write_all(spec.as_bytes()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+
+    const SPEC: &'static str = "
+        name = 'svc'
+        [[option]]
+        longs = ['--verbose', '--loud']
+        short = '-v'
+        description = 'say \"more\"'
+        [[command]]
+        name = 'start'
+        [[command.argument]]
+        name = 'MODE'
+        kind = 'choice(fast|slow)'
+        [[command.argument]]
+        name = 'UNITS'
+        kind = 'file+'
+        [[command.argument]]
+        name = 'LATE'
+        kind = 'file'
+        [[command.option]]
+        long = '--unit'
+        argkind = 'command(ls /etc/init.d)'
+        description = 'unit'
+        cache = 10
+        [[command.option]]
+        long = '-hook'
+        argkind = 'function(__hooks)'
+        description = 'hook'
+    ";
+
+    #[test]
+    fn write_spec() {
+        let prog = parse_program(SPEC).unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+        assert!(out.ends_with("name: \"svc\"\n\
+            flags:\n  \
+              \"-v, --verbose\": \"say \\\"more\\\"\"\n\
+            commands:\n  \
+              - name: \"start\"\n    \
+                flags:\n      \
+                  \"--unit=\": \"unit\"\n      \
+                  \"-hook=\": \"hook\"\n    \
+                completion:\n      \
+                  flag:\n        \
+                    \"unit\": [\"$(ls /etc/init.d)\"]\n      \
+                  positional:\n        \
+                    - [\"fast\", \"slow\"]\n      \
+                  positionalany: [\"$files\"]\n"));
+    }
+
+    #[test]
+    fn lossy_translations() {
+        let prog = parse_program(SPEC).unwrap();
+        assert_eq!(warnings(&prog), vec![
+            "svc --verbose: only the first short and long name are kept",
+            "svc start --unit: cache settings are dropped",
+            "svc start -hook: function `__hooks` cannot be run by carapace",
+            "svc start LATE: comes after a variadic argument and is dropped",
+            "commands see carapace's C_* variables instead of COMPLETIST_*",
+        ]);
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+        assert!(out.contains("\n# warning: svc start --unit: cache settings are dropped\n"));
+    }
+}
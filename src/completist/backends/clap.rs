@@ -0,0 +1,256 @@
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{Output, Write};
+use completist::program::{Program, Command, Argument, Opt, OptKind};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("clap");
+    builder.exts(&[".rs"]).writer(write_program).checker(warnings);
+    builder.build().unwrap()
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n").replace("\t", "\\t"))
+}
+
+fn quote_char(c: char) -> String {
+    match c {
+        '\'' | '\\' => format!("'\\{}'", c),
+        c => format!("'{}'", c),
+    }
+}
+
+// Wraps a builder in the method call that adds it to its parent.
+fn nest(lines: Vec<String>, open: &str) -> Vec<String> {
+    let last = lines.len() - 1;
+    lines.into_iter().enumerate().map(|(i, line)| {
+        let line = if i == 0 { format!("{}{}", open, line) } else { line };
+        format!("    {}{}", line, if i == last { ")" } else { "" })
+    }).collect()
+}
+
+fn methods(constructor: String, calls: Vec<String>) -> Vec<String> {
+    let mut lines = vec![constructor];
+    lines.extend(calls.into_iter().map(|call| format!("    {}", call)));
+    lines
+}
+
+// The clap settings for a kind, and a comment for the ones clap can't
+// complete by itself:
+//   file, file+    -> ValueHint::AnyPath, with any number of values for file+
+//   choice(a|b)    -> a value parser accepting only those values
+//   command(cmd)   -> nothing
+//   function(...)  -> nothing
+fn kind_lines(kind: &OptKind, what: &str, lines: &mut Vec<String>, comments: &mut Vec<String>,
+              warnings: &mut Vec<String>) {
+    match *kind {
+        OptKind::File => lines.push(".value_hint(ValueHint::AnyPath)".to_string()),
+        OptKind::FilePlus => {
+            lines.push(".num_args(1..)".to_string());
+            lines.push(".value_hint(ValueHint::AnyPath)".to_string());
+        },
+        OptKind::Choices(ref choices) => {
+            let choices: Vec<String> = choices.iter().map(|c| quote(c)).collect();
+            lines.push(format!(".value_parser([{}])", choices.join(", ")));
+        },
+        OptKind::Command(ref cmd) => {
+            warnings.push(format!("{}: command `{}` has no clap equivalent", what, cmd));
+            comments.push(format!("// completist completes {} with the command `{}`", what, cmd));
+        },
+        OptKind::Function(ref func) => {
+            warnings.push(format!("{}: function `{}` has no clap equivalent", what, func));
+            comments.push(format!("// completist completes {} with the function `{}`", what, func));
+        },
+    }
+}
+
+fn option_lines(opt: &Opt, what: &str, comments: &mut Vec<String>, warnings: &mut Vec<String>) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut longs = Vec::new();
+    let mut shorts = Vec::new();
+    for name in opt.shorts.iter().chain(opt.longs.iter()) {
+        let bare = name.trim_start_matches('-');
+        if !name.starts_with("--") && bare.chars().count() == 1 {
+            shorts.push(bare.chars().next().unwrap());
+        } else {
+            if !name.starts_with("--") {
+                warnings.push(format!("{}: `{}` is written as `--{}`", what, name, bare));
+            }
+            longs.push(bare);
+        }
+    }
+    for (i, short) in shorts.into_iter().enumerate() {
+        let method = if i == 0 { "short" } else { "visible_short_alias" };
+        lines.push(format!(".{}({})", method, quote_char(short)));
+    }
+    for (i, long) in longs.into_iter().enumerate() {
+        let method = if i == 0 { "long" } else { "visible_alias" };
+        lines.push(format!(".{}({})", method, quote(long)));
+    }
+    if !opt.description.is_empty() {
+        lines.push(format!(".help({})", quote(&opt.description)));
+    }
+    match opt.argkind {
+        Some(ref kind) => {
+            lines.push(format!(".value_name({})", quote(&opt.name().to_uppercase())));
+            kind_lines(kind, what, &mut lines, comments, warnings);
+            lines.push(".action(ArgAction::Set)".to_string());
+        },
+        None => lines.push(".action(ArgAction::SetTrue)".to_string()),
+    }
+    methods(format!("Arg::new({})", quote(&opt.name())), lines)
+}
+
+fn argument_lines(arg: &Argument, what: &str, comments: &mut Vec<String>, warnings: &mut Vec<String>) -> Vec<String> {
+    let mut lines = Vec::new();
+    if !arg.optional {
+        lines.push(".required(true)".to_string());
+    }
+    kind_lines(&arg.kind, what, &mut lines, comments, warnings);
+    methods(format!("Arg::new({})", quote(&arg.name)), lines)
+}
+
+fn command_lines(path: &[&str], command: &Command, warnings: &mut Vec<String>) -> Vec<String> {
+    let what = path.join(" ");
+    let mut lines = vec![format!("Command::new({})", quote(&command.name))];
+    if !command.description.is_empty() {
+        lines.push(format!("    .about({})", quote(&command.description)));
+    }
+
+    for opt in &command.options {
+        let what = format!("{} {}", what, opt.longs.first().or(opt.shorts.first()).unwrap());
+        let mut comments = Vec::new();
+        let arg = option_lines(opt, &what, &mut comments, warnings);
+        lines.extend(comments.into_iter().map(|c| format!("    {}", c)));
+        lines.extend(nest(arg, ".arg("));
+    }
+    for arg in &command.arguments {
+        let what = format!("{} {}", what, arg.name);
+        let mut comments = Vec::new();
+        let arg = argument_lines(arg, &what, &mut comments, warnings);
+        lines.extend(comments.into_iter().map(|c| format!("    {}", c)));
+        lines.extend(nest(arg, ".arg("));
+    }
+    for sub in &command.commands {
+        let mut subpath = path.to_vec();
+        subpath.push(&sub.name);
+        let sub = command_lines(&subpath, sub, warnings);
+        lines.extend(nest(sub, ".subcommand("));
+    }
+    lines
+}
+
+fn translate(prog: &Program) -> (Vec<String>, Vec<String>) {
+    let mut warnings = Vec::new();
+    let mut lines = command_lines(&[&prog.name], &prog.base_command, &mut warnings);
+    let mut meta = Vec::new();
+    if let Some(ref version) = prog.meta.version {
+        meta.push(format!("    .version({})", quote(version)));
+    }
+    if !prog.meta.authors.is_empty() {
+        meta.push(format!("    .author({})", quote(&prog.meta.authors.join(", "))));
+    }
+    let at = if prog.base_command.description.is_empty() { 1 } else { 2 };
+    for (i, line) in meta.into_iter().enumerate() {
+        lines.insert(at + i, line);
+    }
+    (lines, warnings)
+}
+
+pub fn warnings(prog: &Program) -> Vec<String> {
+    translate(prog).1
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    let (lines, _) = translate(prog);
+    try!(out.write_fmt(format_args!("// clap command for {}, generated by completist\n", prog.name)));
+    try!(out.write_fmt(format_args!("use clap::{{Arg, ArgAction, Command, ValueHint}};\n\n")));
+    try!(out.write_fmt(format_args!("pub fn command() -> Command {{\n")));
+    for line in lines {
+        try!(out.write_fmt(format_args!("    {}\n", line)));
+    }
+    try!(out.write_fmt(format_args!("}}\n")));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+    use completist::io::capture;
+
+    const SPEC: &'static str = "
+        name = 'svc'
+        version = '0.3.0'
+        [[option]]
+        long = '--verbose'
+        short = '-v'
+        description = 'say \"more\"'
+        [[command]]
+        name = 'start'
+        description = 'start units'
+        [[command.argument]]
+        name = 'MODE'
+        kind = 'choice(fast|slow)'
+        optional = true
+        [[command.argument]]
+        name = 'UNITS'
+        kind = 'file+'
+        [[command.option]]
+        long = '--unit'
+        argkind = 'command(ls /etc/init.d)'
+        description = 'unit'
+        [[command.option]]
+        longs = ['--hook', '-hook-dir']
+        argkind = 'file'
+        description = ''
+    ";
+
+    #[test]
+    fn write_builder() {
+        let prog = parse_program(SPEC).unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+        assert_eq!(out, "\
+// clap command for svc, generated by completist
+use clap::{Arg, ArgAction, Command, ValueHint};
+
+pub fn command() -> Command {
+    Command::new(\"svc\")
+        .version(\"0.3.0\")
+        .arg(Arg::new(\"verbose\")
+            .short('v')
+            .long(\"verbose\")
+            .help(\"say \\\"more\\\"\")
+            .action(ArgAction::SetTrue))
+        .subcommand(Command::new(\"start\")
+            .about(\"start units\")
+            // completist completes svc start --unit with the command `ls /etc/init.d`
+            .arg(Arg::new(\"unit\")
+                .long(\"unit\")
+                .help(\"unit\")
+                .value_name(\"UNIT\")
+                .action(ArgAction::Set))
+            .arg(Arg::new(\"hook\")
+                .long(\"hook\")
+                .visible_alias(\"hook-dir\")
+                .value_name(\"HOOK\")
+                .value_hint(ValueHint::AnyPath)
+                .action(ArgAction::Set))
+            .arg(Arg::new(\"MODE\")
+                .value_parser([\"fast\", \"slow\"]))
+            .arg(Arg::new(\"UNITS\")
+                .required(true)
+                .num_args(1..)
+                .value_hint(ValueHint::AnyPath)))
+}
+");
+    }
+
+    #[test]
+    fn lossy_translations() {
+        let prog = parse_program(SPEC).unwrap();
+        assert_eq!(warnings(&prog), vec![
+            "svc start --unit: command `ls /etc/init.d` has no clap equivalent",
+            "svc start --hook: `-hook-dir` is written as `--hook-dir`",
+        ]);
+    }
+}
@@ -0,0 +1,183 @@
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{Output, Write};
+use completist::program::{Program, Command, Cache, OptKind};
+
+// Writes the spec format itself, which is what the importers produce.
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("completist");
+    builder.exts(&[".ct"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n").replace("\t", "\\t"))
+}
+
+fn quote_list(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|i| quote(i)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn escape_kind(s: &str) -> String {
+    s.replace("\\", "\\\\").replace("(", "\\(").replace(")", "\\)")
+}
+
+pub fn kind_name(kind: &OptKind) -> String {
+    match *kind {
+        OptKind::File => "file".to_string(),
+        OptKind::FilePlus => "file+".to_string(),
+        OptKind::Command(ref cmd) => format!("command({})", escape_kind(cmd)),
+        OptKind::Function(ref func) => format!("function({})", escape_kind(func)),
+        OptKind::Choices(ref choices) => {
+            let choices: Vec<String> = choices.iter().map(|c| escape_kind(c).replace("|", "\\|")).collect();
+            format!("choice({})", choices.join("|"))
+        },
+    }
+}
+
+fn cache_value(cache: &Cache) -> String {
+    if cache.per_directory && cache.watch.is_empty() {
+        cache.ttl.to_string()
+    } else {
+        format!("{{ ttl = {}, per-directory = {}, watch = {} }}",
+            cache.ttl, cache.per_directory, quote_list(&cache.watch))
+    }
+}
+
+fn write_names(out: &mut Output, key: &str, names: &[String]) -> FmtResult {
+    match names.len() {
+        0 => Ok(()),
+        1 => out.write_fmt(format_args!("{} = {}\n", key, quote(&names[0]))),
+        _ => out.write_fmt(format_args!("{}s = {}\n", key, quote_list(names))),
+    }
+}
+
+// Tables have to follow the keys of the table they belong to, so each command
+// writes its own keys, then its arguments and options, then its subcommands.
+fn write_command(out: &mut Output, prefix: &str, command: &Command) -> FmtResult {
+    for arg in &command.arguments {
+        try!(out.write_fmt(format_args!("\n[[{}argument]]\nname = {}\nkind = {}\n",
+            prefix, quote(&arg.name), quote(&kind_name(&arg.kind)))));
+        if arg.optional {
+            try!(out.write_fmt(format_args!("optional = true\n")));
+        }
+        if let Some(ref cache) = arg.cache {
+            try!(out.write_fmt(format_args!("cache = {}\n", cache_value(cache))));
+        }
+    }
+
+    for opt in &command.options {
+        try!(out.write_fmt(format_args!("\n[[{}option]]\n", prefix)));
+        try!(write_names(out, "long", &opt.longs));
+        try!(write_names(out, "short", &opt.shorts));
+        try!(out.write_fmt(format_args!("description = {}\n", quote(&opt.description))));
+        if let Some(ref kind) = opt.argkind {
+            try!(out.write_fmt(format_args!("argkind = {}\n", quote(&kind_name(kind)))));
+        }
+        if let Some(ref cache) = opt.cache {
+            try!(out.write_fmt(format_args!("cache = {}\n", cache_value(cache))));
+        }
+        if !opt.excludes.is_empty() {
+            try!(out.write_fmt(format_args!("excludes = {}\n", quote_list(&opt.excludes))));
+        }
+        if !opt.repeatable {
+            try!(out.write_fmt(format_args!("repeatable = false\n")));
+        }
+    }
+
+    let prefix = format!("{}command", prefix);
+    for sub in &command.commands {
+        try!(out.write_fmt(format_args!("\n[[{}]]\nname = {}\n", prefix, quote(&sub.name))));
+        if !sub.description.is_empty() {
+            try!(out.write_fmt(format_args!("description = {}\n", quote(&sub.description))));
+        }
+        try!(write_command(out, &format!("{}.", prefix), sub));
+    }
+    Ok(())
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    try!(out.write_fmt(format_args!("name = {}\n", quote(&prog.name))));
+    if !prog.base_command.description.is_empty() {
+        try!(out.write_fmt(format_args!("description = {}\n", quote(&prog.base_command.description))));
+    }
+    if prog.meta.section != "1" {
+        try!(out.write_fmt(format_args!("section = {}\n", quote(&prog.meta.section))));
+    }
+    if let Some(ref version) = prog.meta.version {
+        try!(out.write_fmt(format_args!("version = {}\n", quote(version))));
+    }
+    if let Some(ref date) = prog.meta.date {
+        try!(out.write_fmt(format_args!("date = {}\n", quote(date))));
+    }
+    if !prog.meta.authors.is_empty() {
+        try!(out.write_fmt(format_args!("authors = {}\n", quote_list(&prog.meta.authors))));
+    }
+    write_command(out, "", &prog.base_command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::io::capture;
+    use completist::parse_program;
+
+    #[test]
+    fn kind_names() {
+        assert_eq!(kind_name(&OptKind::FilePlus), "file+");
+        assert_eq!(kind_name(&OptKind::Command("echo (a) \\".to_string())), "command(echo \\(a\\) \\\\)");
+        assert_eq!(kind_name(&OptKind::Choices(vec!["a".to_string(), "b".to_string()])), "choice(a|b)");
+        assert_eq!(kind_name(&OptKind::Choices(vec!["a|b".to_string(), "(c)".to_string()])),
+            "choice(a\\|b|\\(c\\))");
+    }
+
+    #[test]
+    fn choice_round_trip() {
+        let choices = vec!["a|b".to_string(), "f(x)".to_string(), "c\\".to_string()];
+        let kind = OptKind::Choices(choices);
+        assert_eq!(::completist::program::construct_optkind(&kind_name(&kind)), Some(kind));
+    }
+
+    #[test]
+    fn round_trip() {
+        let spec = "name = \"svc\"
+description = \"manage \\\"services\\\"\"
+section = \"8\"
+authors = [\"Jo Bloggs\"]
+
+[[argument]]
+name = \"UNIT\"
+kind = \"command(ls \\\\(x\\\\))\"
+optional = true
+cache = { ttl = 5, per-directory = false, watch = [\"/etc\"] }
+
+[[option]]
+longs = [\"--verbose\", \"--loud\"]
+short = \"-v\"
+description = \"say more\"
+
+[[command]]
+name = \"start\"
+description = \"start it\"
+
+[[command.option]]
+long = \"--wait\"
+description = \"\"
+argkind = \"choice(yes|no)\"
+cache = 10
+excludes = [\"--now\"]
+repeatable = false
+
+[[command.command]]
+name = \"now\"
+
+[[command.command.argument]]
+name = \"FILES\"
+kind = \"file+\"
+";
+        let prog = parse_program(spec).unwrap();
+        assert_eq!(prog.base_command.arguments[0].kind, OptKind::Command("ls (x)".to_string()));
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+        assert_eq!(out, spec);
+    }
+}
@@ -0,0 +1,234 @@
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{Output, Write};
+use completist::program::{Program, Command, Argument, Opt};
+use completist::usage::{usage, describe_kind, Style};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("markdown");
+    builder.exts(&[".md", ".markdown"]).writer(write_markdown);
+    builder.build().unwrap()
+}
+
+pub fn html_formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("html");
+    builder.exts(&[".html", ".htm"]).writer(write_html);
+    builder.build().unwrap()
+}
+
+fn anchor(path: &[&str]) -> String {
+    path.join("-").chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect()
+}
+
+fn opt_names(opt: &Opt) -> Vec<&String> {
+    opt.shorts.iter().chain(opt.longs.iter()).collect()
+}
+
+fn placeholder(opt: &Opt) -> Option<String> {
+    opt.argkind.as_ref().map(|_| opt.name().to_uppercase())
+}
+
+fn subcommands<'a>(path: &[&'a str], command: &'a Command) -> Vec<(Vec<&'a str>, &'a Command)> {
+    command.commands.iter()
+        .map(|sub| {
+            let mut subpath = path.to_vec();
+            subpath.push(&sub.name);
+            (subpath, sub)
+        })
+        .collect()
+}
+
+fn md_cell(s: &str) -> String {
+    s.replace("|", "\\|").replace("\n", " ")
+}
+
+fn write_md_arguments(out: &mut Output, arguments: &[Argument]) -> FmtResult {
+    try!(out.write_fmt(format_args!("\n### Arguments\n\n| Argument | Value |\n| --- | --- |\n")));
+    for arg in arguments {
+        try!(out.write_fmt(format_args!("| `{}` | {} |\n",
+            md_cell(&arg.name), md_cell(&describe_kind(&arg.kind, arg.optional)))));
+    }
+    Ok(())
+}
+
+fn write_md_options(out: &mut Output, options: &[Opt]) -> FmtResult {
+    try!(out.write_fmt(format_args!("\n### Options\n\n| Option | Value | Description |\n| --- | --- | --- |\n")));
+    for opt in options {
+        let names: Vec<String> = opt_names(opt).iter().map(|n| format!("`{}`", md_cell(n))).collect();
+        let value = match (placeholder(opt), opt.argkind.as_ref()) {
+            (Some(name), Some(kind)) => format!("`{}`: {}", name, describe_kind(kind, false)),
+            _ => String::new(),
+        };
+        try!(out.write_fmt(format_args!("| {} | {} | {} |\n",
+            names.join(", "), md_cell(&value), md_cell(&opt.description))));
+    }
+    Ok(())
+}
+
+pub fn write_markdown(out: &mut Output, prog: &Program) -> FmtResult {
+    for (path, command) in prog.walk() {
+        if path.len() == 1 {
+            try!(out.write_fmt(format_args!("# {}\n", prog.name)));
+        } else {
+            try!(out.write_fmt(format_args!("\n<a id=\"{}\"></a>\n\n## {}\n", anchor(&path), path.join(" "))));
+        }
+        if !command.description.is_empty() {
+            try!(out.write_fmt(format_args!("\n{}\n", command.description)));
+        }
+        try!(out.write_fmt(format_args!("\n```\n{}\n```\n", usage(&path, command, Style::Clap, 80))));
+
+        if !command.arguments.is_empty() {
+            try!(write_md_arguments(out, &command.arguments));
+        }
+        if !command.options.is_empty() {
+            try!(write_md_options(out, &command.options));
+        }
+        if !command.commands.is_empty() {
+            try!(out.write_fmt(format_args!("\n### Commands\n\n")));
+            for (subpath, sub) in subcommands(&path, command) {
+                try!(out.write_fmt(format_args!("- [`{}`](#{})", sub.name, anchor(&subpath))));
+                if !sub.description.is_empty() {
+                    try!(out.write_fmt(format_args!(": {}", sub.description)));
+                }
+                try!(out.write_fmt(format_args!("\n")));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;").replace("\"", "&quot;")
+}
+
+// Turns the backtick spans from `describe_kind` into <code> elements.
+fn html_text(s: &str) -> String {
+    let parts: Vec<String> = html_escape(s).split('`').enumerate()
+        .map(|(i, part)| if i % 2 == 1 { format!("<code>{}</code>", part) } else { part.to_string() })
+        .collect();
+    parts.join("")
+}
+
+pub fn write_html(out: &mut Output, prog: &Program) -> FmtResult {
+    try!(out.write_fmt(format_args!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+        <title>{}</title>\n</head>\n<body>\n", html_escape(&prog.name))));
+
+    for (path, command) in prog.walk() {
+        let level = if path.len() == 1 { 1 } else { 2 };
+        try!(out.write_fmt(format_args!("<h{} id=\"{}\">{}</h{}>\n",
+            level, anchor(&path), html_escape(&path.join(" ")), level)));
+        if !command.description.is_empty() {
+            try!(out.write_fmt(format_args!("<p>{}</p>\n", html_escape(&command.description))));
+        }
+        try!(out.write_fmt(format_args!("<pre>{}</pre>\n", html_escape(&usage(&path, command, Style::Clap, 80)))));
+
+        if !command.arguments.is_empty() {
+            try!(out.write_fmt(format_args!("<h3>Arguments</h3>\n<table>\n\
+                <tr><th>Argument</th><th>Value</th></tr>\n")));
+            for arg in &command.arguments {
+                try!(out.write_fmt(format_args!("<tr><td><code>{}</code></td><td>{}</td></tr>\n",
+                    html_escape(&arg.name), html_text(&describe_kind(&arg.kind, arg.optional)))));
+            }
+            try!(out.write_fmt(format_args!("</table>\n")));
+        }
+
+        if !command.options.is_empty() {
+            try!(out.write_fmt(format_args!("<h3>Options</h3>\n<table>\n\
+                <tr><th>Option</th><th>Value</th><th>Description</th></tr>\n")));
+            for opt in &command.options {
+                let names: Vec<String> = opt_names(opt).iter()
+                    .map(|n| format!("<code>{}</code>", html_escape(n)))
+                    .collect();
+                let value = match (placeholder(opt), opt.argkind.as_ref()) {
+                    (Some(name), Some(kind)) => format!("`{}`: {}", name, describe_kind(kind, false)),
+                    _ => String::new(),
+                };
+                try!(out.write_fmt(format_args!("<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    names.join(", "), html_text(&value), html_escape(&opt.description))));
+            }
+            try!(out.write_fmt(format_args!("</table>\n")));
+        }
+
+        if !command.commands.is_empty() {
+            try!(out.write_fmt(format_args!("<h3>Commands</h3>\n<ul>\n")));
+            for (subpath, sub) in subcommands(&path, command) {
+                try!(out.write_fmt(format_args!("<li><a href=\"#{}\"><code>{}</code></a>",
+                    anchor(&subpath), html_escape(&sub.name))));
+                if !sub.description.is_empty() {
+                    try!(out.write_fmt(format_args!(": {}", html_escape(&sub.description))));
+                }
+                try!(out.write_fmt(format_args!("</li>\n")));
+            }
+            try!(out.write_fmt(format_args!("</ul>\n")));
+        }
+    }
+
+    try!(out.write_fmt(format_args!("</body>\n</html>\n")));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::io::capture;
+    use completist::parse_program;
+
+    const SPEC: &'static str = "
+        name = 'svc'
+        description = 'manage services'
+        [[option]]
+        long = '--verbose'
+        short = '-v'
+        description = 'say more | louder'
+        [[command]]
+        name = 'start'
+        description = 'start a service'
+        [[command.argument]]
+        name = 'UNIT'
+        kind = 'command(ls /etc/init.d)'
+        [[command.argument]]
+        name = 'FILES'
+        kind = 'file+'
+        optional = true
+        [[command.option]]
+        long = '--wait'
+        argkind = 'choice(yes|no)'
+        description = 'wait for it'
+    ";
+
+    #[test]
+    fn anchors() {
+        assert_eq!(anchor(&["my prog", "Sub"]), "my-prog-sub");
+    }
+
+    #[test]
+    fn markdown() {
+        let prog = parse_program(SPEC).unwrap();
+        let out = capture(|out| write_markdown(out, &prog)).unwrap();
+        assert_eq!(out, "# svc\n\nmanage services\n\n```\nsvc [-v|--verbose] <SUBCOMMAND>\n```\n\n\
+            ### Options\n\n| Option | Value | Description |\n| --- | --- | --- |\n\
+            | `-v`, `--verbose` |  | say more \\| louder |\n\n\
+            ### Commands\n\n- [`start`](#svc-start): start a service\n\n\
+            <a id=\"svc-start\"></a>\n\n## svc start\n\nstart a service\n\n\
+            ```\nsvc start [--wait <WAIT>] <UNIT> [FILES]...\n```\n\n\
+            ### Arguments\n\n| Argument | Value |\n| --- | --- |\n\
+            | `UNIT` | one of the lines printed by `ls /etc/init.d` |\n\
+            | `FILES` | zero or more files |\n\n\
+            ### Options\n\n| Option | Value | Description |\n| --- | --- | --- |\n\
+            | `--wait` | `WAIT`: one of `yes`, `no` | wait for it |\n");
+    }
+
+    #[test]
+    fn html() {
+        let prog = parse_program(SPEC).unwrap();
+        let out = capture(|out| write_html(out, &prog)).unwrap();
+        assert!(out.starts_with("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>svc</title>\n"));
+        assert!(out.contains("<h1 id=\"svc\">svc</h1>\n<p>manage services</p>\n<pre>svc [-v|--verbose] &lt;SUBCOMMAND&gt;</pre>\n"));
+        assert!(out.contains("<li><a href=\"#svc-start\"><code>start</code></a>: start a service</li>\n"));
+        assert!(out.contains("<h2 id=\"svc-start\">svc start</h2>\n"));
+        assert!(out.contains("<tr><td><code>--wait</code></td>\
+            <td><code>WAIT</code>: one of <code>yes</code>, <code>no</code></td><td>wait for it</td></tr>\n"));
+        assert!(out.ends_with("</body>\n</html>\n"));
+    }
+}
@@ -0,0 +1,203 @@
+use completist::engine::variable_name;
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{Output, Write};
+use completist::program::{Program, Command, OptKind};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("elvish");
+    builder.exts(&[".elv"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace("'", "''"))
+}
+
+fn quote_list<'a, I>(items: I) -> String where I: Iterator<Item=&'a String> {
+    let items: Vec<String> = items.map(|i| quote(i)).collect();
+    format!("[{}]", items.join(" "))
+}
+
+fn kind_fields(kind: Option<&OptKind>) -> String {
+    let none = Vec::new();
+    let (name, value, values) = match kind {
+        None => ("$nil".to_string(), String::new(), &none),
+        Some(&OptKind::File) | Some(&OptKind::FilePlus) => (quote("file"), String::new(), &none),
+        Some(&OptKind::Command(ref cmd)) => (quote("command"), cmd.clone(), &none),
+        Some(&OptKind::Function(ref func)) => (quote("function"), func.clone(), &none),
+        Some(&OptKind::Choices(ref choices)) => (quote("choice"), String::new(), choices),
+    };
+
+    format!("&kind={} &value={} &values={}", name, quote(&value), quote_list(values.iter()))
+}
+
+fn write_command(out: &mut Output, path: &[&str], command: &Command) -> FmtResult {
+    let subcommands: Vec<String> = command.commands.iter().map(|c| c.name.clone()).collect();
+    try!(out.write_fmt(format_args!("    &{}=[\n", quote(&path.join(" ")))));
+    try!(out.write_fmt(format_args!("      &commands={}\n", quote_list(subcommands.iter()))));
+
+    try!(out.write_fmt(format_args!("      &options=[\n")));
+    for opt in &command.options {
+        try!(out.write_fmt(format_args!("        [&names={} &description={} &name={} {}]\n",
+            quote_list(opt.longs.iter().chain(opt.shorts.iter())),
+            quote(&opt.description),
+            quote(&variable_name(&opt.name())),
+            kind_fields(opt.argkind.as_ref()))));
+    }
+    try!(out.write_fmt(format_args!("      ]\n")));
+
+    try!(out.write_fmt(format_args!("      &arguments=[\n")));
+    for arg in &command.arguments {
+        try!(out.write_fmt(format_args!("        [&name={} &variadic=${} {}]\n",
+            quote(&arg.name), arg.kind == OptKind::FilePlus, kind_fields(Some(&arg.kind)))));
+    }
+    try!(out.write_fmt(format_args!("      ]\n")));
+
+    try!(out.write_fmt(format_args!("    ]\n")));
+    Ok(())
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    try!(out.write_fmt(format_args!("# completions for {}, generated by completist\n", prog.name)));
+    try!(out.write_fmt(format_args!("use str\n\n")));
+    try!(out.write_fmt(format_args!("set edit:completion:arg-completer[{}] = {{|@words|\n",
+        quote(&prog.name))));
+    try!(out.write_fmt(format_args!("  var program = {}\n", quote(&prog.name))));
+    try!(out.write_fmt(format_args!("  var commands = [\n")));
+    for (path, command) in prog.walk() {
+        try!(write_command(out, &path, command));
+    }
+    try!(out.write_fmt(format_args!("  ]\n")));
+    try!(out.write_all(COMPLETER.as_bytes()));
+    try!(out.write_fmt(format_args!("}}\n")));
+    Ok(())
+}
+
+// Walks the words typed so far through the `$commands` table written above.
+const COMPLETER: &'static str = r#"
+  var current = $words[-1]
+  var prev = $words[..(- (count $words) 1)]
+  var path = $program
+  var pending = $nil
+  var positionals = 0
+  var separated = $false
+  var used = []
+  var values = [&]
+  for word $prev[1..] {
+    var spec = $commands[$path]
+    if (not-eq $pending $nil) {
+      set values[$pending[name]] = $word
+      set pending = $nil
+    } elif (or $separated (eq $word '-') (not (str:has-prefix $word '-'))) {
+      if (and (not $separated) (== $positionals 0) (has-value $spec[commands] $word)) {
+        set path = $path' '$word
+        set used = []
+      } else {
+        set positionals = (+ $positionals 1)
+      }
+    } elif (eq $word '--') {
+      set separated = $true
+    } else {
+      var parts = [(str:split &max=2 '=' $word)]
+      for opt $spec[options] {
+        if (has-value $opt[names] $parts[0]) {
+          set used = [$@used $opt[name]]
+          if (and (not-eq $opt[kind] $nil) (== (count $parts) 2)) {
+            set values[$opt[name]] = $parts[1]
+          } elif (not-eq $opt[kind] $nil) {
+            set pending = $opt
+          }
+        }
+      }
+    }
+  }
+
+  var spec = $commands[$path]
+  var kind = $pending
+  if (eq $kind $nil) {
+    if (and (not $separated) (str:has-prefix $current '-')) {
+      for opt $spec[options] {
+        if (not (has-value $used $opt[name])) {
+          for name $opt[names] {
+            edit:complex-candidate $name &display=$name' ('$opt[description]')'
+          }
+        }
+      }
+      return
+    }
+
+    if (and (not $separated) (== $positionals 0)) {
+      all $spec[commands]
+    }
+    var index = 0
+    for argument $spec[arguments] {
+      if (or (== $index $positionals) (and (< $index $positionals) $argument[variadic])) {
+        set kind = $argument
+        break
+      }
+      set index = (+ $index 1)
+    }
+  }
+
+  if (eq $kind $nil) {
+    return
+  } elif (eq $kind[kind] 'file') {
+    edit:complete-filename $current
+  } elif (eq $kind[kind] 'choice') {
+    all $kind[values]
+  } else {
+    set-env COMPLETIST_CURRENT $current
+    set-env COMPLETIST_PREV $prev[-1]
+    set-env COMPLETIST_WORDS (str:join "\n" $prev)
+    for name [(keys $values)] {
+      set-env $name $values[$name]
+    }
+    try {
+      if (eq $kind[kind] 'command') {
+        sh -c $kind[value] | from-lines
+      } else {
+        eval $kind[value]
+      }
+    } finally {
+      unset-env COMPLETIST_CURRENT
+      unset-env COMPLETIST_PREV
+      unset-env COMPLETIST_WORDS
+      for name [(keys $values)] {
+        unset-env $name
+      }
+    }
+  }
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::io::capture;
+    use completist::parse_program;
+
+    fn check_golden(spec: &str, golden: &str) {
+        let prog = parse_program(spec).unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+        assert_eq!(out, golden);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("it's"), "'it''s'");
+        assert_eq!(kind_fields(None), "&kind=$nil &value='' &values=[]");
+        assert_eq!(kind_fields(Some(&OptKind::Choices(vec!["a".to_string(), "b".to_string()]))),
+            "&kind='choice' &value='' &values=['a' 'b']");
+    }
+
+    #[test]
+    fn golden_cat() {
+        check_golden(include_str!("../../../samples/cat.ct"),
+            include_str!("../../../samples/golden/cat.elv"));
+    }
+
+    #[test]
+    fn golden_cargo() {
+        check_golden(include_str!("../../../samples/cargo.ct"),
+            include_str!("../../../samples/golden/cargo.elv"));
+    }
+}
@@ -0,0 +1,202 @@
+use std::io::Result;
+
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{capture, Output, Write};
+use completist::program::{Program, Command, Cache, OptKind};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("fig");
+    builder.exts(&[".ts"]).writer(write_program).checker(warnings);
+    builder.build().unwrap()
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n").replace("\t", "\\t"))
+}
+
+fn quote_list<'a, I>(items: I) -> String where I: Iterator<Item=&'a String> {
+    let items: Vec<String> = items.map(|i| quote(i)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn cache_fields(cache: Option<&Cache>, what: &str, warnings: &mut Vec<String>) -> String {
+    match cache {
+        Some(cache) => {
+            if !cache.watch.is_empty() {
+                warnings.push(format!("{}: watched files are not supported by fig caches", what));
+            }
+            format!(", cache: {{ ttl: {}, cacheByDirectory: {} }}", cache.ttl * 1000, cache.per_directory)
+        },
+        None => String::new(),
+    }
+}
+
+// The Fig arg fields for a kind:
+//   file, file+    -> template: "filepaths"
+//   command(cmd)   -> a generator running `sh -c cmd`, one suggestion per line
+//   choice(a|b)    -> suggestions
+//   function(...)  -> nothing, fig has no shell to run it in
+fn kind_fields(kind: &OptKind, cache: Option<&Cache>, what: &str, warnings: &mut Vec<String>) -> String {
+    match *kind {
+        OptKind::File | OptKind::FilePlus => ", template: \"filepaths\"".to_string(),
+        OptKind::Command(ref cmd) => format!(", generators: {{ script: [\"sh\", \"-c\", {}], splitOn: \"\\n\"{} }}",
+            quote(cmd), cache_fields(cache, what, warnings)),
+        OptKind::Choices(ref choices) => format!(", suggestions: {}", quote_list(choices.iter())),
+        OptKind::Function(ref func) => {
+            warnings.push(format!("{}: function `{}` cannot be run by fig", what, func));
+            String::new()
+        },
+    }
+}
+
+fn write_command(out: &mut Output, depth: usize, path: &[&str], command: &Command,
+                 warnings: &mut Vec<String>) -> Result<()> {
+    let pad = "  ".repeat(depth);
+    let what = path.join(" ");
+    try!(out.write_fmt(format_args!("{}name: {},\n", pad, quote(&command.name))));
+
+    if !command.commands.is_empty() {
+        try!(out.write_fmt(format_args!("{}subcommands: [\n", pad)));
+        for sub in &command.commands {
+            let mut subpath = path.to_vec();
+            subpath.push(&sub.name);
+            try!(out.write_fmt(format_args!("{}  {{\n", pad)));
+            try!(write_command(out, depth + 2, &subpath, sub, warnings));
+            try!(out.write_fmt(format_args!("{}  }},\n", pad)));
+        }
+        try!(out.write_fmt(format_args!("{}],\n", pad)));
+    }
+
+    if !command.options.is_empty() {
+        try!(out.write_fmt(format_args!("{}options: [\n", pad)));
+        for opt in &command.options {
+            let what = format!("{} {}", what, opt.longs.first().or(opt.shorts.first()).unwrap());
+            try!(out.write_fmt(format_args!("{}  {{ name: {}, description: {}",
+                pad, quote_list(opt.longs.iter().chain(opt.shorts.iter())), quote(&opt.description))));
+            if let Some(ref kind) = opt.argkind {
+                try!(out.write_fmt(format_args!(", args: {{ name: {}{} }}",
+                    quote(&opt.name().to_uppercase()), kind_fields(kind, opt.cache.as_ref(), &what, warnings))));
+            }
+            try!(out.write_fmt(format_args!(" }},\n")));
+        }
+        try!(out.write_fmt(format_args!("{}],\n", pad)));
+    }
+
+    if !command.arguments.is_empty() {
+        try!(out.write_fmt(format_args!("{}args: [\n", pad)));
+        for arg in &command.arguments {
+            let what = format!("{} {}", what, arg.name);
+            try!(out.write_fmt(format_args!("{}  {{ name: {}{}", pad, quote(&arg.name),
+                kind_fields(&arg.kind, arg.cache.as_ref(), &what, warnings))));
+            if arg.kind == OptKind::FilePlus {
+                try!(out.write_fmt(format_args!(", isVariadic: true")));
+            }
+            if arg.optional {
+                try!(out.write_fmt(format_args!(", isOptional: true")));
+            }
+            try!(out.write_fmt(format_args!(" }},\n")));
+        }
+        try!(out.write_fmt(format_args!("{}],\n", pad)));
+    }
+    Ok(())
+}
+
+fn translate(prog: &Program) -> Result<(String, Vec<String>)> {
+    let mut warnings = Vec::new();
+    let spec = try!(capture(|out| write_command(out, 1, &[&prog.name], &prog.base_command, &mut warnings)));
+
+    let commands = prog.walk().iter().any(|&(_, command)| {
+        command.options.iter().filter_map(|o| o.argkind.as_ref())
+            .chain(command.arguments.iter().map(|a| &a.kind))
+            .any(|kind| match *kind { OptKind::Command(_) => true, _ => false })
+    });
+    if commands {
+        warnings.push("commands are run without the COMPLETIST_* variables".to_string());
+    }
+    Ok((spec, warnings))
+}
+
+pub fn warnings(prog: &Program) -> Vec<String> {
+    translate(prog).map(|(_, warnings)| warnings).unwrap_or(Vec::new())
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    let (spec, warnings) = try!(translate(prog));
+    try!(out.write_fmt(format_args!("// completions for {}, generated by completist\n", prog.name)));
+    for warning in &warnings {
+        try!(out.write_fmt(format_args!("// warning: {}\n", warning)));
+    }
+    try!(out.write_fmt(format_args!("const completionSpec: Fig.Spec = {{\n")));
+    try!(out.write_all(spec.as_bytes()));
+    try!(out.write_fmt(format_args!("}};\n\nexport default completionSpec;\n")));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+
+    const SPEC: &'static str = "
+        name = 'svc'
+        [[option]]
+        long = '--verbose'
+        short = '-v'
+        description = 'say more'
+        [[command]]
+        name = 'start'
+        [[command.argument]]
+        name = 'MODE'
+        kind = 'choice(fast|slow)'
+        optional = true
+        [[command.argument]]
+        name = 'UNITS'
+        kind = 'file+'
+        [[command.option]]
+        long = '--unit'
+        argkind = 'command(ls /etc/init.d)'
+        description = 'unit'
+        cache = { ttl = 10, watch = ['/etc/init.d'] }
+        [[command.option]]
+        long = '-hook'
+        argkind = 'function(__hooks)'
+        description = 'hook'
+    ";
+
+    #[test]
+    fn write_spec() {
+        let prog = parse_program(SPEC).unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+        assert!(out.ends_with("const completionSpec: Fig.Spec = {\n  \
+            name: \"svc\",\n  \
+            subcommands: [\n    \
+              {\n      \
+                name: \"start\",\n      \
+                options: [\n        \
+                  { name: [\"--unit\"], description: \"unit\", args: { name: \"UNIT\", generators: \
+                    { script: [\"sh\", \"-c\", \"ls /etc/init.d\"], splitOn: \"\\n\", \
+                    cache: { ttl: 10000, cacheByDirectory: true } } } },\n        \
+                  { name: [\"-hook\"], description: \"hook\", args: { name: \"HOOK\" } },\n      \
+                ],\n      \
+                args: [\n        \
+                  { name: \"MODE\", suggestions: [\"fast\", \"slow\"], isOptional: true },\n        \
+                  { name: \"UNITS\", template: \"filepaths\", isVariadic: true },\n      \
+                ],\n    \
+              },\n  \
+            ],\n  \
+            options: [\n    \
+              { name: [\"--verbose\", \"-v\"], description: \"say more\" },\n  \
+            ],\n\
+            };\n\nexport default completionSpec;\n"));
+    }
+
+    #[test]
+    fn lossy_translations() {
+        let prog = parse_program(SPEC).unwrap();
+        assert_eq!(warnings(&prog), vec![
+            "svc start --unit: watched files are not supported by fig caches",
+            "svc start -hook: function `__hooks` cannot be run by fig",
+            "commands are run without the COMPLETIST_* variables",
+        ]);
+    }
+}
@@ -0,0 +1,112 @@
+use completist::formatter::{fish_quote, Formatter, FormatterBuilder, FmtResult};
+use completist::io::{Output, Write};
+use completist::program::{Program, Command, OptKind};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("fish");
+    builder.exts(&[".fish"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+// Every line of a level is conditioned on `__fish_at_level_<prog>`, with the
+// positional index it applies to: subcommands only come first, options may
+// come anywhere, and each argument has its own index.
+fn write_command(fmt: &Formatter, out: &mut Output, prog: &Program, path: &[String], command: &Command) -> FmtResult {
+    let children: Vec<String> = command.commands.iter().map(|c| c.name.clone()).collect();
+
+    for sub in &command.commands {
+        try!(fmt.write_begin(out, prog));
+        try!(fmt.write_level(out, prog, "0", path, &children));
+        try!(out.write_fmt(format_args!(" -a {} ", fish_quote(&sub.name))));
+        if !sub.description.is_empty() {
+            try!(out.write_fmt(format_args!(" -d {} ", fish_quote(&sub.description))));
+        }
+        try!(out.write_all(b"\n"));
+    }
+
+    for opt in &command.options {
+        try!(fmt.write_begin(out, prog));
+        try!(fmt.write_level(out, prog, "any", path, &children));
+        try!(fmt.write_opt(out, opt));
+        try!(fmt.write_opt_description(out, opt));
+        try!(fmt.write_opt_arguments(out, prog, opt));
+        try!(out.write_all(b"\n"));
+    }
+
+    for (i, arg) in command.arguments.iter().enumerate() {
+        let index = if arg.kind == OptKind::FilePlus { format!("{}+", i) } else { i.to_string() };
+        try!(fmt.write_begin(out, prog));
+        try!(fmt.write_level(out, prog, &index, path, &children));
+        try!(fmt.write_arg_kind(out, prog, &arg.kind, &arg.cache));
+        try!(out.write_all(b"\n"));
+    }
+
+    for sub in &command.commands {
+        let mut subpath = path.to_vec();
+        subpath.push(sub.name.clone());
+        try!(write_command(fmt, out, prog, &subpath, sub));
+    }
+    Ok(())
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    let fmt = formatter();
+    try!(out.write_fmt(format_args!("# completions for {}, generated by completist\n", prog.name)));
+    try!(fmt.write_header(out, prog));
+
+    // Files are only offered where the spec asks for them, with -F.
+    try!(out.write_all(b"\n"));
+    try!(fmt.write_begin(out, prog));
+    try!(out.write_all(b" -f\n"));
+    try!(write_command(&fmt, out, prog, &[prog.name.clone()], &prog.base_command));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::io::capture;
+    use completist::parse_program;
+
+    #[test]
+    fn write_levels() {
+        let prog = parse_program("
+            name = 'svc'
+            [[argument]]
+            name = 'FILE'
+            kind = 'file+'
+            [[option]]
+            long = '--verbose'
+            short = '-v'
+            description = 'say \"more\"'
+            [[command]]
+            name = 'start'
+            description = 'start units'
+            [[command.argument]]
+            name = 'MODE'
+            kind = 'choice(fast|slow)'
+            [[command.option]]
+            long = '--unit'
+            argkind = 'command(ls /etc/init.d)'
+            description = \"the unit's name\"
+            cache = 10
+        ").unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+
+        assert!(out.contains("function __fish_completist_words_svc\n"));
+        assert!(out.contains("                    case '--unit'\n                      set skip 1\n"));
+        assert!(out.contains("function __fish_at_level_svc\n"));
+        assert!(out.contains("function __fish_completist_run_svc\n"));
+        assert!(out.contains("\ncomplete -c 'svc'  -f\n"));
+        assert!(out.contains("complete -c 'svc'  -n '__fish_at_level_svc 0 \\'svc\\' -- \\'start\\''  \
+            -a 'start'  -d 'start units' \n"));
+        assert!(out.contains("complete -c 'svc'  -n '__fish_at_level_svc any \\'svc\\' -- \\'start\\''  \
+            -s 'v'  -l 'verbose'  -d 'say \"more\"' \n"));
+        assert!(out.contains("complete -c 'svc'  -n '__fish_at_level_svc 0+ \\'svc\\' -- \\'start\\''  -F \n"));
+        assert!(out.contains("complete -c 'svc'  -n '__fish_at_level_svc any \\'svc\\' \\'start\\''  \
+            -l 'unit'  -d 'the unit\\'s name'  \
+            -x -a '(__fish_completist_run_svc 10 1  -- \\'ls /etc/init.d\\')' \n"));
+        assert!(out.contains("complete -c 'svc'  -n '__fish_at_level_svc 0 \\'svc\\' \\'start\\''  \
+            -a '\\'fast\\' \\'slow\\'' \n"));
+    }
+}
@@ -0,0 +1,386 @@
+use completist::engine::variable_name;
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{capture, Output, Write};
+use completist::program::{Program, Command, OptKind, Cache};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("ksh");
+    builder.exts(&[".ksh"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+// The bash and zsh backends share the candidates function, which only needs
+// a different way to print in bash and ksh emulation in zsh.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Dialect {
+    Ksh,
+    Bash,
+    Zsh,
+}
+
+impl Dialect {
+    fn print(&self) -> &'static str {
+        match *self {
+            Dialect::Bash => "printf '%s\\n'",
+            Dialect::Ksh => "print -r --",
+            Dialect::Zsh => "print -rl --",
+        }
+    }
+
+    // zsh can show descriptions, which follow the value after a tab.
+    fn list(&self, values: &[(&String, &String)]) -> String {
+        if *self == Dialect::Zsh && values.iter().any(|&(_, d)| !d.is_empty()) {
+            let pairs: Vec<String> = values.iter().map(|&(v, d)| format!("{} {}", quote(v), quote(d))).collect();
+            format!("printf '%s\\t%s\\n' {}", pairs.join(" "))
+        } else {
+            let values: Vec<String> = values.iter().map(|&(v, _)| quote(v)).collect();
+            format!("{} {}", self.print(), values.join(" "))
+        }
+    }
+}
+
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace("'", "'\\''"))
+}
+
+pub fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn case_key(path: &[&str], key: &str) -> String {
+    quote(&format!("{}/{}", path.join(" "), key))
+}
+
+fn argument_key(name: &str) -> String {
+    format!("argument {}", name)
+}
+
+// Runs a command or, after --function, shell code, and keeps its output for
+// a while, like the fish and PowerShell backends do. Its arguments are the
+// TTL, whether the cache is per directory, the code and the files to watch.
+// The first line of a cache file is the time it was written.
+fn write_cached(out: &mut Output, dialect: Dialect, prog: &Program) -> FmtResult {
+    try!(out.write_fmt(format_args!("function _completist_{}_cached {{\n", identifier(&prog.name))));
+    if dialect == Dialect::Zsh {
+        try!(out.write_all(b"    emulate -L ksh\n"));
+    }
+    try!(out.write_all(br#"    typeset run=sh ttl per_directory cmd key file stamp= output watched
+    typeset dir=${XDG_CACHE_HOME:-$HOME/.cache}/completist
+    if [[ $1 == --function ]]; then
+        run=eval
+        shift
+    fi
+    ttl=$1 per_directory=$2 cmd=$3
+    shift 3
+    key=$cmd$'\n'$COMPLETIST_CURRENT$'\n'$COMPLETIST_PREV$'\n'$COMPLETIST_WORDS$'\n'$(env | grep '^COMPLETIST_OPT_' | sort)
+    (( per_directory )) && key=$PWD$'\n'$key
+    # hashed, since the key can be longer than a file name may be
+    file=$dir/sh_$(printf '%s' "$key" | cksum | tr ' ' _)
+
+    [[ -f $file ]] && read -r stamp < "$file"
+    if [[ -n $stamp && $stamp != *[!0-9]* ]] && (( $(date +%s) - stamp < ttl )); then
+        for watched in "$@"; do
+            [[ -e $watched && $watched -nt $file ]] && stamp=
+        done
+        if [[ -n $stamp ]]; then
+            sed 1d "$file"
+            return 0
+        fi
+    fi
+
+    if [[ $run == eval ]]; then
+        output=$(eval "$cmd")
+    else
+        output=$(sh -c "$cmd")
+    fi
+    # a failed command is run again next time
+    if (( $? == 0 )); then
+        mkdir -p "$dir" && { date +%s; printf '%s\n' "$output"; } > "$file"
+    fi
+    printf '%s\n' "$output"
+}
+
+"#));
+    Ok(())
+}
+
+fn cached_call(prog: &Program, code: &str, function: bool, cache: &Cache) -> String {
+    let mut args = vec![cache.ttl.to_string(), (cache.per_directory as u8).to_string(), quote(code)];
+    args.extend(cache.watch.iter().map(|w| quote(w)));
+    format!("_completist_{}_cached {}{}", identifier(&prog.name),
+        if function { "--function " } else { "" }, args.join(" "))
+}
+
+// Candidates are split on whitespace by the KEYBD trap, so choices with spaces
+// in them can't be offered in ksh.
+fn write_kind(out: &mut Output, dialect: Dialect, prog: &Program, key: &str, kind: &OptKind,
+              cache: &Option<Cache>, comments: &mut Vec<String>) -> FmtResult {
+    let body = match (kind, cache) {
+        (&OptKind::File, _) | (&OptKind::FilePlus, _) => "return 2".to_string(),
+        (&OptKind::Command(ref cmd), &Some(ref cache)) => cached_call(prog, cmd, false, cache),
+        (&OptKind::Function(ref func), &Some(ref cache)) => cached_call(prog, func, true, cache),
+        (&OptKind::Command(ref cmd), &None) => format!("sh -c {}", quote(cmd)),
+        (&OptKind::Function(ref func), &None) => format!("eval {}", quote(func)),
+        (&OptKind::Choices(ref choices), _) => {
+            let (fits, spaced): (Vec<&String>, Vec<&String>) = choices.iter()
+                .partition(|c| dialect != Dialect::Ksh || !c.contains(char::is_whitespace));
+            for choice in spaced {
+                comments.push(format!("choice {} contains whitespace", quote(choice)));
+            }
+            let fits: Vec<String> = fits.iter().map(|c| quote(c)).collect();
+            format!("{} {}", dialect.print(), fits.join(" "))
+        },
+    };
+
+    try!(out.write_fmt(format_args!("    {}) {} ;;\n", key, body)));
+    Ok(())
+}
+
+fn write_words(out: &mut Output, prog: &Program) -> FmtResult {
+    try!(out.write_all(br#"    for word in "${words[@]:1}"; do
+        if [[ -n $pending ]]; then
+            export "$pending=$word"
+            pending=
+        elif (( separated )) || [[ $word == - || $word != -* ]]; then
+            case "$path/$word" in
+"#));
+    for (path, command) in prog.walk() {
+        for sub in &command.commands {
+            let mut subpath = path.clone();
+            subpath.push(&sub.name);
+            try!(out.write_fmt(format_args!(
+                "            {})\n                if (( ! separated && positionals == 0 )); then\n                    \
+                path={}; used=' '; continue\n                fi ;;\n",
+                case_key(&path, &sub.name), quote(&subpath.join(" ")))));
+        }
+    }
+    try!(out.write_all(br#"            esac
+            (( positionals++ ))
+        elif [[ $word == -- ]]; then
+            separated=1
+        else
+            case "$path/${word%%=*}" in
+"#));
+    for (path, command) in prog.walk() {
+        for opt in &command.options {
+            let name = variable_name(&opt.name());
+            let keys: Vec<String> = opt.longs.iter().chain(opt.shorts.iter())
+                .map(|n| case_key(&path, n))
+                .collect();
+            try!(out.write_fmt(format_args!("            {})\n                used+='{} '", keys.join("|"), name)));
+            if opt.argkind.is_some() {
+                try!(out.write_fmt(format_args!(
+                    "\n                if [[ $word == *=* ]]; then export \"{}=${{word#*=}}\"; else pending={}; fi",
+                    name, name)));
+            }
+            try!(out.write_fmt(format_args!(" ;;\n")));
+        }
+    }
+    try!(out.write_all(br#"            esac
+        fi
+    done
+
+"#));
+    Ok(())
+}
+
+// Prints the candidates for the current word, given as the first argument,
+// after the words before it. Returns 2 when files should be completed.
+pub fn write_candidates(out: &mut Output, dialect: Dialect, prog: &Program, comments: &mut Vec<String>) -> FmtResult {
+    if prog.walk().iter().any(|&(_, command)| has_cache(command)) {
+        try!(write_cached(out, dialect, prog));
+    }
+    try!(out.write_fmt(format_args!("function _completist_{}_candidates {{\n", identifier(&prog.name))));
+    if dialect == Dialect::Zsh {
+        try!(out.write_all(b"    emulate -L ksh\n"));
+    }
+    try!(out.write_fmt(format_args!(
+        "    typeset current=$1 word path={} pending= positionals=0 separated=0 used=' '\n", quote(&prog.name))));
+    try!(out.write_all(b"    shift\n    typeset -a words=( \"$@\" )\n"));
+    try!(write_words(out, prog));
+
+    try!(out.write_all(br#"    typeset kind=$pending
+    if [[ -z $kind ]]; then
+        if (( ! separated )) && [[ $current == -* ]]; then
+            case $path in
+"#));
+    for (path, command) in prog.walk() {
+        if command.options.is_empty() {
+            continue;
+        }
+        try!(out.write_fmt(format_args!("            {})\n", quote(&path.join(" ")))));
+        for opt in &command.options {
+            let names: Vec<(&String, &String)> = opt.longs.iter().chain(opt.shorts.iter())
+                .map(|n| (n, &opt.description))
+                .collect();
+            if opt.repeatable {
+                try!(out.write_fmt(format_args!("                {}\n", dialect.list(&names))));
+            } else {
+                try!(out.write_fmt(format_args!("                [[ $used == *' {} '* ]] || {}\n",
+                    variable_name(&opt.name()), dialect.list(&names))));
+            }
+        }
+        try!(out.write_all(b"                ;;\n"));
+    }
+    try!(out.write_all(br#"            esac
+            return 0
+        fi
+        if (( ! separated && positionals == 0 )); then
+            case $path in
+"#));
+    for (path, command) in prog.walk() {
+        if !command.commands.is_empty() {
+            let names: Vec<(&String, &String)> = command.commands.iter().map(|c| (&c.name, &c.description)).collect();
+            try!(out.write_fmt(format_args!("            {}) {} ;;\n",
+                quote(&path.join(" ")), dialect.list(&names))));
+        }
+    }
+    try!(out.write_all(br#"            esac
+        fi
+        case "$path/$positionals" in
+"#));
+    for (path, command) in prog.walk() {
+        for (i, arg) in command.arguments.iter().enumerate() {
+            let position = if arg.kind == OptKind::FilePlus {
+                format!("{}*", case_key(&path, ""))
+            } else {
+                case_key(&path, &i.to_string())
+            };
+            try!(out.write_fmt(format_args!("        {}) kind={} ;;\n", position, quote(&argument_key(&arg.name)))));
+        }
+    }
+    try!(out.write_all(br#"        esac
+    fi
+
+    typeset IFS=$'\n'
+    export COMPLETIST_CURRENT=$current
+    export COMPLETIST_PREV=${words[${#words[@]}-1]}
+    export COMPLETIST_WORDS="${words[*]}"
+    case "$path/$kind" in
+"#));
+    for (path, command) in prog.walk() {
+        for opt in &command.options {
+            if let Some(ref kind) = opt.argkind {
+                try!(write_kind(out, dialect, prog, &case_key(&path, &variable_name(&opt.name())), kind,
+                    &opt.cache, comments));
+            }
+        }
+        for arg in &command.arguments {
+            try!(write_kind(out, dialect, prog, &case_key(&path, &argument_key(&arg.name)), &arg.kind,
+                &arg.cache, comments));
+        }
+    }
+    try!(out.write_all(b"    esac\n}\n"));
+    Ok(())
+}
+
+fn has_cache(command: &Command) -> bool {
+    command.options.iter().any(|o| o.cache.is_some()) ||
+        command.arguments.iter().any(|a| a.cache.is_some())
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    let ident = identifier(&prog.name);
+    let mut comments = Vec::new();
+    let candidates = try!(capture(|out| write_candidates(out, Dialect::Ksh, prog, &mut comments)));
+
+    try!(out.write_fmt(format_args!("# completions for {}, generated by completist\n", prog.name)));
+    try!(out.write_all(b"# ksh93 has no programmable completion, so TAB is intercepted with a KEYBD\n\
+        # trap. Only one KEYBD trap can be set, so loading another one replaces it.\n"));
+    try!(out.write_all(b"# short options can't be grouped, as in -abc\n"));
+    if !comments.is_empty() {
+        try!(out.write_all(b"#\n# not expressible in ksh:\n"));
+        for comment in &comments {
+            try!(out.write_fmt(format_args!("#   {}\n", comment)));
+        }
+    }
+    try!(out.write_all(b"\n"));
+    try!(out.write_all(candidates.as_bytes()));
+
+    try!(out.write_fmt(format_args!("\nfunction _completist_{}_keybd {{\n", ident)));
+    try!(out.write_all(br#"    [[ ${.sh.edchar} == $'\t' ]] || return 0
+    typeset line=${.sh.edtext:0:${.sh.edcol}} current= candidates candidate
+    typeset -a words matches
+    words=( $line )
+"#));
+    try!(out.write_fmt(format_args!("    [[ ${{words[0]}} == {} ]] || return 0\n", quote(&prog.name))));
+    try!(out.write_all(br#"    if [[ $line != *[[:space:]] ]]; then
+        current=${words[${#words[@]}-1]}
+        unset "words[${#words[@]}-1]"
+    fi
+"#));
+    try!(out.write_fmt(format_args!(
+        "    candidates=$(_completist_{}_candidates \"$current\" \"${{words[@]}}\") || return 0\n", ident)));
+    try!(out.write_all(br#"    for candidate in $candidates; do
+        [[ $candidate == "$current"* ]] && matches+=( "$candidate" )
+    done
+    if (( ${#matches[@]} == 1 )); then
+        .sh.edchar="${matches[0]#"$current"} "
+    elif (( ${#matches[@]} > 1 )); then
+        print
+        print -r -- "${matches[@]}"
+        .sh.edchar=$'\f'
+    fi
+}
+"#));
+    try!(out.write_fmt(format_args!("trap _completist_{}_keybd KEYBD\n", ident)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(case_key(&["cargo", "bench"], "--bin"), "'cargo bench/--bin'");
+    }
+
+    #[test]
+    fn write_keybd() {
+        let prog = parse_program("
+            name = 'svc'
+            [[argument]]
+            name = 'FILE'
+            kind = 'file+'
+            [[option]]
+            long = '--verbose'
+            short = '-v'
+            description = 'say more'
+            repeatable = false
+            [[command]]
+            name = 'start'
+            [[command.argument]]
+            name = 'MODE'
+            kind = 'choice(fast|really slow)'
+            [[command.option]]
+            long = '--unit'
+            argkind = 'command(ls /etc/init.d)'
+            description = 'unit'
+        ").unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+
+        assert!(out.contains("#   choice 'really slow' contains whitespace\n"));
+        assert!(out.contains("            'svc/start')\n                \
+            if (( ! separated && positionals == 0 )); then\n                    \
+            path='svc start'; used=' '; continue\n                fi ;;\n"));
+        assert!(out.contains("            'svc/--verbose'|'svc/-v')\n                used+='COMPLETIST_OPT_VERBOSE ' ;;\n"));
+        assert!(out.contains("            'svc start/--unit')\n                used+='COMPLETIST_OPT_UNIT '\n                \
+            if [[ $word == *=* ]]; then export \"COMPLETIST_OPT_UNIT=${word#*=}\"; \
+            else pending=COMPLETIST_OPT_UNIT; fi ;;\n"));
+        assert!(out.contains("                [[ $used == *' COMPLETIST_OPT_VERBOSE '* ]] || print -r -- '--verbose' '-v'\n"));
+        assert!(out.contains("                print -r -- '--unit'\n"));
+        assert!(out.contains("            'svc') print -r -- 'start' ;;\n"));
+        assert!(out.contains("        'svc/'*) kind='argument FILE' ;;\n"));
+        assert!(out.contains("        'svc start/0') kind='argument MODE' ;;\n"));
+        assert!(out.contains("    'svc/argument FILE') return 2 ;;\n"));
+        assert!(out.contains("    'svc start/COMPLETIST_OPT_UNIT') sh -c 'ls /etc/init.d' ;;\n"));
+        assert!(out.contains("    'svc start/argument MODE') print -r -- 'fast' ;;\n"));
+        assert!(out.contains("    [[ ${words[0]} == 'svc' ]] || return 0\n"));
+        assert!(out.ends_with("trap _completist_svc_keybd KEYBD\n"));
+    }
+}
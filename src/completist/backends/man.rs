@@ -0,0 +1,214 @@
+use std::io::Result;
+
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{capture, Output, Write};
+use completist::program::{Program, Command, Opt};
+use completist::usage::{self, Part, Style};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("man");
+    builder.exts(&[".1", ".2", ".3", ".4", ".5", ".6", ".7", ".8", ".9"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+// Escapes text for roff, including control characters at the start of a line.
+fn escape(s: &str) -> String {
+    let lines: Vec<String> = s.lines()
+        .map(|line| line.replace("\\", "\\e").replace("-", "\\-"))
+        .map(|line| if line.starts_with('.') || line.starts_with('\'') { format!("\\&{}", line) } else { line })
+        .collect();
+    lines.join("\n")
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escape(s).replace("\"", "\\(dq"))
+}
+
+fn bold(s: &str) -> String {
+    format!("\\fB{}\\fR", escape(s))
+}
+
+fn italic(s: &str) -> String {
+    format!("\\fI{}\\fR", escape(s))
+}
+
+fn synopsis(path: &[&str], command: &Command) -> String {
+    let elements: Vec<String> = usage::elements(path, command, Style::Gnu).iter()
+        .map(|element| {
+            element.iter()
+                .map(|part| match *part {
+                    Part::Literal(ref s) => bold(s),
+                    Part::Placeholder(ref s) => italic(s),
+                    Part::Syntax(s) => s.to_string(),
+                })
+                .collect()
+        })
+        .collect();
+    elements.join(" ")
+}
+
+fn write_options(out: &mut Output, options: &[Opt]) -> Result<()> {
+    for opt in options {
+        let names: Vec<String> = opt.shorts.iter().chain(opt.longs.iter()).map(|n| bold(n)).collect();
+        try!(out.write_fmt(format_args!(".TP\n{}", names.join(", "))));
+        if opt.argkind.is_some() {
+            try!(out.write_fmt(format_args!(" {}", italic(&opt.name().to_uppercase()))));
+        }
+        try!(out.write_fmt(format_args!("\n{}\n", escape(&opt.description))));
+    }
+    Ok(())
+}
+
+fn page_name(path: &[&str]) -> String {
+    path.join("-")
+}
+
+fn write_page(out: &mut Output, prog: &Program, path: &[&str], command: &Command, split: bool) -> Result<()> {
+    let name = page_name(path);
+    let source = match prog.meta.version {
+        Some(ref version) => format!("{} {}", prog.name, version),
+        None => prog.name.clone(),
+    };
+    let manual = if prog.meta.section == "1" { "User Commands" } else { "" };
+    try!(out.write_fmt(format_args!(".TH {} {} {} {} {}\n", quote(&name.to_uppercase()),
+        quote(&prog.meta.section), quote(prog.meta.date.as_ref().map(|d| &d[..]).unwrap_or("")),
+        quote(&source), quote(manual))));
+
+    try!(out.write_fmt(format_args!(".SH NAME\n{}", escape(&name))));
+    if !command.description.is_empty() {
+        try!(out.write_fmt(format_args!(" \\- {}", escape(&command.description))));
+    }
+    try!(out.write_fmt(format_args!("\n.SH SYNOPSIS\n{}\n", synopsis(path, command))));
+
+    if !command.options.is_empty() {
+        try!(out.write_fmt(format_args!(".SH OPTIONS\n")));
+        try!(write_options(out, &command.options));
+    }
+
+    if split && !command.commands.is_empty() {
+        try!(out.write_fmt(format_args!(".SH COMMANDS\n")));
+        for sub in &command.commands {
+            let mut subpath = path.to_vec();
+            subpath.push(&sub.name);
+            try!(out.write_fmt(format_args!(".TP\n{}({})\n{}\n", bold(&page_name(&subpath)),
+                escape(&prog.meta.section), escape(&sub.description))));
+        }
+    } else if !command.commands.is_empty() {
+        // Everything below this command goes on the same page, each with its
+        // options indented under it.
+        try!(out.write_fmt(format_args!(".SH COMMANDS\n")));
+        let mut descendants = Vec::new();
+        for (subpath, sub) in prog.walk() {
+            if subpath.len() > path.len() && subpath.starts_with(path) {
+                descendants.push((subpath, sub));
+            }
+        }
+        for (subpath, sub) in descendants {
+            try!(out.write_fmt(format_args!(".TP\n{}\n{}\n", synopsis(&subpath, sub), escape(&sub.description))));
+            if !sub.options.is_empty() {
+                try!(out.write_fmt(format_args!(".RS\n")));
+                try!(write_options(out, &sub.options));
+                try!(out.write_fmt(format_args!(".RE\n")));
+            }
+        }
+    }
+
+    if !prog.meta.authors.is_empty() {
+        try!(out.write_fmt(format_args!(".SH AUTHORS\n{}\n", escape(&prog.meta.authors.join(", ")))));
+    }
+
+    if split {
+        let mut related = Vec::new();
+        if path.len() > 1 {
+            related.push(page_name(&path[..path.len() - 1]));
+        }
+        for sub in &command.commands {
+            let mut subpath = path.to_vec();
+            subpath.push(&sub.name);
+            related.push(page_name(&subpath));
+        }
+        if !related.is_empty() {
+            let related: Vec<String> = related.iter()
+                .map(|page| format!("{}({})", bold(page), escape(&prog.meta.section)))
+                .collect();
+            try!(out.write_fmt(format_args!(".SH SEE ALSO\n{}\n", related.join(", "))));
+        }
+    }
+    Ok(())
+}
+
+// One page per command, named like `cargo-bench.1`.
+pub fn pages(prog: &Program) -> Result<Vec<(String, String)>> {
+    let mut pages = Vec::new();
+    for (path, command) in prog.walk() {
+        let page = try!(capture(|out| write_page(out, prog, &path, command, true)));
+        pages.push((format!("{}.{}", page_name(&path), prog.meta.section), page));
+    }
+    Ok(pages)
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    write_page(out, prog, &[&prog.name], &prog.base_command, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+
+    const SPEC: &'static str = "
+        name = 'svc'
+        description = 'manage services'
+        version = '1.2'
+        date = '2026-10-18'
+        author = 'Jo Bloggs'
+        [[option]]
+        long = '--verbose'
+        short = '-v'
+        description = 'say more'
+        [[command]]
+        name = 'start'
+        description = 'start a service'
+        [[command.argument]]
+        name = 'UNIT'
+        kind = 'file+'
+        optional = true
+        [[command.option]]
+        long = '--wait'
+        argkind = 'choice(yes|no)'
+        description = '.wait for it'
+    ";
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("a-b\\c\n.d"), "a\\-b\\ec\n\\&.d");
+        assert_eq!(quote("say \"hi\""), "\"say \\(dqhi\\(dq\"");
+    }
+
+    #[test]
+    fn single_page() {
+        let prog = parse_program(SPEC).unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+        assert_eq!(out, ".TH \"SVC\" \"1\" \"2026\\-10\\-18\" \"svc 1.2\" \"User Commands\"\n\
+            .SH NAME\nsvc \\- manage services\n\
+            .SH SYNOPSIS\n\\fBsvc\\fR [\\fIOPTION\\fR]... \\fICOMMAND\\fR\n\
+            .SH OPTIONS\n.TP\n\\fB\\-v\\fR, \\fB\\-\\-verbose\\fR\nsay more\n\
+            .SH COMMANDS\n.TP\n\\fBsvc start\\fR [\\fIOPTION\\fR]... [\\fIUNIT\\fR]...\nstart a service\n\
+            .RS\n.TP\n\\fB\\-\\-wait\\fR \\fIWAIT\\fR\n\\&.wait for it\n.RE\n\
+            .SH AUTHORS\nJo Bloggs\n");
+    }
+
+    #[test]
+    fn split_pages() {
+        let prog = parse_program(SPEC).unwrap();
+        let pages = pages(&prog).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].0, "svc.1");
+        assert!(pages[0].1.contains(".SH COMMANDS\n.TP\n\\fBsvc\\-start\\fR(1)\nstart a service\n"));
+        assert!(pages[0].1.ends_with(".SH SEE ALSO\n\\fBsvc\\-start\\fR(1)\n"));
+        assert_eq!(pages[1].0, "svc-start.1");
+        assert!(pages[1].1.starts_with(".TH \"SVC\\-START\" \"1\""));
+        assert!(pages[1].1.contains(".SH NAME\nsvc\\-start \\- start a service\n"));
+        assert!(pages[1].1.ends_with(".SH SEE ALSO\n\\fBsvc\\fR(1)\n"));
+    }
+}
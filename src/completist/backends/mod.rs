@@ -0,0 +1,70 @@
+use completist::formatter::Formatter;
+
+pub mod fish;
+pub mod bash;
+pub mod zsh;
+pub mod powershell;
+pub mod nushell;
+pub mod elvish;
+pub mod xonsh;
+pub mod tcsh;
+pub mod ksh;
+pub mod carapace;
+pub mod fig;
+pub mod man;
+pub mod docs;
+pub mod ct;
+pub mod clap;
+
+pub fn formatters() -> Vec<Formatter> {
+    vec![
+        fish::formatter(),
+        bash::formatter(),
+        zsh::formatter(),
+        powershell::formatter(),
+        nushell::formatter(),
+        elvish::formatter(),
+        xonsh::formatter(),
+        tcsh::formatter(),
+        ksh::formatter(),
+        carapace::formatter(),
+        fig::formatter(),
+        man::formatter(),
+        docs::formatter(),
+        docs::html_formatter(),
+        ct::formatter(),
+        clap::formatter(),
+    ]
+}
+
+pub fn find_formatter(name: &str) -> Option<Formatter> {
+    formatters().into_iter().find(|f| f.name == name)
+}
+
+pub fn formatter_for_path(path: &str) -> Option<Formatter> {
+    let extension = match path.rfind('.') {
+        Some(i) if i > path.rfind('/').map(|j| j + 1).unwrap_or(0) => &path[i..],
+        _ => return None,
+    };
+
+    formatters().into_iter().find(|f| f.matches_extension(extension.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_formatters() {
+        assert_eq!(find_formatter("powershell").map(|f| f.name), Some("powershell".to_string()));
+        assert!(find_formatter("cobol").is_none());
+    }
+
+    #[test]
+    fn formatters_for_paths() {
+        assert_eq!(formatter_for_path("out/cargo.ps1").map(|f| f.name), Some("powershell".to_string()));
+        assert_eq!(formatter_for_path("cargo.csh").map(|f| f.name), Some("tcsh".to_string()));
+        assert!(formatter_for_path("out.d/cargo").is_none());
+        assert!(formatter_for_path(".ps1").is_none());
+    }
+}
@@ -0,0 +1,287 @@
+use completist::engine::variable_name;
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{Output, Write};
+use completist::program::{Program, Command, Argument, Opt, OptKind};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("nushell");
+    builder.exts(&[".nu"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\""))
+}
+
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect()
+}
+
+fn completer_name(path: &[&str], name: &str) -> String {
+    format!("nu-complete {} {}", path.join(" "), name)
+}
+
+fn context_name(prog: &Program) -> String {
+    format!("nu-complete {} context", prog.name)
+}
+
+fn value_type(kind: &OptKind, completer: &str) -> String {
+    match *kind {
+        OptKind::File | OptKind::FilePlus => "path".to_string(),
+        _ => format!("string@{}", quote(completer)),
+    }
+}
+
+// Custom completers get the command line so far, which is turned into the
+// same COMPLETIST_* variables the other backends export.
+fn write_context(out: &mut Output, prog: &Program) -> FmtResult {
+    try!(out.write_fmt(format_args!("def {} [context: string] {{\n", quote(&context_name(prog)))));
+    try!(out.write_all(br#"    let words = ($context | split row -r '\s+')
+    let prev = ($words | drop 1)
+    mut vars = {
+        COMPLETIST_CURRENT: ($words | last)
+        COMPLETIST_PREV: (if ($prev | is-empty) { "" } else { $prev | last })
+        COMPLETIST_WORDS: ($prev | str join "\n")
+    }
+    mut i = 0
+    while $i + 1 < ($prev | length) {
+        let word = ($prev | get $i)
+        let value = ($prev | get ($i + 1))
+        match $word {
+"#));
+
+    let mut seen = Vec::new();
+    for (_, command) in prog.walk() {
+        for opt in &command.options {
+            if opt.argkind.is_none() || seen.contains(&opt.name()) {
+                continue;
+            }
+            seen.push(opt.name());
+
+            let names: Vec<String> = opt.longs.iter().chain(opt.shorts.iter())
+                .map(|name| quote(name))
+                .collect();
+            try!(out.write_fmt(format_args!(
+                "            {} => {{ $vars = ($vars | upsert {} $value) }}\n",
+                names.join(" | "), variable_name(&opt.name()))));
+        }
+    }
+
+    try!(out.write_all(br#"            _ => {}
+        }
+        $i += 1
+    }
+    $vars
+}
+"#));
+    Ok(())
+}
+
+fn write_completer(out: &mut Output, prog: &Program, name: &str, kind: &OptKind) -> FmtResult {
+    let body = match *kind {
+        OptKind::File | OptKind::FilePlus => return Ok(()),
+        OptKind::Choices(ref choices) => {
+            let choices: Vec<String> = choices.iter().map(|c| quote(c)).collect();
+            format!("[{}]", choices.join(" "))
+        },
+        OptKind::Command(ref cmd) => format!("with-env ({} $context) {{ ^sh -c {} | lines }}",
+            context_name(prog), quote(cmd)),
+        OptKind::Function(ref func) => format!("with-env ({} $context) {{ {} }}",
+            context_name(prog), func),
+    };
+
+    try!(out.write_fmt(format_args!("\ndef {} [context: string] {{\n    {}\n}}\n", quote(name), body)));
+    Ok(())
+}
+
+fn write_flag(out: &mut Output, path: &[&str], opt: &Opt, taken: &mut Vec<String>) -> FmtResult {
+    let argtype = match opt.argkind {
+        Some(ref kind) => format!(": {}", value_type(kind, &completer_name(path, &opt.name()))),
+        None => String::new(),
+    };
+
+    let mut shorts = opt.shorts.iter().filter(|s| s.chars().count() == 2);
+    let mut flags = Vec::new();
+    for long in &opt.longs {
+        if !long.starts_with("--") {
+            try!(out.write_fmt(format_args!("    # {} cannot be expressed as a nushell flag\n", long)));
+        } else if !taken.contains(long) {
+            taken.push(long.clone());
+            let short = shorts.by_ref().find(|s| !taken.contains(s));
+            match short {
+                Some(short) => {
+                    taken.push(short.clone());
+                    flags.push(format!("{}({})", long, short));
+                },
+                None => flags.push(long.clone()),
+            }
+        }
+    }
+    for short in shorts {
+        if !taken.contains(short) {
+            taken.push(short.clone());
+            flags.push(short.clone());
+        }
+    }
+
+    for flag in flags {
+        try!(out.write_fmt(format_args!("    {}{} # {}\n", flag, argtype, opt.description)));
+    }
+    Ok(())
+}
+
+fn write_positional(out: &mut Output, path: &[&str], arg: &Argument, optional: bool) -> FmtResult {
+    let argtype = value_type(&arg.kind, &completer_name(path, &arg.name));
+    let name = identifier(&arg.name);
+    if arg.kind == OptKind::FilePlus {
+        try!(out.write_fmt(format_args!("    ...{}: {}\n", name, argtype)));
+    } else if optional {
+        try!(out.write_fmt(format_args!("    {}?: {}\n", name, argtype)));
+    } else {
+        try!(out.write_fmt(format_args!("    {}: {}\n", name, argtype)));
+    }
+    Ok(())
+}
+
+fn write_extern(out: &mut Output, prog: &Program, path: &[&str], command: &Command) -> FmtResult {
+    for opt in &command.options {
+        if let Some(ref kind) = opt.argkind {
+            try!(write_completer(out, prog, &completer_name(path, &opt.name()), kind));
+        }
+    }
+    for arg in &command.arguments {
+        try!(write_completer(out, prog, &completer_name(path, &arg.name), &arg.kind));
+    }
+
+    try!(out.write_fmt(format_args!("\nexport extern {} [\n", quote(&path.join(" ")))));
+    let mut taken = Vec::new();
+    for opt in &command.options {
+        try!(write_flag(out, path, opt, &mut taken));
+    }
+    // nushell only accepts required positionals, then optional ones, then
+    // one rest parameter.
+    let mut optional = None;
+    let mut rest = None;
+    for arg in &command.arguments {
+        if let Some(ref rest) = rest {
+            try!(out.write_fmt(format_args!("    # {} cannot follow ...{}\n", arg.name, rest)));
+            continue;
+        }
+        if let (false, Some(optional)) = (arg.optional, optional) {
+            try!(out.write_fmt(format_args!("    # {} is optional here since it follows {}\n", arg.name, optional)));
+        }
+        try!(write_positional(out, path, arg, arg.optional || optional.is_some()));
+        if arg.kind == OptKind::FilePlus {
+            rest = Some(identifier(&arg.name));
+        } else if arg.optional && optional.is_none() {
+            optional = Some(&arg.name);
+        }
+    }
+    try!(out.write_fmt(format_args!("]\n")));
+    Ok(())
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    try!(out.write_fmt(format_args!("# completions for {}, generated by completist\n\n", prog.name)));
+    try!(write_context(out, prog));
+    for (path, command) in prog.walk() {
+        try!(write_extern(out, prog, &path, command));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::io::capture;
+    use completist::parse_program;
+
+    #[test]
+    fn names() {
+        assert_eq!(quote("say \"hi\\\""), "\"say \\\"hi\\\\\\\"\"");
+        assert_eq!(identifier("OUT-FILE"), "out_file");
+        assert_eq!(completer_name(&["cargo", "bench"], "bin"), "nu-complete cargo bench bin");
+    }
+
+    #[test]
+    fn write_externs() {
+        let prog = parse_program("
+            name = 'cat'
+            [[argument]]
+            name = 'FILE'
+            kind = 'file+'
+            [[option]]
+            long = '--show-all'
+            short = '-A'
+            description = 'equivalent to -vET'
+            [[option]]
+            short = '-t'
+            description = 'equivalent to -vT'
+            [[option]]
+            long = '--show-tabs'
+            short = '-t'
+            description = 'display TAB characters as ^I'
+            [[option]]
+            long = '-old'
+            description = 'old style'
+            [[command]]
+            name = 'sub'
+            [[command.argument]]
+            name = 'MODE'
+            kind = 'choice(fast|slow)'
+            [[command.argument]]
+            name = 'BRANCH'
+            kind = 'command(git branch)'
+            optional = true
+            [[command.option]]
+            long = '--output'
+            argkind = 'file'
+            description = 'output file'
+        ").unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+
+        assert!(out.starts_with("# completions for cat, generated by completist\n\n\
+            def \"nu-complete cat context\" [context: string] {\n"));
+        assert!(out.contains("\"--output\" => { $vars = ($vars | upsert COMPLETIST_OPT_OUTPUT $value) }\n"));
+        assert!(out.contains("\nexport extern \"cat\" [\n    \
+            --show-all(-A) # equivalent to -vET\n    \
+            -t # equivalent to -vT\n    \
+            --show-tabs # display TAB characters as ^I\n    \
+            # -old cannot be expressed as a nushell flag\n    \
+            ...file: path\n]\n"));
+        assert!(out.contains("\ndef \"nu-complete cat sub MODE\" [context: string] {\n    \
+            [\"fast\" \"slow\"]\n}\n"));
+        assert!(out.contains("\ndef \"nu-complete cat sub BRANCH\" [context: string] {\n    \
+            with-env (nu-complete cat context $context) { ^sh -c \"git branch\" | lines }\n}\n"));
+        assert!(out.contains("\nexport extern \"cat sub\" [\n    \
+            --output: path # output file\n    \
+            mode: string@\"nu-complete cat sub MODE\"\n    \
+            branch?: string@\"nu-complete cat sub BRANCH\"\n]\n"));
+    }
+
+    #[test]
+    fn invalid_signatures() {
+        let prog = parse_program("
+            name = 'cp'
+            [[argument]]
+            name = 'MODE'
+            kind = 'choice(fast|slow)'
+            optional = true
+            [[argument]]
+            name = 'SOURCE'
+            kind = 'file+'
+            [[argument]]
+            name = 'DEST'
+            kind = 'file'
+        ").unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+
+        assert!(out.contains("\nexport extern \"cp\" [\n    \
+            mode?: string@\"nu-complete cp MODE\"\n    \
+            # SOURCE is optional here since it follows MODE\n    \
+            ...source: path\n    \
+            # DEST cannot follow ...source\n]\n"));
+    }
+}
@@ -0,0 +1,309 @@
+use completist::engine::variable_name;
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{Output, Write};
+use completist::program::{Program, Command, Opt, OptKind, Cache};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("powershell");
+    builder.exts(&[".ps1", ".psm1"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace("'", "''"))
+}
+
+fn quote_list<'a, I>(items: I) -> String where I: Iterator<Item=&'a String> {
+    let items: Vec<String> = items.map(|i| quote(i)).collect();
+    format!("@({})", items.join(", "))
+}
+
+fn write_kind(out: &mut Output, kind: &OptKind, cache: &Option<Cache>) -> FmtResult {
+    match *kind {
+        OptKind::File => try!(out.write_fmt(format_args!("Kind = 'file'"))),
+        OptKind::FilePlus => try!(out.write_fmt(format_args!("Kind = 'file'; Variadic = $true"))),
+        OptKind::Command(ref cmd) =>
+            try!(out.write_fmt(format_args!("Kind = 'command'; Value = {}", quote(cmd)))),
+        OptKind::Function(ref func) =>
+            try!(out.write_fmt(format_args!("Kind = 'function'; Value = {}", quote(func)))),
+        OptKind::Choices(ref choices) =>
+            try!(out.write_fmt(format_args!("Kind = 'choice'; Values = {}", quote_list(choices.iter())))),
+    }
+
+    if let Some(ref cache) = *cache {
+        try!(out.write_fmt(format_args!("; Ttl = {}; PerDirectory = ${}; Watch = {}",
+            cache.ttl, cache.per_directory, quote_list(cache.watch.iter()))));
+    }
+
+    Ok(())
+}
+
+fn write_opt(out: &mut Output, opt: &Opt) -> FmtResult {
+    // tooltips must not be empty
+    let description = if opt.description.is_empty() { opt.name() } else { opt.description.clone() };
+    try!(out.write_fmt(format_args!("                @{{ Names = {}; Description = {}; Name = {}",
+        quote_list(opt.longs.iter().chain(opt.shorts.iter())),
+        quote(&description),
+        quote(&variable_name(&opt.name())))));
+    if let Some(ref kind) = opt.argkind {
+        try!(out.write_fmt(format_args!("; ")));
+        try!(write_kind(out, kind, &opt.cache));
+    }
+    try!(out.write_fmt(format_args!(" }}\n")));
+    Ok(())
+}
+
+fn write_command(out: &mut Output, path: &[&str], command: &Command) -> FmtResult {
+    let subcommands: Vec<String> = command.commands.iter().map(|c| c.name.clone()).collect();
+    try!(out.write_fmt(format_args!("        {} = @{{\n", quote(&path.join(" ")))));
+    try!(out.write_fmt(format_args!("            Commands = {}\n", quote_list(subcommands.iter()))));
+
+    try!(out.write_fmt(format_args!("            Options = @(\n")));
+    for opt in &command.options {
+        try!(write_opt(out, opt));
+    }
+    try!(out.write_fmt(format_args!("            )\n")));
+
+    try!(out.write_fmt(format_args!("            Arguments = @(\n")));
+    for argument in &command.arguments {
+        try!(out.write_fmt(format_args!("                @{{ Name = {}; ", quote(&argument.name))));
+        try!(write_kind(out, &argument.kind, &argument.cache));
+        try!(out.write_fmt(format_args!(" }}\n")));
+    }
+    try!(out.write_fmt(format_args!("            )\n")));
+
+    try!(out.write_fmt(format_args!("        }}\n")));
+    Ok(())
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    try!(out.write_fmt(format_args!("# completion for {}, generated by completist\n", prog.name)));
+    try!(out.write_fmt(format_args!(
+        "Register-ArgumentCompleter -Native -CommandName {} -ScriptBlock {{\n", quote(&prog.name))));
+    try!(out.write_fmt(format_args!("    param($wordToComplete, $commandAst, $cursorPosition)\n\n")));
+    try!(out.write_fmt(format_args!("    $program = {}\n", quote(&prog.name))));
+    try!(out.write_fmt(format_args!("    $commands = @{{\n")));
+    for (path, command) in prog.walk() {
+        try!(write_command(out, &path, command));
+    }
+    try!(out.write_fmt(format_args!("    }}\n")));
+    try!(out.write_all(COMPLETER.as_bytes()));
+    try!(out.write_fmt(format_args!("}}\n")));
+    Ok(())
+}
+
+// The part of the script block that walks the command AST. It only depends on
+// the `$program` and `$commands` tables written above it.
+const COMPLETER: &'static str = r#"
+    $words = @($commandAst.CommandElements |
+        Where-Object { $_.Extent.EndOffset -lt $cursorPosition } |
+        ForEach-Object { $_.ToString() })
+
+    $path = $program
+    $pending = $null
+    $positionals = 0
+    $separated = $false
+    $used = @()
+    $values = @{}
+    foreach ($word in ($words | Select-Object -Skip 1)) {
+        $spec = $commands[$path]
+        if ($pending) {
+            $values[$pending.Name] = $word
+            $pending = $null
+        } elseif ($separated -or $word -eq '-' -or -not $word.StartsWith('-')) {
+            if (-not $separated -and $positionals -eq 0 -and $spec.Commands -contains $word) {
+                $path = "$path $word"
+                $used = @()
+            } else {
+                $positionals++
+            }
+        } elseif ($word -eq '--') {
+            $separated = $true
+        } else {
+            $name, $value = $word -split '=', 2
+            $opt = $spec.Options | Where-Object { $_.Names -ccontains $name } | Select-Object -First 1
+            if ($opt) {
+                $used += $opt.Name
+                if ($opt.Kind -and $null -ne $value) {
+                    $values[$opt.Name] = $value
+                } elseif ($opt.Kind) {
+                    $pending = $opt
+                }
+            } elseif (-not $word.StartsWith('--')) {
+                # a cluster of short options like -vo, where the rest of the
+                # word after one that takes a value is that value, as in -ofile
+                for ($i = 1; $i -lt $word.Length; $i++) {
+                    $short = "-$($word[$i])"
+                    $opt = $spec.Options | Where-Object { $_.Names -ccontains $short } | Select-Object -First 1
+                    if (-not $opt) { continue }
+                    $used += $opt.Name
+                    if ($opt.Kind) {
+                        if ($i + 1 -lt $word.Length) {
+                            $values[$opt.Name] = $word.Substring($i + 1)
+                        } else {
+                            $pending = $opt
+                        }
+                        break
+                    }
+                }
+            }
+        }
+    }
+
+    $spec = $commands[$path]
+    $kind = $pending
+    if (-not $kind) {
+        if (-not $separated -and $wordToComplete.StartsWith('-')) {
+            foreach ($opt in $spec.Options) {
+                if ($used -contains $opt.Name) { continue }
+                foreach ($name in $opt.Names) {
+                    if ($name.StartsWith($wordToComplete)) {
+                        [System.Management.Automation.CompletionResult]::new(
+                            $name, $name, 'ParameterName', $opt.Description)
+                    }
+                }
+            }
+            return
+        }
+
+        if (-not $separated -and $positionals -eq 0) {
+            foreach ($sub in $spec.Commands) {
+                if ($sub.StartsWith($wordToComplete)) {
+                    [System.Management.Automation.CompletionResult]::new(
+                        $sub, $sub, 'Command', $sub)
+                }
+            }
+        }
+
+        $index = 0
+        foreach ($argument in $spec.Arguments) {
+            if ($index -eq $positionals -or ($index -lt $positionals -and $argument.Variadic)) {
+                $kind = $argument
+                break
+            }
+            $index++
+        }
+    }
+
+    if (-not $kind) { return }
+    if ($kind.Kind -eq 'choice') {
+        foreach ($choice in $kind.Values) {
+            if ($choice.StartsWith($wordToComplete)) {
+                [System.Management.Automation.CompletionResult]::new(
+                    $choice, $choice, 'ParameterValue', $choice)
+            }
+        }
+        return
+    }
+    if ($kind.Kind -eq 'file') {
+        [System.Management.Automation.CompletionCompleters]::CompleteFilename($wordToComplete)
+        return
+    }
+
+    $env:COMPLETIST_CURRENT = $wordToComplete
+    $env:COMPLETIST_PREV = $words[-1]
+    $env:COMPLETIST_WORDS = $words -join "`n"
+    foreach ($name in $values.Keys) {
+        Set-Item "Env:$name" $values[$name]
+    }
+
+    # Commands run in sh like in every other backend, so that they see the
+    # COMPLETIST_* variables as environment variables, and functions are
+    # PowerShell code.
+    $run = {
+        if ($kind.Kind -eq 'command') {
+            @(sh -c $kind.Value)
+        } else {
+            @(& ([scriptblock]::Create($kind.Value)))
+        }
+    }
+    if ($null -eq $kind.Ttl) {
+        $lines = @(& $run)
+    } else {
+        $cacheDir = if ($env:XDG_CACHE_HOME) { Join-Path $env:XDG_CACHE_HOME 'completist' } else { Join-Path $HOME '.cache/completist' }
+        $key = @($kind.Value, $env:COMPLETIST_CURRENT, $env:COMPLETIST_PREV, $env:COMPLETIST_WORDS) +
+            @($values.GetEnumerator() | Sort-Object Name | ForEach-Object { "$($_.Name)=$($_.Value)" })
+        if ($kind.PerDirectory) { $key = @($PWD.Path) + $key }
+        $sha = [System.Security.Cryptography.SHA1]::Create()
+        $hash = [BitConverter]::ToString($sha.ComputeHash([Text.Encoding]::UTF8.GetBytes($key -join "`0"))) -replace '-', ''
+        $file = Join-Path $cacheDir "pwsh_$hash"
+
+        $item = Get-Item $file -ErrorAction SilentlyContinue
+        $fresh = $item -and ((Get-Date) - $item.LastWriteTime).TotalSeconds -lt $kind.Ttl
+        foreach ($watched in $kind.Watch) {
+            $watchedItem = Get-Item $watched -ErrorAction SilentlyContinue
+            if ($fresh -and $watchedItem -and $watchedItem.LastWriteTime -gt $item.LastWriteTime) {
+                $fresh = $false
+            }
+        }
+
+        if ($fresh) {
+            $lines = @(Get-Content $file)
+        } else {
+            $lines = @(& $run)
+            New-Item -ItemType Directory -Force $cacheDir | Out-Null
+            $lines | Set-Content $file
+        }
+    }
+    Remove-Item Env:COMPLETIST_* -ErrorAction SilentlyContinue
+
+    foreach ($line in $lines) {
+        if ("$line".StartsWith($wordToComplete)) {
+            [System.Management.Automation.CompletionResult]::new(
+                "$line", "$line", 'ParameterValue', "$line")
+        }
+    }
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::io::capture;
+    use completist::parse_program;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("it's"), "'it''s'");
+        assert_eq!(quote_list(vec!["a".to_string(), "b".to_string()].iter()), "@('a', 'b')");
+    }
+
+    #[test]
+    fn write_cargo() {
+        let prog = parse_program("
+            name = 'cargo'
+            [[command]]
+            name = 'bench'
+            [[command.option]]
+            long = '--lib'
+            description = \"Benchmark only this package's library\"
+            [[command.option]]
+            long = '--bin'
+            argkind = 'command(ls target/debug)'
+            description = 'Benchmark only the specified binary'
+            cache = { ttl = 60, watch = ['Cargo.toml'] }
+            [[command.argument]]
+            name = 'FILE'
+            kind = 'file+'
+            [[command.argument]]
+            name = 'MODE'
+            kind = 'choice(fast|slow)'
+        ").unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+
+        assert!(out.starts_with("# completion for cargo, generated by completist\n\
+            Register-ArgumentCompleter -Native -CommandName 'cargo' -ScriptBlock {\n"));
+        assert!(out.contains("\n        'cargo' = @{\n            Commands = @('bench')\n"));
+        assert!(out.contains("\n        'cargo bench' = @{\n            Commands = @()\n"));
+        assert!(out.contains("@{ Names = @('--lib'); Description = 'Benchmark only this package''s library'; \
+            Name = 'COMPLETIST_OPT_LIB' }"));
+        assert!(out.contains("@{ Names = @('--bin'); Description = 'Benchmark only the specified binary'; \
+            Name = 'COMPLETIST_OPT_BIN'; Kind = 'command'; Value = 'ls target/debug'; \
+            Ttl = 60; PerDirectory = $true; Watch = @('Cargo.toml') }"));
+        assert!(out.contains("@{ Name = 'FILE'; Kind = 'file'; Variadic = $true }"));
+        assert!(out.contains("@{ Name = 'MODE'; Kind = 'choice'; Values = @('fast', 'slow') }"));
+        assert!(out.contains("            @(sh -c $kind.Value)\n"));
+        assert!(out.contains("$values[$opt.Name] = $word.Substring($i + 1)"));
+        assert!(out.contains("$key = @($kind.Value, $env:COMPLETIST_CURRENT, $env:COMPLETIST_PREV, $env:COMPLETIST_WORDS)"));
+        assert!(out.ends_with("\"$line\", \"$line\", 'ParameterValue', \"$line\")\n        }\n    }\n}\n"));
+    }
+}
@@ -0,0 +1,239 @@
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{Output, Write};
+use completist::program::{Program, Opt, OptKind};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("tcsh");
+    builder.exts(&[".tcsh", ".csh"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+// tcsh's word lists, or the reason a kind doesn't have one
+fn word_list(kind: &OptKind) -> Result<String, String> {
+    match *kind {
+        OptKind::File | OptKind::FilePlus => Ok("f".to_string()),
+        OptKind::Choices(ref choices) => {
+            if choices.iter().any(|c| c.contains(|ch: char| ch.is_whitespace() || "()'".contains(ch))) {
+                Err("choices containing spaces, quotes or parentheses".to_string())
+            } else {
+                Ok(format!("({})", choices.join(" ")))
+            }
+        },
+        OptKind::Command(ref cmd) => {
+            if cmd.contains('`') || cmd.contains('\'') {
+                Err(format!("command `{}` contains quotes", cmd))
+            } else {
+                Ok(format!("`{}`", cmd))
+            }
+        },
+        OptKind::Function(ref func) => Err(format!("function `{}`", func)),
+    }
+}
+
+// Patterns are written as `kind/word/list/`, but any character can be used
+// instead of `/` as long as it doesn't appear in the word or list.
+fn pattern(kind: char, word: &str, list: &str) -> Option<String> {
+    "/@%:|,#+".chars()
+        .find(|&sep| !word.contains(sep) && !list.contains(sep))
+        .map(|sep| format!("{}{}{}{}{}{}", kind, sep, word, sep, list, sep))
+}
+
+struct Complete {
+    patterns: Vec<String>,
+    comments: Vec<String>,
+}
+
+impl Complete {
+    fn add(&mut self, kind: char, word: &str, what: &str, list: Result<String, String>) {
+        match list {
+            Ok(list) => match pattern(kind, word, &list) {
+                Some(pattern) => self.patterns.push(pattern),
+                None => self.comments.push(format!("{}: no separator fits `{}`", what, list)),
+            },
+            Err(reason) => self.comments.push(format!("{}: {}", what, reason)),
+        }
+    }
+
+    fn add_opt_arguments(&mut self, opt: &Opt) {
+        if let Some(ref kind) = opt.argkind {
+            for name in opt.longs.iter().chain(opt.shorts.iter()) {
+                self.add('n', name, &format!("argument of {}", name), word_list(kind));
+            }
+        }
+    }
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    let base = &prog.base_command;
+    let mut complete = Complete { patterns: Vec::new(), comments: Vec::new() };
+
+    let mut longs = Vec::new();
+    let mut shorts = Vec::new();
+    let mut has_cache = false;
+    for (path, command) in prog.walk() {
+        if path.len() > 1 && !command.options.is_empty() {
+            complete.comments.push(format!("options of `{}` are offered everywhere", path.join(" ")));
+        }
+        for opt in &command.options {
+            complete.add_opt_arguments(opt);
+            has_cache = has_cache || opt.cache.is_some();
+            for long in &opt.longs {
+                if long.starts_with("--") && !longs.contains(&long[2..].to_string()) {
+                    longs.push(long[2..].to_string());
+                } else if !long.starts_with("--") && !shorts.contains(&long[1..].to_string()) {
+                    shorts.push(long[1..].to_string());
+                }
+            }
+            for short in &opt.shorts {
+                if !shorts.contains(&short[1..].to_string()) {
+                    shorts.push(short[1..].to_string());
+                }
+            }
+        }
+        has_cache = has_cache || command.arguments.iter().any(|a| a.cache.is_some());
+    }
+    if !longs.is_empty() {
+        complete.patterns.push(format!("c/--/({})/", longs.join(" ")));
+    }
+    if !shorts.is_empty() {
+        complete.patterns.push(format!("c/-/({})/", shorts.join(" ")));
+    }
+
+    for sub in &base.commands {
+        if !sub.commands.is_empty() {
+            let names: Vec<&str> = sub.commands.iter().map(|c| &c.name[..]).collect();
+            complete.add('n', &sub.name, &format!("subcommands of {}", sub.name),
+                Ok(format!("({})", names.join(" "))));
+            if !sub.arguments.is_empty() {
+                complete.comments.push(format!("arguments of `{}` are hidden by its subcommands", sub.name));
+            }
+        } else {
+            for (i, arg) in sub.arguments.iter().enumerate() {
+                let what = format!("{} of {}", arg.name, sub.name);
+                match i {
+                    0 => complete.add('n', &sub.name, &what, word_list(&arg.kind)),
+                    1 => complete.add('N', &sub.name, &what, word_list(&arg.kind)),
+                    _ => complete.comments.push(format!("{}: too far from the subcommand", what)),
+                }
+            }
+        }
+        for nested in &sub.commands {
+            if !nested.commands.is_empty() || !nested.arguments.is_empty() {
+                complete.comments.push(format!("arguments and subcommands of `{} {}` and below",
+                    sub.name, nested.name));
+            }
+        }
+    }
+
+    if !base.commands.is_empty() {
+        let names: Vec<&str> = base.commands.iter().map(|c| &c.name[..]).collect();
+        complete.patterns.push(format!("p/1/({})/", names.join(" ")));
+        for arg in &base.arguments {
+            complete.comments.push(format!("{}: hidden by the subcommands", arg.name));
+        }
+    } else {
+        for (i, arg) in base.arguments.iter().enumerate() {
+            let position = if arg.kind == OptKind::FilePlus {
+                format!("{}-", i + 1)
+            } else {
+                format!("{}", i + 1)
+            };
+            complete.add('p', &position, &arg.name, word_list(&arg.kind));
+        }
+    }
+
+    try!(out.write_fmt(format_args!("# completions for {}, generated by completist\n", prog.name)));
+    try!(out.write_fmt(format_args!("# positions count options as well as arguments, and commands\n")));
+    try!(out.write_fmt(format_args!("# can only read the command line from $COMMAND_LINE\n")));
+    if has_cache {
+        try!(out.write_fmt(format_args!("# command output is never cached\n")));
+    }
+    if !complete.comments.is_empty() {
+        try!(out.write_fmt(format_args!("#\n# not expressible in tcsh:\n")));
+        for comment in &complete.comments {
+            try!(out.write_fmt(format_args!("#   {}\n", comment)));
+        }
+    }
+
+    try!(out.write_fmt(format_args!("complete {}", prog.name)));
+    for pattern in &complete.patterns {
+        try!(out.write_fmt(format_args!(" \\\n    '{}'", pattern)));
+    }
+    try!(out.write_fmt(format_args!("\n")));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::io::capture;
+    use completist::parse_program;
+    use completist::program::OptKind;
+
+    #[test]
+    fn word_lists() {
+        assert_eq!(word_list(&OptKind::File), Ok("f".to_string()));
+        assert_eq!(word_list(&OptKind::Command("git branch".to_string())), Ok("`git branch`".to_string()));
+        assert_eq!(word_list(&OptKind::Choices(vec!["a".to_string(), "b".to_string()])),
+            Ok("(a b)".to_string()));
+        assert!(word_list(&OptKind::Choices(vec!["a b".to_string()])).is_err());
+        assert!(word_list(&OptKind::Function("__fish_foo".to_string())).is_err());
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(pattern('n', "--bin", "`ls target/debug`"), Some("n@--bin@`ls target/debug`@".to_string()));
+        assert_eq!(pattern('p', "1", "f"), Some("p/1/f/".to_string()));
+        assert_eq!(pattern('p', "1", "/@%:|,#+"), None);
+    }
+
+    #[test]
+    fn write_cat() {
+        let prog = parse_program(include_str!("../../../samples/cat.ct")).unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+        assert!(out.starts_with("# completions for cat, generated by completist\n"));
+        assert!(out.contains("\ncomplete cat \\\n    \
+            'c/--/(show-all number-nonblank show-ends number squeeze-blank show-tabs \
+            show-nonprinting help version)/' \\\n    \
+            'c/-/(A b e E n s t u v)/' \\\n    \
+            'p/1-/f/'\n"));
+    }
+
+    #[test]
+    fn write_subcommands() {
+        let prog = parse_program("
+            name = 'svc'
+            [[argument]]
+            name = 'IGNORED'
+            kind = 'file'
+            [[command]]
+            name = 'start'
+            [[command.argument]]
+            name = 'SERVICE'
+            kind = 'command(ls /etc/init.d)'
+            [[command.argument]]
+            name = 'MODE'
+            kind = 'choice(fast|slow)'
+            [[command.argument]]
+            name = 'EXTRA'
+            kind = 'function(__extra)'
+            [[command.option]]
+            long = '--config'
+            short = '-c'
+            argkind = 'file'
+            description = 'config file'
+        ").unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+        assert!(out.contains("#   options of `svc start` are offered everywhere\n"));
+        assert!(out.contains("#   EXTRA of start: too far from the subcommand\n"));
+        assert!(out.contains("#   IGNORED: hidden by the subcommands\n"));
+        assert!(out.contains("\ncomplete svc \\\n    \
+            'n/--config/f/' \\\n    \
+            'n/-c/f/' \\\n    \
+            'c/--/(config)/' \\\n    \
+            'c/-/(c)/' \\\n    \
+            'n@start@`ls /etc/init.d`@' \\\n    \
+            'N/start/(fast slow)/' \\\n    \
+            'p/1/(start)/'\n"));
+    }
+}
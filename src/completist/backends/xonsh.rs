@@ -0,0 +1,206 @@
+use completist::engine::variable_name;
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{Output, Write};
+use completist::program::{Program, Command, OptKind};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("xonsh");
+    builder.exts(&[".xsh"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace("\\", "\\\\").replace("'", "\\'").replace("\n", "\\n"))
+}
+
+fn quote_list<'a, I>(items: I) -> String where I: Iterator<Item=&'a String> {
+    let items: Vec<String> = items.map(|i| quote(i)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn kind_fields(kind: Option<&OptKind>) -> String {
+    let none = Vec::new();
+    let (name, value, values) = match kind {
+        None => ("None".to_string(), String::new(), &none),
+        Some(&OptKind::File) | Some(&OptKind::FilePlus) => (quote("file"), String::new(), &none),
+        Some(&OptKind::Command(ref cmd)) => (quote("command"), cmd.clone(), &none),
+        Some(&OptKind::Function(ref func)) => (quote("function"), func.clone(), &none),
+        Some(&OptKind::Choices(ref choices)) => (quote("choice"), String::new(), choices),
+    };
+
+    format!("'kind': {}, 'value': {}, 'values': {}", name, quote(&value), quote_list(values.iter()))
+}
+
+fn write_command(out: &mut Output, path: &[&str], command: &Command) -> FmtResult {
+    let subcommands: Vec<String> = command.commands.iter().map(|c| c.name.clone()).collect();
+    try!(out.write_fmt(format_args!("    {}: {{\n", quote(&path.join(" ")))));
+    try!(out.write_fmt(format_args!("        'commands': {},\n", quote_list(subcommands.iter()))));
+
+    try!(out.write_fmt(format_args!("        'options': [\n")));
+    for opt in &command.options {
+        try!(out.write_fmt(format_args!("            {{'names': {}, 'description': {}, 'name': {}, {}}},\n",
+            quote_list(opt.longs.iter().chain(opt.shorts.iter())),
+            quote(&opt.description),
+            quote(&variable_name(&opt.name())),
+            kind_fields(opt.argkind.as_ref()))));
+    }
+    try!(out.write_fmt(format_args!("        ],\n")));
+
+    try!(out.write_fmt(format_args!("        'arguments': [\n")));
+    for arg in &command.arguments {
+        try!(out.write_fmt(format_args!("            {{'name': {}, 'variadic': {}, {}}},\n",
+            quote(&arg.name),
+            if arg.kind == OptKind::FilePlus { "True" } else { "False" },
+            kind_fields(Some(&arg.kind)))));
+    }
+    try!(out.write_fmt(format_args!("        ],\n")));
+
+    try!(out.write_fmt(format_args!("    }},\n")));
+    Ok(())
+}
+
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    let ident = identifier(&prog.name);
+    try!(out.write_fmt(format_args!("# completions for {}, generated by completist\n", prog.name)));
+    try!(out.write_all(IMPORTS.as_bytes()));
+    try!(out.write_fmt(format_args!("\n_completist_{}_commands = {{\n", ident)));
+    for (path, command) in prog.walk() {
+        try!(write_command(out, &path, command));
+    }
+    try!(out.write_fmt(format_args!("}}\n\n\n")));
+
+    try!(out.write_fmt(format_args!("@contextual_command_completer_for({})\n", quote(&prog.name))));
+    try!(out.write_fmt(format_args!("def _completist_{}(context):\n", ident)));
+    try!(out.write_fmt(format_args!("    return _completist_complete({}, _completist_{}_commands, context)\n\n\n",
+        quote(&prog.name), ident)));
+    try!(out.write_fmt(format_args!("add_one_completer({}, _completist_{}, 'start')\n",
+        quote(&format!("completist_{}", ident)), ident)));
+    Ok(())
+}
+
+// The walk over the words typed so far only depends on the table of commands
+// it is given, so it is the same for every program.
+const IMPORTS: &'static str = r#"import glob
+import os
+import subprocess
+
+from xonsh.completers.completer import add_one_completer
+from xonsh.completers.tools import RichCompletion, contextual_command_completer_for
+
+
+def _completist_run(kind, env):
+    if kind['kind'] == 'command':
+        args = ['sh', '-c', kind['value']]
+    else:
+        args = ['xonsh', '-c', kind['value']]
+    result = subprocess.run(args, env=env, stdout=subprocess.PIPE, universal_newlines=True)
+    return result.stdout.splitlines()
+
+
+def _completist_complete(program, commands, context):
+    words = [arg.value for arg in context.args[:context.arg_index]]
+    current = context.prefix
+    path = program
+    pending = None
+    positionals = 0
+    separated = False
+    used = []
+    values = {}
+    for word in words[1:]:
+        spec = commands[path]
+        if pending is not None:
+            values[pending['name']] = word
+            pending = None
+        elif separated or word == '-' or not word.startswith('-'):
+            if not separated and positionals == 0 and word in spec['commands']:
+                path += ' ' + word
+                used = []
+            else:
+                positionals += 1
+        elif word == '--':
+            separated = True
+        else:
+            name, equals, value = word.partition('=')
+            for opt in spec['options']:
+                if name in opt['names']:
+                    used.append(opt['name'])
+                    if opt['kind'] is not None and equals:
+                        values[opt['name']] = value
+                    elif opt['kind'] is not None:
+                        pending = opt
+                    break
+
+    spec = commands[path]
+    kind = pending
+    results = set()
+    if kind is None:
+        if not separated and current.startswith('-'):
+            for opt in spec['options']:
+                if opt['name'] in used:
+                    continue
+                for name in opt['names']:
+                    if name.startswith(current):
+                        results.add(RichCompletion(name, description=opt['description']))
+            return results
+
+        if not separated and positionals == 0:
+            results.update(sub for sub in spec['commands'] if sub.startswith(current))
+        for index, argument in enumerate(spec['arguments']):
+            if index == positionals or (index < positionals and argument['variadic']):
+                kind = argument
+                break
+
+    if kind is None:
+        return results
+    elif kind['kind'] == 'file':
+        for path in glob.glob(os.path.expanduser(current) + '*'):
+            results.add(path + '/' if os.path.isdir(path) else path)
+    elif kind['kind'] == 'choice':
+        results.update(choice for choice in kind['values'] if choice.startswith(current))
+    else:
+        env = dict(os.environ)
+        env['COMPLETIST_CURRENT'] = current
+        env['COMPLETIST_PREV'] = words[-1]
+        env['COMPLETIST_WORDS'] = '\n'.join(words)
+        env.update(values)
+        results.update(line for line in _completist_run(kind, env) if line.startswith(current))
+    return results
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::io::capture;
+    use completist::parse_program;
+
+    fn check_golden(spec: &str, golden: &str) {
+        let prog = parse_program(spec).unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+        assert_eq!(out, golden);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("it's a \\ \n"), "'it\\'s a \\\\ \\n'");
+        assert_eq!(identifier("my-prog"), "my_prog");
+        assert_eq!(kind_fields(None), "'kind': None, 'value': '', 'values': []");
+    }
+
+    #[test]
+    fn golden_cat() {
+        check_golden(include_str!("../../../samples/cat.ct"),
+            include_str!("../../../samples/golden/cat.xsh"));
+    }
+
+    #[test]
+    fn golden_cargo() {
+        check_golden(include_str!("../../../samples/cargo.ct"),
+            include_str!("../../../samples/golden/cargo.xsh"));
+    }
+}
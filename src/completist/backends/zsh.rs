@@ -0,0 +1,99 @@
+use completist::formatter::{Formatter, FormatterBuilder, FmtResult};
+use completist::io::{capture, Output, Write};
+use completist::program::Program;
+use super::ksh::{self, Dialect};
+
+pub fn formatter() -> Formatter {
+    let mut builder = FormatterBuilder::new("zsh");
+    builder.exts(&[".zsh"]).writer(write_program);
+    builder.build().unwrap()
+}
+
+// The script works both as an autoloaded `_<prog>` file in $fpath and when it
+// is sourced after compinit.
+pub fn write_program(out: &mut Output, prog: &Program) -> FmtResult {
+    let ident = ksh::identifier(&prog.name);
+    let mut comments = Vec::new();
+    let candidates = try!(capture(|out| ksh::write_candidates(out, Dialect::Zsh, prog, &mut comments)));
+
+    try!(out.write_fmt(format_args!("#compdef {}\n", prog.name)));
+    try!(out.write_fmt(format_args!("# completions for {}, generated by completist\n", prog.name)));
+    try!(out.write_all(b"# short options can't be grouped, as in -abc\n"));
+    if !comments.is_empty() {
+        try!(out.write_all(b"#\n# not expressible in zsh:\n"));
+        for comment in &comments {
+            try!(out.write_fmt(format_args!("#   {}\n", comment)));
+        }
+    }
+    try!(out.write_all(b"\n"));
+    try!(out.write_all(candidates.as_bytes()));
+
+    try!(out.write_fmt(format_args!("\n_{}() {{\n", ident)));
+    try!(out.write_all(br#"    local candidates candidate
+    local -a matches displays
+"#));
+    try!(out.write_fmt(format_args!(
+        "    candidates=$(_completist_{}_candidates \"$PREFIX\" \"${{(@)words[1,CURRENT-1]}}\")\n", ident)));
+    try!(out.write_all(br#"    case $? in
+        0) ;;
+        2) _files; return ;;
+        *) return 1 ;;
+    esac
+    for candidate in ${(f)candidates}; do
+        matches+=( "${candidate%%$'\t'*}" )
+        if [[ $candidate == *$'\t'?* ]]; then
+            displays+=( "${candidate%%$'\t'*}  -- ${candidate#*$'\t'}" )
+        else
+            displays+=( "$candidate" )
+        fi
+    done
+    compadd -d displays -a matches
+}
+
+"#));
+    try!(out.write_fmt(format_args!(
+        "if [ \"$funcstack[1]\" = \"_{}\" ]; then\n    _{} \"$@\"\nelse\n    compdef _{} {}\nfi\n",
+        ident, ident, ident, ksh::quote(&prog.name))));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+
+    #[test]
+    fn write_completion() {
+        let prog = parse_program("
+            name = 'svc'
+            [[argument]]
+            name = 'FILE'
+            kind = 'file+'
+            [[option]]
+            long = '--verbose'
+            short = '-v'
+            description = 'say more'
+            repeatable = false
+            [[command]]
+            name = 'start'
+            description = 'start a unit'
+            [[command]]
+            name = 'stop'
+            [[command.argument]]
+            name = 'MODE'
+            kind = 'choice(fast|really slow)'
+        ").unwrap();
+        let out = capture(|out| write_program(out, &prog)).unwrap();
+
+        assert!(out.starts_with("#compdef svc\n"));
+        assert!(!out.contains("not expressible"));
+        assert!(out.contains("function _completist_svc_candidates {\n    emulate -L ksh\n"));
+        assert!(out.contains("                [[ $used == *' COMPLETIST_OPT_VERBOSE '* ]] || \
+            printf '%s\\t%s\\n' '--verbose' 'say more' '-v' 'say more'\n"));
+        assert!(out.contains("            'svc') printf '%s\\t%s\\n' 'start' 'start a unit' 'stop' '' ;;\n"));
+        assert!(out.contains("    'svc stop/argument MODE') print -rl -- 'fast' 'really slow' ;;\n"));
+        assert!(out.contains("    compadd -d displays -a matches\n"));
+        assert!(out.contains("\n_svc() {\n"));
+        assert!(out.ends_with("else\n    compdef _svc 'svc'\nfi\n"));
+    }
+}
@@ -0,0 +1,300 @@
+use completist::backends::ct::kind_name;
+use completist::dynamic;
+use completist::engine;
+use completist::importers::shell;
+use completist::program::{Program, OptKind};
+
+// A completion case, written as
+//
+//   $ cat -<TAB>
+//   offers -A  equivalent to -vET
+//   omits --bogus
+//   = -A  equivalent to -vET
+//   = ...
+//
+// `offers` and `omits` check single candidates, while the `=` lines are a
+// snapshot of every candidate in order, which `update` can rewrite. A `run`
+// line makes the case run command kinds in the current directory, with the
+// COMPLETIST_* variables of dynamic mode, instead of listing them as kinds.
+pub struct Case {
+    pub line: usize,
+    pub command: String,
+    pub run: bool,
+    pub offers: Vec<(String, Option<String>)>,
+    pub omits: Vec<String>,
+    pub snapshot: Option<Vec<String>>,
+    // The index of the case's last line, which snapshots are written after.
+    last: usize,
+}
+
+// Candidates with a description are written `value  description`, and kinds
+// that the shell expands are written as their spec kind in angle brackets.
+fn split_candidate(line: &str) -> (String, Option<String>) {
+    match line.find("  ") {
+        Some(i) => (line[..i].to_string(), Some(line[i..].trim().to_string())),
+        None => (line.trim().to_string(), None),
+    }
+}
+
+pub fn parse_cases(text: &str) -> Result<Vec<Case>, String> {
+    let mut cases: Vec<Case> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if trimmed.starts_with("$ ") {
+            cases.push(Case {
+                line: i + 1,
+                command: trimmed[2..].trim().to_string(),
+                run: false,
+                offers: Vec::new(),
+                omits: Vec::new(),
+                snapshot: None,
+                last: i,
+            });
+            continue;
+        }
+
+        let case = match cases.last_mut() {
+            Some(case) => case,
+            None => return Err(format!("line {}: expected a `$ command` line", i + 1)),
+        };
+        case.last = i;
+        let (keyword, rest) = match trimmed.find(' ') {
+            Some(j) => (&trimmed[..j], trimmed[j + 1..].trim_start()),
+            None => (trimmed, ""),
+        };
+        match keyword {
+            "offers" => case.offers.push(split_candidate(rest)),
+            "omits" => case.omits.push(rest.trim().to_string()),
+            "run" if rest.is_empty() => case.run = true,
+            "=" => {
+                let snapshot = case.snapshot.get_or_insert(Vec::new());
+                if !rest.is_empty() {
+                    snapshot.push(rest.to_string());
+                }
+            },
+            _ => return Err(format!("line {}: unknown check `{}`", i + 1, keyword)),
+        }
+    }
+    Ok(cases)
+}
+
+// Completes the command line at its `<TAB>` marker.
+pub fn candidates(prog: &Program, command: &str, run: bool) -> Result<Vec<String>, String> {
+    let tab = match command.find("<TAB>") {
+        Some(tab) => tab,
+        None => return Err("there is no <TAB> to complete at".to_string()),
+    };
+    let before = &command[..tab];
+    let mut words = try!(shell::words(before));
+    let cursor = if before.is_empty() || before.ends_with(char::is_whitespace) {
+        words.push(String::new());
+        words.len() - 1
+    } else {
+        words.len() - 1
+    };
+    if cursor == 0 {
+        return Err("the program name can't be completed".to_string());
+    }
+
+    let completion = engine::complete(prog, &words, cursor);
+    let mut lines: Vec<String> = completion.candidates.iter()
+        .map(|c| if c.description.is_empty() {
+            c.value.clone()
+        } else {
+            format!("{}  {}", c.value, c.description)
+        })
+        .collect();
+    for kind in &completion.expand {
+        match **kind {
+            OptKind::Command(ref cmd) if run => lines.extend(dynamic::run_command(&completion, cmd).into_iter()
+                .filter(|value| value.starts_with(&completion.prefix[..]))),
+            _ => lines.push(format!("<{}>", kind_name(kind))),
+        }
+    }
+    Ok(lines)
+}
+
+// Describes everything about the candidates that the case didn't expect.
+pub fn check(prog: &Program, case: &Case) -> Vec<String> {
+    let lines = match candidates(prog, &case.command, case.run) {
+        Ok(lines) => lines,
+        Err(message) => return vec![message],
+    };
+    let actual: Vec<(String, Option<String>)> = lines.iter().map(|l| split_candidate(l)).collect();
+    let mut problems = Vec::new();
+
+    for &(ref value, ref description) in &case.offers {
+        match actual.iter().find(|&&(ref v, _)| v == value) {
+            None => problems.push(format!("`{}` is not offered", value)),
+            Some(&(_, ref actual)) if description.is_some() && actual != description => problems.push(format!(
+                "`{}` is offered with the description `{}`", value, actual.as_ref().map_or("", |d| &d[..]))),
+            Some(_) => {},
+        }
+    }
+    for value in &case.omits {
+        if actual.iter().any(|&(ref v, _)| v == value) {
+            problems.push(format!("`{}` is offered", value));
+        }
+    }
+
+    if let Some(ref snapshot) = case.snapshot {
+        for line in snapshot.iter().filter(|l| !lines.contains(l)) {
+            problems.push(format!("missing `{}`", line));
+        }
+        for line in lines.iter().filter(|l| !snapshot.contains(l)) {
+            problems.push(format!("unexpected `{}`", line));
+        }
+        if problems.is_empty() && *snapshot != lines {
+            problems.push("the candidates are in a different order".to_string());
+        }
+    }
+    problems
+}
+
+// Rewrites the `=` lines of every case that has a snapshot, or that has no
+// checks at all yet.
+pub fn update(prog: &Program, text: &str) -> Result<String, String> {
+    let cases = try!(parse_cases(text));
+    let mut snapshots = Vec::new();
+    for case in &cases {
+        if case.snapshot.is_some() || (case.offers.is_empty() && case.omits.is_empty()) {
+            let lines = try!(candidates(prog, &case.command, case.run)
+                .map_err(|message| format!("line {}: {}", case.line, message)));
+            snapshots.push((case.line - 1, case.last, lines));
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut current: Option<&(usize, usize, Vec<String>)> = None;
+    for (i, line) in text.lines().enumerate() {
+        if let Some(snapshot) = snapshots.iter().find(|&&(start, _, _)| start == i) {
+            current = Some(snapshot);
+        }
+        let trimmed = line.trim();
+        let in_case = current.map_or(false, |&(start, last, _)| i > start && i <= last);
+        if !(in_case && (trimmed == "=" || trimmed.starts_with("= "))) {
+            out.push(line.to_string());
+        }
+        if let Some(&(_, last, ref lines)) = current {
+            if i == last {
+                if lines.is_empty() {
+                    out.push("=".to_string());
+                }
+                out.extend(lines.iter().map(|l| format!("= {}", l)));
+                current = None;
+            }
+        }
+    }
+    let mut text = out.join("\n");
+    text.push('\n');
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    const SPEC: &'static str = "
+        name = 'svc'
+        [[option]]
+        long = '--verbose'
+        short = '-v'
+        description = 'say more'
+        [[command]]
+        name = 'start'
+        [[command.argument]]
+        name = 'MODE'
+        kind = 'choice(fast|slow)'
+        [[command.option]]
+        long = '--unit'
+        argkind = 'command(ls /etc/init.d)'
+        description = 'unit'
+    ";
+
+    const CASES: &'static str = "# svc behaviour
+
+$ svc --v<TAB>
+offers --verbose  say more
+omits -v
+
+$ svc start <TAB>
+= fast
+= slow
+
+$ svc start --unit <TAB>
+
+$ svc st<TAB>
+offers start  starts things
+offers stop
+=
+";
+
+    #[test]
+    fn run_cases() {
+        let prog = parse_program(SPEC).unwrap();
+        let cases = parse_cases(CASES).unwrap();
+        let lines: Vec<usize> = cases.iter().map(|c| c.line).collect();
+        assert_eq!(lines, vec![3, 7, 11, 13]);
+        assert_eq!(cases[1].snapshot, Some(strings(&["fast", "slow"])));
+
+        let problems: Vec<Vec<String>> = cases.iter().map(|c| check(&prog, c)).collect();
+        assert_eq!(problems, vec![
+            vec![],
+            vec![],
+            vec![],
+            strings(&["`start` is offered with the description ``", "`stop` is not offered", "unexpected `start`"]),
+        ]);
+        assert_eq!(candidates(&prog, "svc start --unit <TAB>", false).unwrap(), strings(&["<command(ls /etc/init.d)>"]));
+        assert!(candidates(&prog, "svc start", false).is_err());
+        assert!(parse_cases("offers x").is_err());
+        assert!(parse_cases("$ x<TAB>\nexpects y").is_err());
+    }
+
+    #[test]
+    fn run_commands() {
+        let prog = parse_program("
+            name = 'svc'
+            [[option]]
+            long = '--unit'
+            argkind = 'command(printf \"%s\\\\n\" \"$COMPLETIST_PREV\" nginx ntpd sshd)'
+            description = 'unit'
+        ").unwrap();
+        assert_eq!(candidates(&prog, "svc --unit n<TAB>", true).unwrap(), strings(&["nginx", "ntpd"]));
+        let cases = parse_cases("$ svc --unit <TAB>\nrun\noffers --unit\noffers sshd\nomits <command(x)>\n").unwrap();
+        assert!(cases[0].run);
+        assert_eq!(check(&prog, &cases[0]), Vec::<String>::new());
+        assert!(parse_cases("$ svc <TAB>\nrun now").is_err());
+    }
+
+    #[test]
+    fn update_snapshots() {
+        let prog = parse_program(SPEC).unwrap();
+        let updated = update(&prog, &CASES.replace("= slow\n", "= medium\n")).unwrap();
+        assert_eq!(updated, CASES
+            .replace("$ svc start --unit <TAB>\n", "$ svc start --unit <TAB>\n= <command(ls /etc/init.d)>\n")
+            .replace("offers stop\n=\n", "offers stop\n= start\n"));
+    }
+
+    #[test]
+    fn sample_cases() {
+        for &(spec, cases) in &[
+            (include_str!("../../samples/cat.ct"), include_str!("../../samples/cat.cases")),
+            (include_str!("../../samples/cargo.ct"), include_str!("../../samples/cargo.cases")),
+        ] {
+            let prog = parse_program(spec).unwrap();
+            // Cases that run commands need a project to run them in, which
+            // tests/shells.rs sets up.
+            for case in parse_cases(cases).unwrap().into_iter().filter(|c| !c.run) {
+                assert_eq!(check(&prog, &case), Vec::<String>::new(), "line {}", case.line);
+            }
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use std::env;
+use std::fs;
+use std::io::{stderr, Read};
+use std::path::Path;
+
+use completist::{parse_program, CompletistError};
+use completist::io::{capture, open_input, open_output, Write};
+use completist::program::{Program, Command};
+use completist::engine;
+use completist::argv;
+use completist::lint;
+use completist::cases;
+use completist::dynamic::{self, Shell};
+use completist::backends;
+use completist::importers;
+use completist::help;
+use completist::usage::{self, Style};
+
+pub const USAGE: &'static str = "usage:
+    completist generate <spec> [--format <format>] [--output <path>]
+    completist complete <spec> [--shell <shell>] [--cursor <n>] -- <words>...
+    completist shim <spec> --shell <shell>
+    completist man <spec> [--output-dir <dir>] [--split]
+    completist usage <spec> [<command>...] [--style <gnu|docopt|clap>] [--width <n>]
+    completist help <spec> [<command>...] [--width <n>]
+    completist import <format> <input> [--name <name>] [--output <path>]
+    completist parse <spec> -- <args>...
+    completist check-args <spec> -- <args>...
+    completist check-args <spec> --docs <markdown>...
+    completist test <spec> [<cases>] [--update]";
+
+pub struct Args {
+    pub positionals: Vec<String>,
+    pub flags: Vec<(String, String)>,
+    pub switches: Vec<String>,
+    pub rest: Vec<String>,
+}
+
+impl Args {
+    pub fn parse(args: &[String], flags: &[&str], switches: &[&str]) -> Result<Self, CompletistError> {
+        let mut parsed = Args {
+            positionals: Vec::new(),
+            flags: Vec::new(),
+            switches: Vec::new(),
+            rest: Vec::new(),
+        };
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if arg == "--" {
+                parsed.rest.extend(iter.cloned());
+                break;
+            } else if flags.contains(&&arg[..]) {
+                let value = try!(iter.next().ok_or_else(|| usage_error(&format!("{} expects a value", arg))));
+                parsed.flags.push((arg.clone(), value.clone()));
+            } else if switches.contains(&&arg[..]) {
+                parsed.switches.push(arg.clone());
+            } else if arg.starts_with("--") {
+                return Err(usage_error(&format!("unknown flag {}", arg)));
+            } else {
+                parsed.positionals.push(arg.clone());
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn flag(&self, name: &str) -> Option<&str> {
+        self.flags.iter().rev()
+            .find(|&&(ref flag, _)| flag == name)
+            .map(|&(_, ref value)| &value[..])
+    }
+
+    pub fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|switch| switch == name)
+    }
+
+    pub fn positional(&self, index: usize, name: &str) -> Result<&str, CompletistError> {
+        self.positionals.get(index)
+            .map(|p| &p[..])
+            .ok_or_else(|| usage_error(&format!("missing {}", name)))
+    }
+}
+
+pub fn usage_error(message: &str) -> CompletistError {
+    CompletistError::UsageError(format!("{}\n{}", message, USAGE))
+}
+
+pub fn read_program(path: &str) -> Result<Program, CompletistError> {
+    let mut input = try!(open_input(path));
+    let mut spec = String::new();
+    try!(input.read_to_string(&mut spec));
+    parse_program(&spec)
+}
+
+// Defaults to the terminal width that shells export as COLUMNS.
+fn width_flag(args: &Args) -> Result<usize, CompletistError> {
+    match args.flag("--width") {
+        Some(width) => width.parse::<usize>()
+            .map_err(|_| usage_error(&format!("invalid width {}", width)))
+            .and_then(|width| if width < 20 { Err(usage_error("width must be at least 20")) } else { Ok(width) }),
+        None => Ok(env::var("COLUMNS").ok()
+            .and_then(|columns| columns.parse::<usize>().ok())
+            .map(|columns| if columns < 20 { 20 } else { columns })
+            .unwrap_or(80)),
+    }
+}
+
+fn shell_flag(args: &Args, default: Shell) -> Result<Shell, CompletistError> {
+    match args.flag("--shell") {
+        Some(name) => Shell::from_name(name)
+            .ok_or_else(|| usage_error(&format!("unsupported shell {}", name))),
+        None => Ok(default),
+    }
+}
+
+pub fn run(args: &[String]) -> Result<(), CompletistError> {
+    match args.first().map(|a| &a[..]) {
+        Some("generate") => generate(&args[1..]),
+        Some("complete") => complete(&args[1..]),
+        Some("shim") => shim(&args[1..]),
+        Some("man") => man(&args[1..]),
+        Some("usage") => usage(&args[1..]),
+        Some("help") => help(&args[1..]),
+        Some("import") => import(&args[1..]),
+        Some("parse") => parse(&args[1..]),
+        Some("check-args") => check_args(&args[1..]),
+        Some("test") => test(&args[1..]),
+        Some(command) => Err(usage_error(&format!("unknown command {}", command))),
+        None => Err(usage_error("missing command")),
+    }
+}
+
+fn generate(args: &[String]) -> Result<(), CompletistError> {
+    let args = try!(Args::parse(args, &["--format", "--output"], &[]));
+    let prog = try!(read_program(try!(args.positional(0, "spec"))));
+    let output = args.flag("--output").unwrap_or("--");
+    let formatter = match args.flag("--format") {
+        Some(name) => try!(backends::find_formatter(name)
+            .ok_or_else(|| usage_error(&format!("unknown format {}", name)))),
+        None => try!(backends::formatter_for_path(output)
+            .ok_or_else(|| usage_error("cannot guess the format, use --format"))),
+    };
+
+    let mut out = try!(open_output(output));
+    try!(formatter.write_program(&mut out, &prog));
+    try!(out.flush());
+
+    for warning in formatter.warnings(&prog) {
+        try!(writeln!(stderr(), "completist: warning: {}", warning));
+    }
+    Ok(())
+}
+
+fn complete(args: &[String]) -> Result<(), CompletistError> {
+    let args = try!(Args::parse(args, &["--shell", "--cursor"], &[]));
+    let prog = try!(read_program(try!(args.positional(0, "spec"))));
+    let shell = try!(shell_flag(&args, Shell::Plain));
+    let cursor = match args.flag("--cursor") {
+        Some(cursor) => try!(cursor.parse::<usize>()
+            .map_err(|_| usage_error(&format!("invalid cursor {}", cursor)))),
+        None => if args.rest.is_empty() { 0 } else { args.rest.len() - 1 },
+    };
+
+    let completion = engine::complete(&prog, &args.rest, cursor);
+    let candidates = dynamic::expand(&completion, shell);
+
+    let mut out = try!(open_output("--"));
+    try!(dynamic::write_candidates(&mut out, shell, &candidates));
+    try!(out.flush());
+    Ok(())
+}
+
+fn shim(args: &[String]) -> Result<(), CompletistError> {
+    let args = try!(Args::parse(args, &["--shell"], &[]));
+    let spec = try!(args.positional(0, "spec"));
+    let prog = try!(read_program(spec));
+    let shell = try!(shell_flag(&args, Shell::Plain));
+    if shell == Shell::Plain {
+        return Err(usage_error("shim needs a --shell"));
+    }
+
+    let mut out = try!(open_output("--"));
+    try!(dynamic::write_shim(&mut out, shell, &prog, &try!(absolute_spec(spec))));
+    try!(out.flush());
+    Ok(())
+}
+
+// The shim runs wherever the user completes, so it needs the spec's full path.
+fn absolute_spec(spec: &str) -> Result<String, CompletistError> {
+    if spec == "--" {
+        return Err(usage_error("shim needs a spec file rather than standard input"));
+    }
+    Ok(try!(fs::canonicalize(spec)).to_string_lossy().into_owned())
+}
+
+fn man(args: &[String]) -> Result<(), CompletistError> {
+    let args = try!(Args::parse(args, &["--output-dir"], &["--split"]));
+    let prog = try!(read_program(try!(args.positional(0, "spec"))));
+    let dir = Path::new(args.flag("--output-dir").unwrap_or("."));
+
+    let pages = if args.switch("--split") {
+        try!(backends::man::pages(&prog))
+    } else {
+        let page = try!(capture(|out| backends::man::write_program(out, &prog)));
+        vec![(format!("{}.{}", prog.name, prog.meta.section), page)]
+    };
+    for (name, page) in pages {
+        let mut out = try!(open_output(&dir.join(name).to_string_lossy()));
+        try!(out.write_all(page.as_bytes()));
+        try!(out.flush());
+    }
+    Ok(())
+}
+
+fn find_command<'a>(prog: &'a Program, names: &[String]) -> Result<&'a Command, CompletistError> {
+    prog.find(names).ok_or_else(|| usage_error(&format!("unknown command {}", names.join(" "))))
+}
+
+fn usage(args: &[String]) -> Result<(), CompletistError> {
+    let args = try!(Args::parse(args, &["--style", "--width"], &[]));
+    let prog = try!(read_program(try!(args.positional(0, "spec"))));
+    let names = &args.positionals[1..];
+    let command = try!(find_command(&prog, names));
+    let style = match args.flag("--style") {
+        Some(name) => try!(Style::from_name(name)
+            .ok_or_else(|| usage_error(&format!("unknown style {}", name)))),
+        None => Style::Gnu,
+    };
+    let width = try!(width_flag(&args));
+
+    let mut path = vec![&prog.name[..]];
+    path.extend(names.iter().map(|n| &n[..]));
+    let mut out = try!(open_output("--"));
+    try!(out.write_fmt(format_args!("{}\n", usage::usage(&path, command, style, width))));
+    try!(out.flush());
+    Ok(())
+}
+
+fn help(args: &[String]) -> Result<(), CompletistError> {
+    let args = try!(Args::parse(args, &["--width"], &[]));
+    let prog = try!(read_program(try!(args.positional(0, "spec"))));
+    let names = &args.positionals[1..];
+    let command = try!(find_command(&prog, names));
+    let width = try!(width_flag(&args));
+
+    let mut path = vec![&prog.name[..]];
+    path.extend(names.iter().map(|n| &n[..]));
+    let mut out = try!(open_output("--"));
+    try!(out.write_all(help::help(&path, command, width).as_bytes()));
+    try!(out.flush());
+    Ok(())
+}
+
+fn import(args: &[String]) -> Result<(), CompletistError> {
+    let args = try!(Args::parse(args, &["--name", "--output"], &[]));
+    let format = try!(args.positional(0, "format"));
+    let importer = try!(importers::find_importer(format)
+        .ok_or_else(|| usage_error(&format!("unknown import format {}", format))));
+    let mut input = try!(open_input(try!(args.positional(1, "input"))));
+    let mut text = String::new();
+    try!(input.read_to_string(&mut text));
+
+    let import = try!(importer(&text, args.flag("--name")));
+    let mut out = try!(open_output(args.flag("--output").unwrap_or("--")));
+    try!(backends::ct::write_program(&mut out, &import.program));
+    try!(out.flush());
+
+    for item in import.skipped {
+        try!(writeln!(stderr(), "completist: skipped: {}", item));
+    }
+    Ok(())
+}
+
+// Prints the result as JSON either way, so that wrapper scripts only need to
+// check the exit status before reading it.
+fn parse(args: &[String]) -> Result<(), CompletistError> {
+    let args = try!(Args::parse(args, &[], &[]));
+    let prog = try!(read_program(try!(args.positional(0, "spec"))));
+    let result = argv::parse(&prog, &args.rest);
+
+    let mut out = try!(open_output("--"));
+    match result {
+        Ok(ref matches) => try!(writeln!(out, "{}", matches.to_json())),
+        Err(ref failure) => try!(writeln!(out, "{}", failure.to_json())),
+    }
+    try!(out.flush());
+    result.map(|_| ()).map_err(|failure| CompletistError::ArgvError(failure.error))
+}
+
+// With --docs, the positionals after the spec are markdown files whose shell
+// code blocks are checked instead.
+fn check_args(args: &[String]) -> Result<(), CompletistError> {
+    let args = try!(Args::parse(args, &[], &["--docs"]));
+    let prog = try!(read_program(try!(args.positional(0, "spec"))));
+    if !args.switch("--docs") {
+        return argv::parse(&prog, &args.rest)
+            .map(|_| ())
+            .map_err(|failure| CompletistError::ArgvError(failure.error));
+    }
+
+    try!(args.positional(1, "markdown"));
+    let mut invalid = 0;
+    let mut out = try!(open_output("--"));
+    for path in &args.positionals[1..] {
+        let mut input = try!(open_input(path));
+        let mut text = String::new();
+        try!(input.read_to_string(&mut text));
+
+        for (line, words, error) in lint::check_markdown(&prog, &text) {
+            try!(writeln!(out, "{}:{}: `{} {}`: {}", path, line, prog.name, words.join(" "), error));
+            invalid += 1;
+        }
+    }
+    try!(out.flush());
+
+    if invalid > 0 {
+        return Err(CompletistError::InvalidExamples(invalid));
+    }
+    Ok(())
+}
+
+// The cases default to the file next to the spec, `cat.ct` -> `cat.cases`.
+// With --update, snapshots are rewritten instead of checked.
+fn test(args: &[String]) -> Result<(), CompletistError> {
+    let args = try!(Args::parse(args, &[], &["--update"]));
+    let spec = try!(args.positional(0, "spec"));
+    let prog = try!(read_program(spec));
+    let path = match args.positionals.get(1) {
+        Some(path) => path.clone(),
+        None => Path::new(spec).with_extension("cases").to_string_lossy().into_owned(),
+    };
+    let mut input = try!(open_input(&path));
+    let mut text = String::new();
+    try!(input.read_to_string(&mut text));
+
+    if args.switch("--update") {
+        text = try!(cases::update(&prog, &text)
+            .map_err(|message| usage_error(&format!("{}: {}", path, message))));
+        let mut out = try!(open_output(&path));
+        try!(out.write_all(text.as_bytes()));
+        try!(out.flush());
+    }
+
+    let all = try!(cases::parse_cases(&text)
+        .map_err(|message| usage_error(&format!("{}: {}", path, message))));
+    let mut failed = 0;
+    let mut out = try!(open_output("--"));
+    for case in &all {
+        let problems = cases::check(&prog, case);
+        for problem in &problems {
+            try!(writeln!(out, "{}:{}: `{}`: {}", path, case.line, case.command, problem));
+        }
+        if !problems.is_empty() {
+            failed += 1;
+        }
+    }
+    try!(out.flush());
+
+    if failed > 0 {
+        return Err(CompletistError::FailedCases(failed, all.len()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args() {
+        let args = Args::parse(&strings(&["spec.ct", "--shell", "fish", "--split", "--", "cat", "-"]),
+            &["--shell", "--cursor"], &["--split"]).unwrap();
+        assert_eq!(args.positionals, vec!["spec.ct"]);
+        assert_eq!(args.flag("--shell"), Some("fish"));
+        assert_eq!(args.flag("--cursor"), None);
+        assert!(args.switch("--split"));
+        assert_eq!(args.rest, vec!["cat", "-"]);
+    }
+
+    #[test]
+    fn parse_args_errors() {
+        assert!(Args::parse(&strings(&["--shell"]), &["--shell"], &[]).is_err());
+        assert!(Args::parse(&strings(&["--unknown"]), &["--shell"], &[]).is_err());
+        assert!(Args::parse(&strings(&["spec.ct"]), &[], &[]).unwrap().positional(1, "x").is_err());
+    }
+
+    #[test]
+    fn shims_use_absolute_specs() {
+        let spec = absolute_spec("samples/../samples/cat.ct").unwrap();
+        assert_eq!(spec, env::current_dir().unwrap().join("samples").join("cat.ct").to_string_lossy());
+        assert!(absolute_spec("--").is_err());
+        assert!(absolute_spec("samples/missing.ct").is_err());
+    }
+
+    #[test]
+    fn unknown_commands() {
+        assert!(run(&strings(&[])).is_err());
+        assert!(run(&strings(&["frobnicate"])).is_err());
+    }
+}
@@ -0,0 +1,403 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+use completist::engine::{Candidate, CandidateKind, Completion};
+use completist::io::{Output, Write};
+use completist::program::{Program, OptKind, Cache};
+use completist::formatter::FmtResult;
+use completist::utils::fnv1a;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Shell {
+    Fish,
+    Bash,
+    Zsh,
+    Plain,
+}
+
+impl Shell {
+    pub fn from_name(name: &str) -> Option<Shell> {
+        match name {
+            "fish" => Some(Shell::Fish),
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "plain" => Some(Shell::Plain),
+            _ => None,
+        }
+    }
+
+    fn executable(&self) -> &'static str {
+        match *self {
+            Shell::Fish => "fish",
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Plain => "sh",
+        }
+    }
+}
+
+pub fn expand(completion: &Completion, shell: Shell) -> Vec<Candidate> {
+    let mut candidates = completion.candidates.clone();
+    let vars = completion.variables();
+    for kind in &completion.expand {
+        let values = match **kind {
+            OptKind::File | OptKind::FilePlus => list_files(&completion.prefix),
+            OptKind::Command(ref cmd) => match (completion.cache, cache_dir()) {
+                (Some(cache), Some(dir)) => cached_lines(&dir, cmd, cache, &vars),
+                _ => run_lines("sh", cmd, &vars).0,
+            },
+            OptKind::Function(ref func) => run_lines(shell.executable(), func, &vars).0,
+            OptKind::Choices(ref choices) => choices.clone(),
+        };
+
+        for value in values {
+            if value.starts_with(&completion.prefix[..]) {
+                candidates.push(Candidate {
+                    value: value,
+                    description: String::new(),
+                    kind: CandidateKind::Value,
+                });
+            }
+        }
+    }
+
+    candidates
+}
+
+fn list_files(prefix: &str) -> Vec<String> {
+    let (dir, base) = match prefix.rfind('/') {
+        Some(i) => prefix.split_at(i + 1),
+        None => ("", prefix),
+    };
+
+    let entries = match fs::read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut files = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(".") && !base.starts_with(".") {
+            continue;
+        }
+
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        files.push(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }));
+    }
+
+    files.sort();
+    files
+}
+
+// Runs a command kind with the COMPLETIST_* variables, as `expand` does, but
+// without a cache.
+pub fn run_command(completion: &Completion, cmd: &str) -> Vec<String> {
+    run_lines("sh", cmd, &completion.variables()).0
+}
+
+// The lines a script prints, and whether it succeeded.
+fn run_lines(shell: &str, script: &str, vars: &[(String, String)]) -> (Vec<String>, bool) {
+    let mut command = process::Command::new(shell);
+    command.arg("-c").arg(script);
+    for &(ref name, ref value) in vars {
+        command.env(name, value);
+    }
+
+    match command.output() {
+        Ok(output) => (String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.to_string())
+            .collect(), output.status.success()),
+        Err(_) => (Vec::new(), false),
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|dir| dir.join("completist"))
+}
+
+// Every variable the command can see is part of the key, since its output
+// may depend on any of them
+fn cache_key(cmd: &str, cache: &Cache, vars: &[(String, String)]) -> String {
+    let mut key = cmd.to_string();
+    for &(ref name, ref value) in vars {
+        key = format!("{}\0{}={}", key, name, value);
+    }
+    if cache.per_directory {
+        if let Ok(cwd) = env::current_dir() {
+            key = format!("{}\0{}", cwd.display(), key);
+        }
+    }
+
+    format!("{:016x}", fnv1a(key.as_bytes()))
+}
+
+fn is_fresh(path: &Path, cache: &Cache) -> bool {
+    let modified = match fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return false,
+    };
+
+    let age = SystemTime::now().duration_since(modified)
+        .map(|age| age.as_secs())
+        .unwrap_or(0);
+    if age >= cache.ttl {
+        return false;
+    }
+
+    cache.watch.iter().all(|watched| fs::metadata(watched)
+        .and_then(|m| m.modified())
+        .map(|m| m <= modified)
+        .unwrap_or(true))
+}
+
+fn cached_lines(dir: &Path, cmd: &str, cache: &Cache, vars: &[(String, String)]) -> Vec<String> {
+    let path = dir.join(cache_key(cmd, cache, vars));
+    if is_fresh(&path, cache) {
+        let mut contents = String::new();
+        if File::open(&path).and_then(|mut f| f.read_to_string(&mut contents)).is_ok() {
+            return contents.lines().map(|line| line.to_string()).collect();
+        }
+    }
+
+    // A failed command, say one that can't reach its server, is run again
+    // next time rather than serving what little it printed.
+    let (lines, success) = run_lines("sh", cmd, vars);
+    if success {
+        fs::create_dir_all(dir)
+            .and_then(|_| File::create(&path))
+            .and_then(|mut f| f.write_all(lines.join("\n").as_bytes()))
+            .ok();
+    }
+    lines
+}
+
+pub fn write_candidates(out: &mut Output, shell: Shell, candidates: &[Candidate]) -> FmtResult {
+    for candidate in candidates {
+        match shell {
+            Shell::Fish if !candidate.description.is_empty() =>
+                try!(out.write_fmt(format_args!("{}\t{}\n",
+                    candidate.value, candidate.description))),
+            Shell::Zsh =>
+                try!(out.write_fmt(format_args!("{}:{}\n",
+                    candidate.value.replace(":", "\\:"), candidate.description))),
+            _ =>
+                try!(out.write_fmt(format_args!("{}\n", candidate.value))),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn write_shim(out: &mut Output, shell: Shell, prog: &Program, spec: &str) -> FmtResult {
+    let spec = spec.replace("'", "'\\''");
+    match shell {
+        Shell::Fish => try!(out.write_fmt(format_args!(
+"# function kinds run in a new `fish -c`, which doesn't see this session's
+# functions, only the ones that fish's configuration defines
+function __completist_{name}
+    set -l tokens (commandline -opc) (commandline -ct)
+    completist complete '{spec}' --shell fish --cursor (math (count $tokens) - 1) -- $tokens
+end
+complete -c '{name}' -f -a '(__completist_{name})'
+", name = prog.name, spec = spec))),
+        Shell::Bash => try!(out.write_fmt(format_args!(
+"# function kinds run in a new `bash -c`, which doesn't see this session's
+# functions, only the ones that $BASH_ENV defines
+_completist_{ident}() {{
+    local IFS=$'\\n' line=\"${{COMP_LINE:0:COMP_POINT}}\" words
+    # COMP_WORDS is split at COMP_WORDBREAKS too, so `--opt=value` would be
+    # three words
+    IFS=$' \\t' read -ra words <<< \"$line\"
+    [[ $line == *[[:space:]] ]] && words+=('')
+    COMPREPLY=($(completist complete '{spec}' --shell bash --cursor $((${{#words[@]}} - 1)) -- \"${{words[@]}}\"))
+    # bash only replaces the part of the word after its last break
+    local before=\"${{words[${{#words[@]}} - 1]%\"${{COMP_WORDS[COMP_CWORD]}}\"}}\"
+    COMPREPLY=(\"${{COMPREPLY[@]#\"$before\"}}\")
+}}
+complete -o filenames -F _completist_{ident} '{name}'
+", ident = prog.name.replace("-", "_"), name = prog.name, spec = spec))),
+        Shell::Zsh => try!(out.write_fmt(format_args!(
+"#compdef {name}
+# function kinds run in a new `zsh -c`, which doesn't see this session's
+# functions, only the ones that ~/.zshenv defines
+_completist_{ident}() {{
+    local -a candidates
+    candidates=(\"${{(@f)$(completist complete '{spec}' --shell zsh --cursor $((CURRENT - 1)) -- \"${{words[@]}}\")}}\")
+    _describe 'values' candidates
+}}
+compdef _completist_{ident} '{name}'
+", ident = prog.name.replace("-", "_"), name = prog.name, spec = spec))),
+        Shell::Plain => {},
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate toml;
+    use super::*;
+    use completist::engine::{complete, Candidate, CandidateKind};
+    use completist::io::capture;
+    use completist::program::{Program, Cache};
+    use std::env;
+    use std::fs::{self, File};
+    use std::process;
+
+    fn program(spec: &str) -> Program {
+        let toml = toml::Parser::new(spec).parse().unwrap();
+        Program::from_toml(&toml).unwrap()
+    }
+
+    fn words(line: &str) -> Vec<String> {
+        line.split(' ').map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn shell_names() {
+        assert_eq!(Shell::from_name("fish"), Some(Shell::Fish));
+        assert_eq!(Shell::from_name("zsh"), Some(Shell::Zsh));
+        assert_eq!(Shell::from_name("csh"), None);
+    }
+
+    #[test]
+    fn expand_commands() {
+        let prog = program("
+            name = 'prog'
+            [[argument]]
+            name = 'BRANCH'
+            kind = 'command(echo main; echo master; echo dev)'
+        ");
+        let completion = complete(&prog, &words("prog ma"), 1);
+        let values: Vec<String> = expand(&completion, Shell::Plain)
+            .into_iter().map(|c| c.value).collect();
+        assert_eq!(values, vec!["main", "master"]);
+    }
+
+    #[test]
+    fn cache_command_output() {
+        let dir = env::temp_dir().join(format!("completist-test-{}", process::id()));
+        let mut cache = Cache { ttl: 3600, per_directory: true, watch: vec![] };
+        let vars = vec![];
+
+        assert_eq!(cached_lines(&dir, "echo fresh", &cache, &vars), vec!["fresh"]);
+        let path = dir.join(cache_key("echo fresh", &cache, &vars));
+        File::create(&path).and_then(|mut f| f.write_all(b"cached")).unwrap();
+        assert_eq!(cached_lines(&dir, "echo fresh", &cache, &vars), vec!["cached"]);
+
+        cache.per_directory = false;
+        assert_eq!(cached_lines(&dir, "echo fresh", &cache, &vars), vec!["fresh"]);
+
+        let vars = vec![("COMPLETIST_OPT_X".to_string(), "y".to_string())];
+        assert!(cache_key("echo fresh", &cache, &vars) != cache_key("echo fresh", &cache, &[]));
+        let prev = |word: &str| vec![("COMPLETIST_PREV".to_string(), word.to_string())];
+        assert!(cache_key("echo fresh", &cache, &prev("origin")) != cache_key("echo fresh", &cache, &prev("upstream")));
+
+        cache.ttl = 0;
+        File::create(&path).and_then(|mut f| f.write_all(b"cached")).unwrap();
+        cache.per_directory = true;
+        assert_eq!(cached_lines(&dir, "echo fresh", &cache, &[]), vec!["fresh"]);
+
+        cache.ttl = 3600;
+        assert_eq!(cached_lines(&dir, "echo partial; exit 1", &cache, &[]), vec!["partial"]);
+        assert!(!dir.join(cache_key("echo partial; exit 1", &cache, &[])).exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn commands_see_context() {
+        let prog = program("
+            name = 'prog'
+            [[option]]
+            long = '--manifest-path'
+            argkind = 'file'
+            description = 'manifest'
+            [[argument]]
+            name = 'BIN'
+            kind = 'command(echo $COMPLETIST_OPT_MANIFEST_PATH-$COMPLETIST_PREV-$COMPLETIST_CURRENT)'
+        ");
+        let completion = complete(&prog, &words("prog --manifest-path a/Cargo.toml "), 3);
+        let values: Vec<String> = expand(&completion, Shell::Plain)
+            .into_iter().map(|c| c.value).collect();
+        assert_eq!(values, vec!["a/Cargo.toml-a/Cargo.toml-"]);
+    }
+
+    #[test]
+    fn expand_files() {
+        let prog = program("
+            name = 'prog'
+            [[argument]]
+            name = 'FILE'
+            kind = 'file'
+        ");
+        let completion = complete(&prog, &words("prog src/ma"), 1);
+        let values: Vec<String> = expand(&completion, Shell::Plain)
+            .into_iter().map(|c| c.value).collect();
+        assert_eq!(values, vec!["src/main.rs"]);
+    }
+
+    #[test]
+    fn shims_call_back_into_completist() {
+        let prog = program("name = 'my-prog'");
+        let fish = capture(|out| write_shim(out, Shell::Fish, &prog, "my-prog.ct")).unwrap();
+        assert!(fish.contains("completist complete 'my-prog.ct' --shell fish"));
+        assert!(fish.contains("complete -c 'my-prog' -f -a '(__completist_my-prog)'"));
+
+        let bash = capture(|out| write_shim(out, Shell::Bash, &prog, "it's.ct")).unwrap();
+        assert!(bash.contains("completist complete 'it'\\''s.ct' --shell bash"));
+        assert!(bash.contains("complete -o filenames -F _completist_my_prog 'my-prog'"));
+    }
+
+    #[test]
+    fn bash_shim_splits_on_whitespace() {
+        let prog = program("name = 'my-prog'");
+        let shim = capture(|out| write_shim(out, Shell::Bash, &prog, "my-prog.ct")).unwrap();
+        // Stands in for completist, replying with the cursor and the words.
+        let script = format!("{}
+            completist() {{ echo \"$6\"; shift 7; printf '%s\\n' \"$@\"; }}
+            COMP_LINE='my-prog --opt=a b:c'
+            COMP_POINT=${{#COMP_LINE}}
+            COMP_WORDS=(my-prog --opt = a b : c)
+            COMP_CWORD=6
+            _completist_my_prog
+            printf '%s\\n' \"${{COMPREPLY[@]}}\"
+        ", shim);
+        let output = match process::Command::new("bash").arg("-c").arg(&script).output() {
+            Ok(output) => output,
+            Err(_) => return,
+        };
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2\nmy-prog\n--opt=a\nc\n");
+    }
+
+    #[test]
+    fn candidate_formats() {
+        let candidates = vec![
+            Candidate {
+                value: "--a:b".to_string(),
+                description: "desc".to_string(),
+                kind: CandidateKind::Opt,
+            },
+        ];
+
+        let expected = vec![
+            (Shell::Fish, "--a:b\tdesc\n"),
+            (Shell::Bash, "--a:b\n"),
+            (Shell::Zsh, "--a\\:b:desc\n"),
+        ];
+        for (shell, text) in expected {
+            let out = capture(|out| write_candidates(out, shell, &candidates)).unwrap();
+            assert_eq!(out, text);
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use completist::program::{Program, Command, Argument, Opt, OptKind, Cache};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CandidateKind {
+    Command,
+    Opt,
+    Value,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Candidate {
+    pub value: String,
+    pub description: String,
+    pub kind: CandidateKind,
+}
+
+impl Candidate {
+    fn new(value: &str, description: &str, kind: CandidateKind) -> Self {
+        Candidate {
+            value: value.to_string(),
+            description: description.to_string(),
+            kind: kind,
+        }
+    }
+}
+
+pub struct State<'a> {
+    pub command: &'a Command,
+    pub path: Vec<String>,
+    pub used: Vec<&'a Opt>,
+    pub values: Vec<(String, String)>,
+    pub positionals: Vec<String>,
+    pub words: Vec<String>,
+    pub separated: bool,
+    pub pending: Option<&'a Opt>,
+}
+
+impl<'a> State<'a> {
+    pub fn new(prog: &'a Program) -> Self {
+        State {
+            command: &prog.base_command,
+            path: Vec::new(),
+            used: Vec::new(),
+            values: Vec::new(),
+            positionals: Vec::new(),
+            words: Vec::new(),
+            separated: false,
+            pending: None,
+        }
+    }
+
+    pub fn feed(&mut self, word: &str) {
+        self.words.push(word.to_string());
+
+        if let Some(opt) = self.pending.take() {
+            self.values.push((opt.name(), word.to_string()));
+        } else if self.separated || word == "-" || !word.starts_with("-") {
+            self.feed_positional(word);
+        } else if word == "--" {
+            self.separated = true;
+        } else if let Some(opt) = self.find_long(word) {
+            self.use_opt(opt, None);
+        } else if word.starts_with("--") && word.contains("=") {
+            let (long, value) = word.split_at(word.find("=").unwrap());
+            if let Some(opt) = self.find_long(long) {
+                self.use_opt(opt, Some(&value[1..]));
+            }
+        } else if !word.starts_with("--") {
+            self.feed_shorts(&word[1..]);
+        }
+    }
+
+    fn feed_positional(&mut self, word: &str) {
+        if !self.separated && self.positionals.is_empty() {
+            let command = self.command;
+            if let Some(sub) = command.commands.iter().find(|c| c.name == word) {
+                self.command = sub;
+                self.path.push(word.to_string());
+                self.used.clear();
+                return;
+            }
+        }
+
+        self.positionals.push(word.to_string());
+    }
+
+    fn feed_shorts(&mut self, cluster: &str) {
+        for (i, c) in cluster.char_indices() {
+            let opt = match self.find_short(c) {
+                Some(opt) => opt,
+                None => continue,
+            };
+
+            let rest = &cluster[i + c.len_utf8()..];
+            if opt.argkind.is_some() && rest.len() > 0 {
+                self.use_opt(opt, Some(rest));
+                return;
+            }
+
+            self.use_opt(opt, None);
+            if opt.argkind.is_some() {
+                return;
+            }
+        }
+    }
+
+    fn use_opt(&mut self, opt: &'a Opt, value: Option<&str>) {
+        self.used.push(opt);
+        if opt.argkind.is_some() {
+            match value {
+                Some(value) => self.values.push((opt.name(), value.to_string())),
+                None => self.pending = Some(opt),
+            }
+        }
+    }
+
+    fn find_long(&self, long: &str) -> Option<&'a Opt> {
+        let command = self.command;
+        command.options.iter().find(|opt| opt.longs.iter().any(|l| l == long))
+    }
+
+    fn find_short(&self, short: char) -> Option<&'a Opt> {
+        let command = self.command;
+        command.options.iter().find(|opt| opt.shorts.iter()
+            .any(|s| s.len() == 1 + short.len_utf8() && s.ends_with(short)))
+    }
+
+    pub fn is_used(&self, opt: &Opt) -> bool {
+        self.used.iter().any(|used| *used as *const Opt == opt as *const Opt)
+    }
+
+    // Whether an option given earlier excludes `opt`, or the other way round.
+    pub fn is_excluded(&self, opt: &Opt) -> bool {
+        let named = |o: &Opt, names: &[String]| o.longs.iter().chain(o.shorts.iter()).any(|n| names.contains(n));
+        self.used.iter().any(|used| named(opt, &used.excludes) || named(used, &opt.excludes))
+    }
+
+    pub fn value_of(&self, name: &str) -> Option<&str> {
+        self.values.iter().rev()
+            .find(|&&(ref opt, _)| opt == name)
+            .map(|&(_, ref value)| &value[..])
+    }
+
+    pub fn argument(&self) -> Option<&'a Argument> {
+        let index = self.positionals.len();
+        let command = self.command;
+        command.arguments.iter().enumerate()
+            .find(|&(i, arg)| i == index || (i < index && arg.kind == OptKind::FilePlus))
+            .map(|(_, arg)| arg)
+    }
+}
+
+// Command and function kinds are run with these variables exported, in every
+// backend:
+//
+//   COMPLETIST_CURRENT   the word being completed
+//   COMPLETIST_PREV      the word before it
+//   COMPLETIST_WORDS     every word before it, one per line
+//   COMPLETIST_OPT_<OPT> the value given to an option earlier on the command
+//                        line, named after `Opt::name` in upper case with
+//                        punctuation replaced by `_` (`--manifest-path` is
+//                        COMPLETIST_OPT_MANIFEST_PATH)
+pub fn variable_name(opt: &str) -> String {
+    let name: String = opt.chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    format!("COMPLETIST_OPT_{}", name)
+}
+
+pub struct Completion<'a> {
+    pub prefix: String,
+    pub candidates: Vec<Candidate>,
+    pub expand: Vec<&'a OptKind>,
+    pub cache: Option<&'a Cache>,
+    pub state: State<'a>,
+}
+
+impl<'a> Completion<'a> {
+    fn new(state: State<'a>, prefix: &str) -> Self {
+        Completion {
+            prefix: prefix.to_string(),
+            candidates: Vec::new(),
+            expand: Vec::new(),
+            cache: None,
+            state: state,
+        }
+    }
+
+    pub fn variables(&self) -> Vec<(String, String)> {
+        let mut vars = vec![
+            ("COMPLETIST_CURRENT".to_string(), self.prefix.clone()),
+            ("COMPLETIST_PREV".to_string(), self.state.words.last().cloned().unwrap_or_else(String::new)),
+            ("COMPLETIST_WORDS".to_string(), self.state.words.join("\n")),
+        ];
+
+        for &(ref opt, ref value) in &self.state.values {
+            vars.push((variable_name(opt), value.clone()));
+        }
+
+        vars
+    }
+
+    fn add(&mut self, value: &str, description: &str, kind: CandidateKind) {
+        if value.starts_with(&self.prefix[..]) {
+            self.candidates.push(Candidate::new(value, description, kind));
+        }
+    }
+
+    fn add_kind(&mut self, kind: &'a OptKind) {
+        match *kind {
+            OptKind::Choices(ref choices) => for choice in choices {
+                self.add(choice, "", CandidateKind::Value);
+            },
+            _ => self.expand.push(kind),
+        }
+    }
+
+    fn add_opts(&mut self) {
+        let command = self.state.command;
+        for opt in &command.options {
+            if (!opt.repeatable && self.state.is_used(opt)) || self.state.is_excluded(opt) {
+                continue;
+            }
+
+            for long in &opt.longs {
+                self.add(long, &opt.description, CandidateKind::Opt);
+            }
+            for short in &opt.shorts {
+                self.add(short, &opt.description, CandidateKind::Opt);
+            }
+        }
+    }
+
+    fn add_commands(&mut self) {
+        let command = self.state.command;
+        for sub in &command.commands {
+            self.add(&sub.name, "", CandidateKind::Command);
+        }
+    }
+}
+
+// `words` is the whole command line including the program name, and `cursor`
+// is the index of the word being completed, which may be one past the end.
+pub fn complete<'a>(prog: &'a Program, words: &[String], cursor: usize) -> Completion<'a> {
+    let mut state = State::new(prog);
+    state.words.extend(words.first().cloned());
+    for word in words.iter().take(cursor).skip(1) {
+        state.feed(word);
+    }
+
+    let current = words.get(cursor).map(|w| &w[..]).unwrap_or("");
+
+    if let Some(opt) = state.pending {
+        let mut completion = Completion::new(state, current);
+        if let Some(ref kind) = opt.argkind {
+            completion.add_kind(kind);
+            completion.cache = opt.cache.as_ref();
+        }
+        return completion;
+    }
+
+    let mut completion = Completion::new(state, current);
+    if !completion.state.separated && current.starts_with("-") {
+        completion.add_opts();
+    } else {
+        if !completion.state.separated && completion.state.positionals.is_empty() {
+            completion.add_commands();
+        }
+        if let Some(arg) = completion.state.argument() {
+            completion.add_kind(&arg.kind);
+            completion.cache = arg.cache.as_ref();
+        }
+    }
+
+    completion
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate toml;
+    use super::*;
+    use completist::program::{Program, OptKind};
+
+    fn program(spec: &str) -> Program {
+        let toml = toml::Parser::new(spec).parse().unwrap();
+        Program::from_toml(&toml).unwrap()
+    }
+
+    fn words(line: &str) -> Vec<String> {
+        line.split(' ').map(|w| w.to_string()).collect()
+    }
+
+    fn values(completion: &Completion) -> Vec<String> {
+        completion.candidates.iter().map(|c| c.value.clone()).collect()
+    }
+
+    const SPEC: &'static str = "
+        name = 'prog'
+        [[argument]]
+        name = 'FILE'
+        kind = 'file+'
+        [[option]]
+        long = '--all'
+        short = '-a'
+        description = 'show all'
+        repeatable = false
+        [[option]]
+        long = '--output'
+        short = '-o'
+        argkind = 'file'
+        description = 'output file'
+        [[command]]
+        name = 'build'
+        [[command.option]]
+        long = '--bin'
+        argkind = 'command(ls target)'
+        description = 'binary to build'
+        cache = 10
+        [[command]]
+        name = 'bench'
+    ";
+
+    #[test]
+    fn complete_subcommands() {
+        let prog = program(SPEC);
+        let completion = complete(&prog, &words("prog b"), 1);
+        assert_eq!(values(&completion), vec!["build", "bench"]);
+        assert_eq!(completion.candidates[0].kind, CandidateKind::Command);
+        assert_eq!(completion.expand, vec![&OptKind::FilePlus]);
+
+        let completion = complete(&prog, &words("prog bu"), 1);
+        assert_eq!(values(&completion), vec!["build"]);
+    }
+
+    #[test]
+    fn complete_options() {
+        let prog = program(SPEC);
+        let completion = complete(&prog, &words("prog -"), 1);
+        assert_eq!(values(&completion), vec!["--all", "-a", "--output", "-o"]);
+        assert_eq!(completion.candidates[0].description, "show all");
+        assert_eq!(completion.candidates[0].kind, CandidateKind::Opt);
+
+        let completion = complete(&prog, &words("prog --a"), 1);
+        assert_eq!(values(&completion), vec!["--all"]);
+    }
+
+    #[test]
+    fn consumed_options_are_skipped() {
+        let prog = program(SPEC);
+        let completion = complete(&prog, &words("prog -a -"), 2);
+        assert_eq!(values(&completion), vec!["--output", "-o"]);
+
+        let completion = complete(&prog, &words("prog --output=x -"), 2);
+        assert_eq!(values(&completion), vec!["--all", "-a", "--output", "-o"]);
+        assert_eq!(completion.state.value_of("output"), Some("x"));
+    }
+
+    #[test]
+    fn repeatable_options_are_offered_again() {
+        let prog = program("
+            name = 'prog'
+            [[option]]
+            short = '-v'
+            description = 'more'
+            [[option]]
+            short = '-q'
+            description = 'less'
+            repeatable = false
+        ");
+        assert_eq!(values(&complete(&prog, &words("prog -v -v -"), 3)), vec!["-v", "-q"]);
+        assert_eq!(values(&complete(&prog, &words("prog -q -"), 2)), vec!["-v"]);
+    }
+
+    #[test]
+    fn excluded_options_are_skipped() {
+        let prog = program("
+            name = 'prog'
+            [[option]]
+            long = '--all'
+            description = 'all'
+            excludes = ['--none']
+            repeatable = false
+            [[option]]
+            long = '--none'
+            description = 'none'
+            repeatable = false
+            [[option]]
+            long = '--verbose'
+            description = 'more'
+        ");
+        assert_eq!(values(&complete(&prog, &words("prog --all -"), 2)), vec!["--verbose"]);
+        assert_eq!(values(&complete(&prog, &words("prog --none -"), 2)), vec!["--verbose"]);
+    }
+
+    #[test]
+    fn complete_option_arguments() {
+        let prog = program(SPEC);
+        let completion = complete(&prog, &words("prog -o "), 2);
+        assert_eq!(completion.candidates.len(), 0);
+        assert_eq!(completion.expand, vec![&OptKind::File]);
+
+        let completion = complete(&prog, &words("prog build --bin "), 3);
+        assert_eq!(completion.state.path, vec!["build"]);
+        assert_eq!(completion.expand, vec![&OptKind::Command("ls target".to_string())]);
+        assert_eq!(completion.cache.map(|c| c.ttl), Some(10));
+    }
+
+    #[test]
+    fn context_variables() {
+        let prog = program(SPEC);
+        let completion = complete(&prog, &words("prog -o out -a bu"), 4);
+        assert_eq!(completion.variables(), vec![
+            ("COMPLETIST_CURRENT".to_string(), "bu".to_string()),
+            ("COMPLETIST_PREV".to_string(), "-a".to_string()),
+            ("COMPLETIST_WORDS".to_string(), "prog\n-o\nout\n-a".to_string()),
+            ("COMPLETIST_OPT_OUTPUT".to_string(), "out".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn complete_choices() {
+        let prog = program("
+            name = 'svc'
+            [[argument]]
+            name = 'ACTION'
+            kind = 'choice(start|stop|status)'
+        ");
+        let completion = complete(&prog, &words("svc st"), 1);
+        assert_eq!(values(&completion), vec!["start", "stop", "status"]);
+        assert_eq!(completion.candidates[0].kind, CandidateKind::Value);
+        assert_eq!(completion.expand.len(), 0);
+    }
+
+    #[test]
+    fn short_option_clusters() {
+        let prog = program(SPEC);
+        let completion = complete(&prog, &words("prog -ao "), 2);
+        assert_eq!(completion.expand, vec![&OptKind::File]);
+        assert_eq!(completion.state.used.len(), 2);
+
+        let completion = complete(&prog, &words("prog -aoout -"), 2);
+        assert_eq!(completion.state.value_of("output"), Some("out"));
+        assert_eq!(values(&completion), vec!["--output", "-o"]);
+    }
+
+    #[test]
+    fn arguments_and_separator() {
+        let prog = program(SPEC);
+        let completion = complete(&prog, &words("prog file "), 2);
+        assert_eq!(completion.candidates.len(), 0);
+        assert_eq!(completion.state.positionals, vec!["file"]);
+        assert_eq!(completion.expand, vec![&OptKind::FilePlus]);
+
+        let completion = complete(&prog, &words("prog -- -"), 2);
+        assert!(completion.state.separated);
+        assert_eq!(completion.candidates.len(), 0);
+        assert_eq!(completion.expand, vec![&OptKind::FilePlus]);
+    }
+
+    #[test]
+    fn subcommands_have_their_own_scope() {
+        let prog = program(SPEC);
+        let completion = complete(&prog, &words("prog -a bench -"), 3);
+        assert_eq!(completion.state.path, vec!["bench"]);
+        assert_eq!(completion.candidates.len(), 0);
+        assert_eq!(completion.expand.len(), 0);
+    }
+}
@@ -1,22 +1,45 @@
 use std::collections::HashSet;
-use std::io::Error;
+use std::io::{Error, ErrorKind};
 
 use completist::utils::normalise_extension;
 use completist::io::{Output, Write};
-use completist::program::{Program, Command, Argument, Opt};
+use completist::engine;
+use completist::program::{Program, Command, Argument, Opt, OptKind, Cache};
 
 pub struct Formatter {
     pub name: String,
     extensions: HashSet<String>,
+    writer: Option<WriteFn>,
+    checker: Option<CheckFn>,
 }
 
 pub type FmtResult = Result<(), Error>;
 
+pub type WriteFn = fn(&mut Output, &Program) -> FmtResult;
+
+// Lists the parts of a program that the format can't represent faithfully.
+pub type CheckFn = fn(&Program) -> Vec<String>;
+
 impl Formatter {
     pub fn matches_extension(&self, extension: String) -> bool {
         self.extensions.contains(&extension)
     }
 
+    pub fn write_program(&self, output: &mut Output, program: &Program) -> FmtResult {
+        match self.writer {
+            Some(writer) => writer(output, program),
+            None => Err(Error::new(ErrorKind::Other,
+                format!("formatter {} cannot write whole programs", self.name))),
+        }
+    }
+
+    pub fn warnings(&self, program: &Program) -> Vec<String> {
+        match self.checker {
+            Some(checker) => checker(program),
+            None => Vec::new(),
+        }
+    }
+
     pub fn write_comment(&self, output: &mut Output, text: String) -> FmtResult {
         for line in text.lines() {
             try!(output.write_fmt(format_args!("# {}", line)));
@@ -26,22 +49,176 @@ impl Formatter {
     }
 
     pub fn write_header(&self, output: &mut Output, program: &Program) -> FmtResult {
+        let mut opts = Vec::new();
+        options_with_arguments(&program.base_command, &mut opts);
+
+        // The words of the command line that aren't options or their values,
+        // which are the subcommands and positional arguments.
         try!(output.write_fmt(format_args!(r#"
-            function __fish_at_level_{}
-              set cmd (commandline -opc)
-              set subcommands $argv
-              set subcommands_len (count $subcommands)
-              if [ (count $cmd) -eq $subcommands_len ]
-                for i in (seq $subcommands_len)
-                  if [ $subcommands[$i] != $cmd[$i] ]
-                    return 1
+            function __fish_completist_words_{}
+              set separated 0
+              set skip 0
+              for token in (commandline -opc)
+                if [ $skip -eq 1 ]
+                  set skip 0
+                else if [ $separated -eq 1 ]
+                  printf '%s\n' $token
+                else
+                  switch $token
+                    case --
+                      set separated 1
+"#, program.name)));
+        let names: Vec<String> = opts.iter()
+            .flat_map(|opt| opt.longs.iter().chain(opt.shorts.iter()))
+            .map(|name| fish_quote(name))
+            .collect();
+        if !names.is_empty() {
+            try!(output.write_fmt(format_args!(r#"                    case {}
+                      set skip 1
+"#, names.join(" "))));
+        }
+        try!(output.write_fmt(format_args!(r#"                    case '-?*'
+                    case '*'
+                      printf '%s\n' $token
                   end
                 end
-                return 0
+              end
+            end
+"#)));
+
+        // Called with the positional index being completed (`any`, `N` or
+        // `N+` for N or more), the subcommand path, and after a `--` the
+        // path's own subcommands, which would start a deeper level.
+        try!(output.write_fmt(format_args!(r#"
+            function __fish_at_level_{name}
+              set words (__fish_completist_words_{name})
+              set index $argv[1]
+              set path $argv[2..-1]
+              set children
+              set sep (contains --index -- -- $path)
+              if [ -n "$sep" ]
+                set children $path[(math $sep + 1)..-1]
+                set path $path[1..(math $sep - 1)]
               end
 
-              return 1
-            end\n"#, program.name)));
+              set n (count $path)
+              if [ (count $words) -lt $n ]
+                return 1
+              end
+              for i in (seq 2 $n)
+                if [ "$words[$i]" != "$path[$i]" ]
+                  return 1
+                end
+              end
+              set rest (math (count $words) - $n)
+              if [ $rest -gt 0 ]; and contains -- $words[(math $n + 1)] $children
+                return 1
+              end
+
+              switch $index
+                case any
+                  return 0
+                case '*+'
+                  [ $rest -ge (string replace + '' -- $index) ]
+                case '*'
+                  [ $rest -eq $index ]
+              end
+            end
+"#, name = program.name)));
+        try!(output.write_fmt(format_args!(r#"
+            function __fish_completist_run_{}
+              set tokens (commandline -opc)
+              set -lx COMPLETIST_CURRENT (commandline -ct)
+              set -lx COMPLETIST_PREV $tokens[-1]
+              set -lx COMPLETIST_WORDS (string join \n -- $tokens)
+              set context
+"#, program.name)));
+
+        for opt in &opts {
+            try!(output.write_fmt(format_args!("              set -lx {} ''\n",
+                engine::variable_name(&opt.name()))));
+        }
+
+        try!(output.write_fmt(format_args!(r#"
+              set i 1
+              while [ $i -le (count $tokens) ]
+                switch $tokens[$i]
+"#)));
+        for opt in &opts {
+            let names: Vec<String> = opt.longs.iter().chain(opt.shorts.iter())
+                .map(|name| fish_quote(name))
+                .collect();
+            let equals: Vec<String> = opt.longs.iter()
+                .map(|long| fish_quote(&format!("{}=*", long)))
+                .collect();
+            try!(output.write_fmt(format_args!(r#"
+                  case {names}
+                    set i (math $i + 1)
+                    set {var} $tokens[$i]
+                    set context $context {var}=$tokens[$i]
+"#, names = names.join(" "), var = engine::variable_name(&opt.name()))));
+            if !equals.is_empty() {
+                try!(output.write_fmt(format_args!(r#"
+                  case {equals}
+                    set {var} (string split -m 1 = -- $tokens[$i])[2]
+                    set context $context {var}=${var}
+"#, equals = equals.join(" "), var = engine::variable_name(&opt.name()))));
+            }
+        }
+
+        try!(output.write_fmt(format_args!(r#"
+                end
+                set i (math $i + 1)
+              end
+
+              # commands run in sh, like in every other backend, and functions
+              # in this shell
+              set cmd $argv[-1]
+              set run sh -c
+              if [ "$argv[1]" = --function ]
+                set run eval
+                set -e argv[1]
+              end
+              if [ (count $argv) -lt 4 ]
+                $run $cmd
+                return
+              end
+
+              set ttl $argv[1]
+              set per_directory $argv[2]
+              set watch
+              if [ (count $argv) -gt 4 ]
+                set watch $argv[3..-3]
+              end
+
+              set dir ~/.cache/completist
+              if set -q XDG_CACHE_HOME
+                set dir $XDG_CACHE_HOME/completist
+              end
+              set key $cmd $context $COMPLETIST_CURRENT $COMPLETIST_PREV $COMPLETIST_WORDS
+              if [ $per_directory -eq 1 ]
+                set key $PWD $key
+              end
+              # hashed, since the key can be longer than a file name may be
+              set file $dir/fish_(printf '%s' "$key" | cksum | string replace ' ' _)
+
+              if [ -f $file ]; and [ (math (date +%s) - (path mtime $file)) -lt $ttl ]
+                set fresh 1
+                for watched in $watch
+                  if [ -e $watched ]; and [ (path mtime $watched) -gt (path mtime $file) ]
+                    set fresh 0
+                  end
+                end
+                if [ $fresh -eq 1 ]
+                  cat $file
+                  return
+                end
+              end
+
+              mkdir -p $dir
+              $run $cmd | tee $file
+            end
+"#)));
         Ok(())
     }
 
@@ -50,38 +227,101 @@ impl Formatter {
         Ok(())
     }
 
-    pub fn write_level(&self, out: &mut Output, prog: &Program, lvl: &[String]) -> FmtResult {
-        try!(out.write_fmt(format_args!(" -n '__fish_at_level_{} {}' ",
-            prog.name, lvl.connect(" "))));
+    // See `__fish_at_level_` in the header for the arguments.
+    pub fn write_level(&self, out: &mut Output, prog: &Program, index: &str,
+                       path: &[String], children: &[String]) -> FmtResult {
+        let mut args: Vec<String> = path.iter().map(|p| fish_quote(p)).collect();
+        if !children.is_empty() {
+            args.push("--".to_string());
+            args.extend(children.iter().map(|c| fish_quote(c)));
+        }
+        try!(out.write_fmt(format_args!(" -n {} ",
+            fish_quote(&format!("__fish_at_level_{} {} {}", prog.name, index, args.join(" "))))));
         Ok(())
     }
 
     pub fn write_opt(&self, out: &mut Output, opt: &Opt) -> FmtResult {
-        for short in &opt.shorts {
-            try!(out.write_fmt(format_args!(" -s '{}' ", short)));
-        }
-
-        for long in &opt.longs {
-            try!(out.write_fmt(format_args!(" -{} '{}' ",
-                (if long.starts_with("--") {"l"} else {"o"}), long)));
+        for name in opt.shorts.iter().chain(opt.longs.iter()) {
+            let bare = name.trim_start_matches('-');
+            let flag = if name.starts_with("--") { "l" } else if bare.chars().count() == 1 { "s" } else { "o" };
+            try!(out.write_fmt(format_args!(" -{} {} ", flag, fish_quote(bare))));
         }
 
         Ok(())
     }
 
     pub fn write_opt_description(&self, out: &mut Output, opt: &Opt) -> FmtResult {
-        try!(out.write_fmt(format_args!(" -d '{}' ", opt.description)));
+        try!(out.write_fmt(format_args!(" -d {} ", fish_quote(&opt.description))));
         Ok(())
     }
 
-    pub fn write_opt_arguments(&self, out: &mut Output, opt: &Opt) -> FmtResult {
+    // The `-a` list of a kind's values, or nothing for files, which fish
+    // completes by itself.
+    fn values(&self, prog: &Program, kind: &OptKind, cache: &Option<Cache>) -> Option<String> {
+        match *kind {
+            OptKind::File | OptKind::FilePlus => None,
+            OptKind::Command(ref cmd) => {
+                let call = match *cache {
+                    Some(ref cache) => format!("__fish_completist_run_{} {} {} {} -- {}",
+                        prog.name, cache.ttl, if cache.per_directory {1} else {0},
+                        cache.watch.iter().map(|w| fish_quote(w)).collect::<Vec<_>>().join(" "),
+                        fish_quote(cmd)),
+                    None => format!("__fish_completist_run_{} -- {}", prog.name, fish_quote(cmd)),
+                };
+                Some(fish_quote(&format!("({})", call)))
+            },
+            OptKind::Function(ref func) => {
+                let call = format!("__fish_completist_run_{} --function -- {}", prog.name, fish_quote(func));
+                Some(fish_quote(&format!("({})", call)))
+            },
+            OptKind::Choices(ref choices) => {
+                let choices: Vec<String> = choices.iter().map(|c| fish_quote(c)).collect();
+                Some(fish_quote(&choices.join(" ")))
+            },
+        }
+    }
+
+    pub fn write_opt_arguments(&self, out: &mut Output, prog: &Program, opt: &Opt) -> FmtResult {
+        if let Some(ref kind) = opt.argkind {
+            match self.values(prog, kind, &opt.cache) {
+                Some(values) => try!(out.write_fmt(format_args!(" -x -a {} ", values))),
+                None => try!(out.write_fmt(format_args!(" -r -F "))),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn write_arg_kind(&self, out: &mut Output, prog: &Program, kind: &OptKind, cache: &Option<Cache>) -> FmtResult {
+        match self.values(prog, kind, cache) {
+            Some(values) => try!(out.write_fmt(format_args!(" -a {} ", values))),
+            None => try!(out.write_fmt(format_args!(" -F "))),
+        }
+
         Ok(())
     }
 }
 
+fn options_with_arguments<'a>(command: &'a Command, opts: &mut Vec<&'a Opt>) {
+    for opt in &command.options {
+        if opt.argkind.is_some() && !opts.iter().any(|o| o.name() == opt.name()) {
+            opts.push(opt);
+        }
+    }
+    for sub in &command.commands {
+        options_with_arguments(sub, opts);
+    }
+}
+
+pub fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace("\\", "\\\\").replace("'", "\\'"))
+}
+
 pub struct FormatterBuilder {
     name: String,
     extensions: HashSet<String>,
+    writer: Option<WriteFn>,
+    checker: Option<CheckFn>,
 }
 
 impl FormatterBuilder {
@@ -89,9 +329,21 @@ impl FormatterBuilder {
         FormatterBuilder {
             name: name.to_string(),
             extensions: HashSet::new(),
+            writer: None,
+            checker: None,
         }
     }
 
+    pub fn writer(&mut self, writer: WriteFn) -> &mut Self {
+        self.writer = Some(writer);
+        self
+    }
+
+    pub fn checker(&mut self, checker: CheckFn) -> &mut Self {
+        self.checker = Some(checker);
+        self
+    }
+
     pub fn ext(&mut self, ext: &str) -> &mut Self {
         self.extensions.insert(normalise_extension(ext.to_string()));
         self
@@ -106,6 +358,8 @@ impl FormatterBuilder {
         Ok(Formatter {
             name: self.name,
             extensions: self.extensions,
+            writer: self.writer,
+            checker: self.checker,
         })
     }
 }
@@ -154,6 +408,43 @@ mod tests {
     mod formatter {
         use super::*;
 
+        #[test]
+        fn write_program() {
+            use completist::io::{capture, Write};
+            use completist::program::Program;
+
+            fn writer(out: &mut Output, prog: &Program) -> FmtResult {
+                out.write_fmt(format_args!("program {}", prog.name))
+            }
+
+            let prog = ::completist::parse_program("name = 'cat'").unwrap();
+            let formatter = FormatterBuilder::new("formatter").build().unwrap();
+            assert!(capture(|out| formatter.write_program(out, &prog)).is_err());
+
+            let mut builder = FormatterBuilder::new("formatter");
+            builder.writer(writer);
+            let formatter = builder.build().unwrap();
+            assert_eq!(capture(|out| formatter.write_program(out, &prog)).unwrap(), "program cat");
+        }
+
+        #[test]
+        fn warnings() {
+            use completist::program::Program;
+
+            fn checker(prog: &Program) -> Vec<String> {
+                vec![format!("{} is lossy", prog.name)]
+            }
+
+            let prog = ::completist::parse_program("name = 'cat'").unwrap();
+            let formatter = FormatterBuilder::new("formatter").build().unwrap();
+            assert!(formatter.warnings(&prog).is_empty());
+
+            let mut builder = FormatterBuilder::new("formatter");
+            builder.checker(checker);
+            let formatter = builder.build().unwrap();
+            assert_eq!(formatter.warnings(&prog), vec!["cat is lossy"]);
+        }
+
         #[test]
         fn matches_extension() {
             let mut builder = FormatterBuilder::new("formatter");
@@ -163,5 +454,67 @@ mod tests {
             assert!(formatter.matches_extension(".fish".to_string()));
             assert!(!formatter.matches_extension(".fhs".to_string()));
         }
+
+        #[test]
+        fn write_opt_arguments() {
+            use completist::io::capture;
+            use completist::program::{Opt, OptKind, Cache};
+
+            let formatter = FormatterBuilder::new("formatter").build().unwrap();
+            let prog = ::completist::parse_program("name = 'cargo'").unwrap();
+            let mut opt = Opt {
+                longs: vec!["--bin".to_string()],
+                shorts: vec![],
+                description: "binary".to_string(),
+                argkind: Some(OptKind::Command("ls 'target/debug'".to_string())),
+                cache: None,
+                excludes: vec![],
+                repeatable: true,
+            };
+
+            let out = capture(|out| formatter.write_opt_arguments(out, &prog, &opt)).unwrap();
+            assert_eq!(out, r" -x -a '(__fish_completist_run_cargo -- \'ls \\\'target/debug\\\'\')' ");
+
+            opt.cache = Some(Cache { ttl: 60, per_directory: true, watch: vec!["Cargo.toml".to_string()] });
+            let out = capture(|out| formatter.write_opt_arguments(out, &prog, &opt)).unwrap();
+            assert_eq!(out, r" -x -a '(__fish_completist_run_cargo 60 1 \'Cargo.toml\' -- \'ls \\\'target/debug\\\'\')' ");
+
+            opt.argkind = Some(OptKind::File);
+            let out = capture(|out| formatter.write_opt_arguments(out, &prog, &opt)).unwrap();
+            assert_eq!(out, " -r -F ");
+
+            opt.argkind = Some(OptKind::Function("__fish_complete_users".to_string()));
+            let out = capture(|out| formatter.write_opt_arguments(out, &prog, &opt)).unwrap();
+            assert_eq!(out, r" -x -a '(__fish_completist_run_cargo --function -- \'__fish_complete_users\')' ");
+
+            opt.argkind = Some(OptKind::Choices(vec!["debug".to_string(), "it's".to_string()]));
+            let out = capture(|out| formatter.write_opt_arguments(out, &prog, &opt)).unwrap();
+            assert_eq!(out, r" -x -a '\'debug\' \'it\\\'s\'' ");
+        }
+
+        #[test]
+        fn header_exports_option_values() {
+            use completist::io::capture;
+
+            let formatter = FormatterBuilder::new("formatter").build().unwrap();
+            let prog = ::completist::parse_program("
+                name = 'cargo'
+                [[command]]
+                name = 'run'
+                [[command.option]]
+                long = '--manifest-path'
+                short = '-m'
+                argkind = 'file'
+                description = 'path to Cargo.toml'
+            ").unwrap();
+
+            let out = capture(|out| formatter.write_header(out, &prog)).unwrap();
+            assert!(out.contains("function __fish_completist_run_cargo"));
+            assert!(out.contains("set -lx COMPLETIST_OPT_MANIFEST_PATH ''"));
+            assert!(out.contains("case '--manifest-path' '-m'"));
+            assert!(out.contains("case '--manifest-path=*'"));
+            assert!(out.contains("set key $cmd $context $COMPLETIST_CURRENT $COMPLETIST_PREV $COMPLETIST_WORDS"));
+            assert!(out.contains("| cksum |"));
+        }
     }
 }
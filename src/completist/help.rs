@@ -0,0 +1,175 @@
+use completist::program::{Command, Opt};
+use completist::usage::{usage, describe_kind, Style};
+use completist::utils::wrap;
+
+// Descriptions start in this column unless every name is narrower.
+const MAX_COLUMN: usize = 30;
+
+fn option_names(opt: &Opt) -> String {
+    // Long-only options are indented as if there were a short one, like GNU.
+    let mut names = if opt.shorts.is_empty() { "    ".to_string() } else { String::new() };
+    let all: Vec<&String> = opt.shorts.iter().chain(opt.longs.iter()).collect();
+    names.push_str(&all.iter().map(|n| &n[..]).collect::<Vec<&str>>().join(", "));
+
+    if opt.argkind.is_some() {
+        let placeholder = opt.name().to_uppercase();
+        match all.last() {
+            Some(last) if last.starts_with("--") => names.push_str(&format!("={}", placeholder)),
+            _ => names.push_str(&format!(" {}", placeholder)),
+        }
+    }
+    names
+}
+
+fn write_table(lines: &mut Vec<String>, rows: &[(String, String)], width: usize) {
+    let widest = rows.iter().map(|&(ref left, _)| left.chars().count()).max().unwrap_or(0);
+    let column = if widest + 4 < MAX_COLUMN { widest + 4 } else { MAX_COLUMN };
+
+    for &(ref left, ref description) in rows {
+        let mut line = format!("  {}", left);
+        let words: Vec<&str> = description.split_whitespace().collect();
+        if words.is_empty() {
+            lines.push(line);
+            continue;
+        }
+        if line.chars().count() + 2 > column {
+            lines.push(line);
+            line = String::new();
+        }
+        let padding = column - line.chars().count();
+        line.push_str(&" ".repeat(padding));
+
+        let mut wrapped = wrap(&words, width, column, column).into_iter();
+        line.push_str(&wrapped.next().unwrap());
+        lines.push(line);
+        lines.extend(wrapped);
+    }
+}
+
+// A GNU-style --help screen for the command at `path`, wrapped to `width`.
+pub fn help(path: &[&str], command: &Command, width: usize) -> String {
+    let mut lines = Vec::new();
+    let usage = usage(path, command, Style::Gnu, width.saturating_sub("Usage: ".len()));
+    for (i, line) in usage.lines().enumerate() {
+        lines.push(format!("{}{}", if i == 0 { "Usage: " } else { "       " }, line));
+    }
+
+    if !command.description.is_empty() {
+        let words: Vec<&str> = command.description.split_whitespace().collect();
+        lines.extend(wrap(&words, width, 0, 0));
+    }
+
+    if !command.arguments.is_empty() {
+        lines.push(String::new());
+        lines.push("Arguments:".to_string());
+        let rows: Vec<(String, String)> = command.arguments.iter()
+            .map(|arg| (arg.name.clone(), describe_kind(&arg.kind, arg.optional)))
+            .collect();
+        write_table(&mut lines, &rows, width);
+    }
+
+    if !command.options.is_empty() {
+        lines.push(String::new());
+        lines.push("Options:".to_string());
+        let rows: Vec<(String, String)> = command.options.iter()
+            .map(|opt| {
+                let mut description = opt.description.clone();
+                if let Some(ref kind) = opt.argkind {
+                    description.push_str(&format!(" ({} is {})", opt.name().to_uppercase(), describe_kind(kind, false)));
+                }
+                (option_names(opt), description)
+            })
+            .collect();
+        write_table(&mut lines, &rows, width);
+    }
+
+    if !command.commands.is_empty() {
+        lines.push(String::new());
+        lines.push("Commands:".to_string());
+        let rows: Vec<(String, String)> = command.commands.iter()
+            .map(|sub| (sub.name.clone(), sub.description.clone()))
+            .collect();
+        write_table(&mut lines, &rows, width);
+    }
+
+    let mut help = lines.join("\n");
+    help.push('\n');
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+
+    const SPEC: &'static str = "
+        name = 'svc'
+        description = 'Manage the services running on this machine, starting and stopping them as needed.'
+        [[argument]]
+        name = 'UNIT'
+        kind = 'file'
+        optional = true
+        [[option]]
+        long = '--verbose'
+        short = '-v'
+        description = 'say more'
+        [[option]]
+        long = '--config'
+        argkind = 'file'
+        description = 'read settings from a file other than the default one'
+        [[option]]
+        short = '-j'
+        argkind = 'choice(1|2|4)'
+        description = 'jobs'
+        [[option]]
+        long = '--a-really-long-option-name'
+        description = 'long'
+        [[command]]
+        name = 'start'
+        description = 'start a service'
+        [[command]]
+        name = 'stop'
+    ";
+
+    #[test]
+    fn names() {
+        let prog = parse_program(SPEC).unwrap();
+        let names: Vec<String> = prog.base_command.options.iter().map(option_names).collect();
+        assert_eq!(names, vec!["-v, --verbose", "    --config=CONFIG", "-j J", "    --a-really-long-option-name"]);
+    }
+
+    #[test]
+    fn narrow_widths() {
+        let prog = parse_program(SPEC).unwrap();
+        for width in 0..8 {
+            assert!(help(&["svc"], &prog.base_command, width).starts_with("Usage: svc\n"));
+        }
+    }
+
+    #[test]
+    fn help_screen() {
+        let prog = parse_program(SPEC).unwrap();
+        assert_eq!(help(&["svc"], &prog.base_command, 60), "\
+Usage: svc [OPTION]... [COMMAND] [UNIT]
+Manage the services running on this machine, starting and
+stopping them as needed.
+
+Arguments:
+  UNIT  a file (optional)
+
+Options:
+  -v, --verbose               say more
+      --config=CONFIG         read settings from a file
+                              other than the default one
+                              (CONFIG is a file)
+  -j J                        jobs (J is one of `1`, `2`,
+                              `4`)
+      --a-really-long-option-name
+                              long
+
+Commands:
+  start  start a service
+  stop
+");
+    }
+}
@@ -0,0 +1,646 @@
+use std::collections::{HashMap, HashSet};
+
+use completist::CompletistError;
+use completist::program::{Program, Command, Opt, OptKind, Metadata};
+use super::{Import, command_at, add_argument, with_functions};
+use super::shell::{self, Node};
+
+enum Candidates {
+    Files,
+    Words(Vec<String>),
+    Command(String),
+}
+
+// The parts of the command line that completion functions look at.
+#[derive(Debug, PartialEq)]
+enum Role {
+    Current,
+    Previous,
+    Word(usize),
+    Index,
+    Other,
+}
+
+fn role(name: &str) -> Role {
+    let name: String = name.chars().filter(|c| !" ${}".contains(*c)).collect();
+    match &name[..] {
+        "cur" | "2" | "COMP_WORDS[COMP_CWORD]" | "words[cword]" => Role::Current,
+        "prev" | "3" | "COMP_WORDS[COMP_CWORD-1]" | "words[cword-1]" => Role::Previous,
+        "COMP_CWORD" | "cword" => Role::Index,
+        _ => {
+            let index = name.trim_start_matches("COMP_WORDS").trim_start_matches("words");
+            if index.starts_with('[') && index.ends_with(']') {
+                index[1..index.len() - 1].parse::<usize>().map(Role::Word).unwrap_or(Role::Other)
+            } else {
+                Role::Other
+            }
+        },
+    }
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(|c| "*?[]".contains(c))
+}
+
+// Actions that can be listed outside of the user's shell run `compgen` in a
+// fresh bash.
+fn action(action: &str) -> Result<Candidates, String> {
+    match action {
+        "file" | "directory" => Ok(Candidates::Files),
+        "command" | "user" | "group" | "hostname" | "service" | "signal" =>
+            Ok(Candidates::Command(format!("bash -c 'compgen -A {}'", action))),
+        _ => Err(format!("`{}` completions depend on the running shell", action)),
+    }
+}
+
+fn action_flag(flag: char) -> Option<&'static str> {
+    match flag {
+        'a' => Some("alias"),
+        'b' => Some("builtin"),
+        'c' => Some("command"),
+        'd' => Some("directory"),
+        'e' => Some("export"),
+        'f' => Some("file"),
+        'g' => Some("group"),
+        'j' => Some("job"),
+        'k' => Some("keyword"),
+        's' => Some("service"),
+        'u' => Some("user"),
+        'v' => Some("variable"),
+        _ => None,
+    }
+}
+
+// The flags shared by `complete` and `compgen`.
+struct Spec {
+    candidates: Vec<Candidates>,
+    function: Option<String>,
+    names: Vec<String>,
+    problems: Vec<String>,
+}
+
+fn find_option<'a>(command: &'a mut Command, names: &[String]) -> &'a mut Opt {
+    let i = match command.options.iter().position(|o| o.longs.iter().chain(o.shorts.iter()).any(|n| names.contains(n))) {
+        Some(i) => i,
+        None => {
+            command.options.push(Opt::new(Vec::new(), Vec::new(), "", None));
+            command.options.len() - 1
+        },
+    };
+    let opt = &mut command.options[i];
+    for name in names {
+        if opt.longs.contains(name) || opt.shorts.contains(name) {
+            continue;
+        } else if name.starts_with("--") || name.len() > 2 {
+            opt.longs.push(name.clone());
+        } else {
+            opt.shorts.push(name.clone());
+        }
+    }
+    opt
+}
+
+#[derive(Clone)]
+struct Context {
+    path: Vec<String>,
+    // The option whose argument is being completed, when `$prev` is one.
+    option: Option<Vec<String>>,
+}
+
+// Walks the completion functions taking every branch, and records what each
+// of them offers.
+struct Importer<'a> {
+    program: String,
+    lines: Vec<&'a str>,
+    functions: HashMap<String, &'a [Node]>,
+    // The name and source of each function, in script order.
+    sources: Vec<(String, String)>,
+    vars: HashMap<String, String>,
+    dispatched: HashSet<String>,
+    running: Vec<String>,
+    visited: HashSet<String>,
+    base: Command,
+    skipped: Vec<String>,
+}
+
+impl<'a> Importer<'a> {
+    fn role(&self, word: &str) -> Role {
+        match role(word) {
+            Role::Other => {
+                let name: String = word.chars().filter(|c| !" ${}".contains(*c)).collect();
+                self.vars.get(&name).map_or(Role::Other, |value| role(value))
+            },
+            role => role,
+        }
+    }
+
+    // Records variables and functions, and which words are subcommands.
+    fn scan(&mut self, nodes: &'a [Node]) {
+        for node in nodes {
+            match *node {
+                Node::Command(_, ref words) => {
+                    let skip = if ["local", "declare", "typeset", "export"].contains(&&words[0][..]) { 1 } else { 0 };
+                    for word in &words[skip..] {
+                        match word.find('=') {
+                            Some(i) if i > 0 && word[..i].chars().all(|c| c.is_alphanumeric() || c == '_') =>
+                                { self.vars.insert(word[..i].to_string(), word[i + 1..].to_string()); },
+                            _ => if skip == 0 { break },
+                        }
+                    }
+                },
+                Node::Function(line, end, ref name, ref body) => {
+                    self.functions.insert(name.clone(), &body[..]);
+                    self.sources.push((name.clone(), self.lines[line - 1..end].join("\n")));
+                    self.scan(body);
+                },
+                Node::Case(_, _, ref branches) => for &(_, ref body) in branches {
+                    self.scan(body);
+                },
+                Node::If(_, ref branches, ref otherwise) => {
+                    for &(ref condition, ref body) in branches {
+                        self.scan(condition);
+                        self.scan(body);
+                    }
+                    self.scan(otherwise);
+                },
+                Node::Assign(..) => {},
+            }
+        }
+    }
+
+    fn scan_dispatch(&mut self, nodes: &'a [Node]) {
+        for node in nodes {
+            match *node {
+                Node::Function(_, _, _, ref body) => self.scan_dispatch(body),
+                Node::Case(_, ref subject, ref branches) => {
+                    let dispatch = match self.role(subject) {
+                        Role::Word(_) => true,
+                        _ => false,
+                    };
+                    for &(ref patterns, ref body) in branches {
+                        if dispatch {
+                            self.dispatched.extend(patterns.iter().filter(|p| !is_glob(p)).cloned());
+                        }
+                        self.scan_dispatch(body);
+                    }
+                },
+                Node::If(_, ref branches, ref otherwise) => {
+                    for &(_, ref body) in branches {
+                        self.scan_dispatch(body);
+                    }
+                    self.scan_dispatch(otherwise);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn words(&self, list: &str) -> Candidates {
+        if let Some(command) = shell::command_substitution(list) {
+            return Candidates::Command(command);
+        }
+        let words = list.split_whitespace()
+            .flat_map(|word| if word.starts_with('$') {
+                let name: String = word.chars().filter(|c| !"${}\"".contains(*c)).collect();
+                self.vars.get(&name).map_or(Vec::new(), |value| value.split_whitespace().map(|w| w.to_string()).collect())
+            } else {
+                vec![word.to_string()]
+            })
+            .collect();
+        Candidates::Words(words)
+    }
+
+    fn spec(&self, args: &[String]) -> Spec {
+        let mut spec = Spec {
+            candidates: Vec::new(),
+            function: None,
+            names: Vec::new(),
+            problems: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                spec.names.extend(args.by_ref().cloned());
+                break;
+            } else if !arg.starts_with('-') || arg.len() == 1 {
+                spec.names.push(arg.clone());
+                continue;
+            }
+
+            let flags: Vec<char> = arg[1..].chars().collect();
+            for (i, &flag) in flags.iter().enumerate() {
+                if "AWFCGXPSo".contains(flag) {
+                    let rest: String = flags[i + 1..].iter().cloned().collect();
+                    let value = match (rest.is_empty(), args.next()) {
+                        (false, _) => rest,
+                        (true, Some(value)) => value.clone(),
+                        (true, None) => {
+                            spec.problems.push(format!("`-{}` is missing its value", flag));
+                            break;
+                        },
+                    };
+                    match flag {
+                        'A' => match action(&value) {
+                            Ok(candidates) => spec.candidates.push(candidates),
+                            Err(problem) => spec.problems.push(problem),
+                        },
+                        'W' => spec.candidates.push(self.words(&value)),
+                        'F' => spec.function = Some(value),
+                        'C' => spec.candidates.push(Candidates::Command(value)),
+                        'G' => spec.problems.push(format!("glob `{}`", value)),
+                        'X' => spec.problems.push(format!("filter `{}`", value)),
+                        'P' | 'S' => spec.problems.push(format!("prefix or suffix `{}`", value)),
+                        _ => match &value[..] {
+                            "nospace" | "filenames" | "noquote" | "nosort" | "fullquote" => {},
+                            value => spec.problems.push(format!("option `-o {}`", value)),
+                        },
+                    }
+                    break;
+                }
+                match action_flag(flag).map(action) {
+                    Some(Ok(candidates)) => spec.candidates.push(candidates),
+                    Some(Err(problem)) => spec.problems.push(problem),
+                    None => spec.problems.push(format!("flag `-{}`", flag)),
+                }
+            }
+        }
+        spec
+    }
+
+    fn run(&mut self, nodes: &'a [Node], context: &Context) {
+        for node in nodes {
+            match *node {
+                Node::Function(..) => {},
+                Node::Assign(line, ref name, ref items, _) => if name == "COMPREPLY" {
+                    for item in items {
+                        self.item(line, item, context);
+                    }
+                },
+                Node::Command(line, ref words) => self.command(line, words, context),
+                Node::Case(line, ref subject, ref branches) => self.case(line, subject, branches, context),
+                Node::If(line, ref branches, ref otherwise) => {
+                    for &(ref condition, ref body) in branches {
+                        match self.condition(condition) {
+                            Ok(Some(option)) => {
+                                let mut context = context.clone();
+                                context.option = Some(vec![option]);
+                                self.run(body, &context);
+                            },
+                            Ok(None) => self.run(body, context),
+                            Err(condition) => {
+                                self.skipped.push(format!("line {}: condition `{}`", line, condition));
+                                self.run(body, context);
+                            },
+                        }
+                    }
+                    self.run(otherwise, context);
+                },
+            }
+        }
+    }
+
+    // Conditions on the current word or its index don't change where the
+    // candidates go, but a test of `$prev` means they belong to an option.
+    fn condition(&self, condition: &[Node]) -> Result<Option<String>, String> {
+        let mut option = None;
+        for node in condition {
+            let words: Vec<&str> = match *node {
+                Node::Command(_, ref words) => words.iter().map(|w| &w[..])
+                    .filter(|w| !["[[", "]]", "[", "]", "test"].contains(w))
+                    .collect(),
+                _ => return Err("...".to_string()),
+            };
+            let understood = words.len() == 3 && ["==", "=", "-eq"].contains(&words[1]) && match self.role(words[0]) {
+                Role::Current => words[2].starts_with('-'),
+                Role::Index => true,
+                Role::Previous if words[2].starts_with('-') && !is_glob(words[2]) => {
+                    option = Some(words[2].to_string());
+                    true
+                },
+                _ => false,
+            };
+            if !understood {
+                return Err(words.join(" "));
+            }
+        }
+        Ok(option)
+    }
+
+    fn command(&mut self, line: usize, words: &[String], context: &Context) {
+        match &words[0][..] {
+            "compgen" => {
+                let spec = self.spec(&words[1..]);
+                self.offer_spec(line, spec, context);
+            },
+            "_filedir" | "_filedir_xspec" => self.offer(line, Candidates::Files, context),
+            "_init_completion" | "_get_comp_words_by_ref" | "_split_longopt" | "_count_args" => {},
+            name if self.functions.contains_key(name) => self.call(name, context),
+            name if name.starts_with('_') => self.skipped.push(format!("line {}: `{}` isn't supported", line, name)),
+            _ => {},
+        }
+    }
+
+    fn call(&mut self, name: &str, context: &Context) {
+        if self.running.iter().any(|r| r == name) {
+            return;
+        }
+        let body = self.functions[name];
+        self.running.push(name.to_string());
+        self.visited.insert(name.to_string());
+        self.run(body, context);
+        self.running.pop();
+    }
+
+    fn item(&mut self, line: usize, item: &str, context: &Context) {
+        if let Some(command) = shell::command_substitution(item) {
+            let words = shell::words(&command).unwrap_or_else(|_| Vec::new());
+            if words.first().map_or(false, |w| w == "compgen") {
+                let spec = self.spec(&words[1..]);
+                return self.offer_spec(line, spec, context);
+            }
+            return self.offer(line, Candidates::Command(command), context);
+        }
+        let candidates = self.words(item);
+        self.offer(line, candidates, context);
+    }
+
+    fn offer_spec(&mut self, line: usize, spec: Spec, context: &Context) {
+        for problem in spec.problems {
+            self.skipped.push(format!("line {}: {}", line, problem));
+        }
+        if let Some(function) = spec.function {
+            self.skipped.push(format!("line {}: function `{}` called by compgen", line, function));
+        }
+        for candidates in spec.candidates {
+            self.offer(line, candidates, context);
+        }
+    }
+
+    fn offer(&mut self, line: usize, candidates: Candidates, context: &Context) {
+        // Commands that call functions of this script take their definitions
+        // along, but not the COMP_* variables those functions may read.
+        let candidates = match candidates {
+            Candidates::Command(command) => {
+                let (with, calls) = with_functions("bash", &command, &self.sources);
+                if !calls.is_empty() {
+                    self.skipped.push(format!("line {}: `{}` runs in a new bash with the functions it calls, \
+                        without the COMP_* variables", line, command));
+                }
+                self.visited.extend(calls);
+                Candidates::Command(with)
+            },
+            candidates => candidates,
+        };
+
+        let command = command_at(&mut self.base, &context.path);
+        if let Some(ref names) = context.option {
+            let kind = match candidates {
+                Candidates::Files => OptKind::File,
+                Candidates::Words(words) => OptKind::Choices(words),
+                Candidates::Command(command) => OptKind::Command(command),
+            };
+            let opt = find_option(command, names);
+            match (&mut opt.argkind, kind) {
+                (&mut Some(OptKind::Choices(ref mut choices)), OptKind::Choices(more)) => choices.extend(more),
+                (&mut Some(_), _) => {},
+                (argkind, kind) => *argkind = Some(kind),
+            }
+            return;
+        }
+
+        let result = match candidates {
+            Candidates::Files => add_argument(command, OptKind::FilePlus, None),
+            Candidates::Command(cmd) => add_argument(command, OptKind::Command(cmd), None),
+            Candidates::Words(words) => {
+                let mut choices = Vec::new();
+                for word in words {
+                    if word.starts_with('-') {
+                        find_option(command, &[word]);
+                    } else if self.dispatched.contains(&word) {
+                        command_at(command, &[word]);
+                    } else {
+                        choices.push(word);
+                    }
+                }
+                if choices.is_empty() { Ok(()) } else { add_argument(command, OptKind::Choices(choices), None) }
+            },
+        };
+        if let Err(message) = result {
+            self.skipped.push(format!("line {}: {}", line, message));
+        }
+    }
+
+    fn case(&mut self, line: usize, subject: &str, branches: &'a [(Vec<String>, Vec<Node>)], context: &Context) {
+        let role = self.role(subject);
+        for &(ref patterns, ref body) in branches {
+            match role {
+                Role::Previous => {
+                    let options: Vec<String> = patterns.iter().filter(|p| p.starts_with('-') && !is_glob(p)).cloned().collect();
+                    if !options.is_empty() {
+                        find_option(command_at(&mut self.base, &context.path), &options);
+                        self.run(body, &Context { path: context.path.clone(), option: Some(options) });
+                    }
+                    for pattern in patterns.iter().filter(|p| !p.starts_with('-')) {
+                        if pattern == "*" {
+                            self.run(body, context);
+                        } else if is_glob(pattern) {
+                            self.skipped.push(format!("line {}: case pattern `{}`", line, pattern));
+                        } else {
+                            // The word before is the program or a subcommand.
+                            let mut path = context.path.clone();
+                            if *pattern != self.program {
+                                path.push(pattern.clone());
+                            }
+                            self.run(body, &Context { path: path, option: None });
+                        }
+                    }
+                },
+                Role::Word(n) if n == context.path.len() + 1 => for pattern in patterns {
+                    if pattern == "*" {
+                        self.run(body, context);
+                    } else if is_glob(pattern) {
+                        self.skipped.push(format!("line {}: case pattern `{}`", line, pattern));
+                    } else {
+                        let mut path = context.path.clone();
+                        path.push(pattern.clone());
+                        command_at(&mut self.base, &path);
+                        self.run(body, &Context { path: path, option: None });
+                    }
+                },
+                Role::Current => for pattern in patterns {
+                    if pattern == "*" || pattern == "-*" {
+                        self.run(body, context);
+                    } else {
+                        self.skipped.push(format!("line {}: case pattern `{}`", line, pattern));
+                    }
+                },
+                Role::Index => self.run(body, context),
+                _ => return self.skipped.push(format!("line {}: case on `{}`", line, subject)),
+            }
+        }
+    }
+}
+
+// `complete` commands can sit inside `if` blocks, but not inside functions.
+fn completes<'a>(nodes: &'a [Node], found: &mut Vec<(usize, &'a [String])>) {
+    for node in nodes {
+        match *node {
+            Node::Command(line, ref words) if words[0] == "complete" => found.push((line, &words[1..])),
+            Node::If(_, ref branches, ref otherwise) => {
+                for &(ref condition, ref body) in branches {
+                    completes(condition, found);
+                    completes(body, found);
+                }
+                completes(otherwise, found);
+            },
+            _ => {},
+        }
+    }
+}
+
+pub fn import(script: &str, name: Option<&str>) -> Result<Import, CompletistError> {
+    let nodes = try!(shell::parse(script).map_err(CompletistError::ImportError));
+    let mut found = Vec::new();
+    completes(&nodes, &mut found);
+
+    let mut importer = Importer {
+        program: String::new(),
+        lines: script.lines().collect(),
+        functions: HashMap::new(),
+        sources: Vec::new(),
+        vars: HashMap::new(),
+        dispatched: HashSet::new(),
+        running: Vec::new(),
+        visited: HashSet::new(),
+        base: Command::new(""),
+        skipped: Vec::new(),
+    };
+    let program = match name {
+        Some(name) => name.to_string(),
+        None => match found.iter().filter_map(|&(_, args)| importer.spec(args).names.into_iter().next()).next() {
+            Some(name) => name,
+            None => return Err(CompletistError::ImportError("no complete commands to take the program name from".to_string())),
+        },
+    };
+    importer.program = program.clone();
+    importer.base = Command::new(&program);
+    importer.scan(&nodes);
+    importer.scan_dispatch(&nodes);
+
+    let context = Context { path: Vec::new(), option: None };
+    for (line, args) in found {
+        let spec = importer.spec(args);
+        if !spec.names.contains(&program) {
+            importer.skipped.push(format!("line {}: completions for `{}`", line, spec.names.join(" ")));
+            continue;
+        }
+        for other in spec.names.iter().filter(|n| **n != program) {
+            importer.skipped.push(format!("line {}: completions for `{}`", line, other));
+        }
+        match spec.function {
+            Some(ref function) if importer.functions.contains_key(function) => importer.call(function, &context),
+            Some(ref function) => importer.skipped.push(format!("line {}: function `{}` isn't defined here", line, function)),
+            None => {},
+        }
+        let spec = Spec { function: None, ..spec };
+        importer.offer_spec(line, spec, &context);
+    }
+
+    importer.skipped.dedup();
+    let mut unused: Vec<String> = importer.functions.keys()
+        .filter(|f| !importer.visited.contains(*f))
+        .map(|f| format!("function `{}` is never used", f))
+        .collect();
+    unused.sort();
+    importer.skipped.extend(unused);
+
+    Ok(Import {
+        program: Program::new(&program, importer.base, Metadata::new()),
+        skipped: importer.skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::program::OptKind;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn roles() {
+        assert_eq!(role("${COMP_WORDS[COMP_CWORD]}"), Role::Current);
+        assert_eq!(role("${COMP_WORDS[COMP_CWORD - 1]}"), Role::Previous);
+        assert_eq!(role("$prev"), Role::Previous);
+        assert_eq!(role("${words[2]}"), Role::Word(2));
+        assert_eq!(role("$COMP_CWORD"), Role::Index);
+        assert_eq!(role("$service"), Role::Other);
+    }
+
+    #[test]
+    fn function() {
+        let import = import(include_str!("../../../samples/bash/svc.bash"), None).unwrap();
+        let prog = import.program;
+        let base = &prog.base_command;
+        assert_eq!(prog.name, "svc");
+        assert!(base.arguments.is_empty());
+
+        let options: Vec<(Vec<String>, Vec<String>)> = base.options.iter().map(|o| (o.longs.clone(), o.shorts.clone())).collect();
+        assert_eq!(options, vec![
+            (strings(&["--config"]), strings(&["-c"])),
+            (strings(&["--log-level"]), vec![]),
+            (vec![], strings(&["-h"])),
+            (strings(&["--help"]), vec![]),
+            (vec![], strings(&["-v"])),
+            (strings(&["--verbose"]), vec![]),
+        ]);
+        assert_eq!(base.options[0].argkind, Some(OptKind::File));
+        assert_eq!(base.options[1].argkind, Some(OptKind::Choices(strings(&["debug", "info", "warn"]))));
+
+        let names: Vec<&str> = base.commands.iter().map(|c| &c.name[..]).collect();
+        assert_eq!(names, vec!["start", "stop", "status", "logs"]);
+        let start = prog.find(&["start"]).unwrap();
+        assert_eq!(start.arguments[0].kind, OptKind::Command("bash -c '_svc_units() {\n    \
+            systemctl list-units --plain --no-legend | cut -d'\\'' '\\'' -f1\n}\n_svc_units'".to_string()));
+        let logs = prog.find(&["logs"]).unwrap();
+        assert_eq!(logs.options.len(), 2);
+        assert_eq!(logs.arguments[0].kind, OptKind::Choices(strings(&["today", "yesterday"])));
+
+        assert_eq!(import.skipped, strings(&[
+            "line 47: completions for `svcctl`",
+            "line 37: `_svc_units` runs in a new bash with the functions it calls, without the COMP_* variables",
+            "line 43: `_known_hosts_real` isn't supported",
+        ]));
+    }
+
+    #[test]
+    fn complete_flags() {
+        let text = include_str!("../../../samples/bash/tools.bash");
+        let import = import(text, None).unwrap();
+        assert_eq!(import.program.name, "svcctl");
+        assert_eq!(import.program.base_command.arguments[0].kind,
+            OptKind::Choices(strings(&["start", "stop", "status"])));
+        assert_eq!(import.skipped.len(), 6);
+        assert_eq!(import.skipped[0], "line 4: completions for `confcheck`");
+
+        let import = super::import(text, Some("confcheck")).unwrap();
+        assert_eq!(import.program.base_command.arguments[0].kind, OptKind::FilePlus);
+        assert!(import.skipped.contains(&"line 4: filter `!*.conf`".to_string()));
+
+        let import = super::import(text, Some("chown-ish")).unwrap();
+        assert_eq!(import.program.base_command.arguments[0].kind,
+            OptKind::Command("bash -c 'compgen -A user'".to_string()));
+        assert!(import.skipped.contains(&"line 6: arguments of `chown-ish` are already completed by another rule".to_string()));
+
+        let skipped = |name| super::import(text, Some(name)).unwrap().skipped.into_iter()
+            .filter(|s| !s.contains("completions for"))
+            .collect::<Vec<_>>();
+        assert_eq!(skipped("unalias-ish"), strings(&["line 7: `alias` completions depend on the running shell"]));
+        assert_eq!(skipped("nothere"), strings(&["line 9: function `_missing` isn't defined here"]));
+        assert!(skipped("goto").is_empty());
+        assert_eq!(super::import(text, Some("deploy")).unwrap().program.base_command.arguments[0].kind,
+            OptKind::Command("deploy --list-targets".to_string()));
+    }
+}
@@ -0,0 +1,236 @@
+extern crate rustc_serialize;
+
+use self::rustc_serialize::json::Json;
+
+use completist::CompletistError;
+use completist::program::{Program, Command, Argument, Opt, OptKind, Metadata};
+use super::Import;
+
+fn string<'a>(value: &'a Json, key: &str) -> Option<&'a str> {
+    value.find(key).and_then(|v| v.as_string())
+}
+
+fn flag(value: &Json, key: &str) -> bool {
+    value.find(key).and_then(|v| v.as_boolean()).unwrap_or(false)
+}
+
+fn list<'a>(value: &'a Json, key: &str) -> &'a [Json] {
+    value.find(key).and_then(|v| v.as_array()).map_or(&[], |a| &a[..])
+}
+
+fn strings(value: &Json, key: &str) -> Vec<String> {
+    list(value, key).iter().filter_map(|v| v.as_string()).map(|v| v.to_string()).collect()
+}
+
+// Hints that other shells can list are run through bash's `compgen`, the same
+// as the bash importer does.
+fn hint_kind(hint: &str) -> Option<OptKind> {
+    match hint {
+        "" | "Unknown" | "AnyPath" | "FilePath" | "DirPath" | "ExecutablePath" => Some(OptKind::File),
+        "CommandName" => Some(OptKind::Command("bash -c 'compgen -c'".to_string())),
+        "Username" => Some(OptKind::Command("bash -c 'compgen -A user'".to_string())),
+        "Hostname" => Some(OptKind::Command("bash -c 'compgen -A hostname'".to_string())),
+        _ => None,
+    }
+}
+
+struct Importer {
+    skipped: Vec<String>,
+}
+
+impl Importer {
+    fn report(&mut self, message: String) {
+        if !self.skipped.contains(&message) {
+            self.skipped.push(message);
+        }
+    }
+
+    fn kind(&mut self, arg: &Json, path: &str, name: &str) -> OptKind {
+        let choices: Vec<String> = list(arg, "possible_values").iter()
+            .filter(|v| !flag(v, "hidden"))
+            .filter_map(|v| string(v, "name"))
+            .map(|v| v.to_string())
+            .collect();
+        if !choices.is_empty() {
+            return OptKind::Choices(choices);
+        }
+
+        let hint = string(arg, "value_hint").unwrap_or("");
+        match hint_kind(hint) {
+            Some(OptKind::File) if flag(arg, "multiple") && flag(arg, "positional") => OptKind::FilePlus,
+            Some(kind) => kind,
+            None => {
+                self.report(format!("`{}`: value hint `{}` of `{}` is completed as a file", path, hint, name));
+                OptKind::File
+            },
+        }
+    }
+
+    fn option(&mut self, arg: &Json, path: &str) -> Option<Opt> {
+        let mut longs: Vec<String> = string(arg, "long").into_iter().map(|l| format!("--{}", l)).collect();
+        longs.extend(strings(arg, "aliases").iter().map(|l| format!("--{}", l)));
+        let mut shorts: Vec<String> = string(arg, "short").into_iter().map(|s| format!("-{}", s)).collect();
+        shorts.extend(strings(arg, "short_aliases").iter().map(|s| format!("-{}", s)));
+
+        // Such an option can only be given by its position or an environment
+        // variable, which no shell completes.
+        let name = match longs.first().or(shorts.first()) {
+            Some(name) => name.clone(),
+            None => {
+                self.report(format!("`{}`: option `{}` has no long or short name", path, string(arg, "id").unwrap_or("")));
+                return None;
+            },
+        };
+        if flag(arg, "hidden") {
+            self.report(format!("`{}`: hidden option `{}`", path, name));
+            return None;
+        }
+        let argkind = if flag(arg, "takes_value") { Some(self.kind(arg, path, &name)) } else { None };
+        Some(Opt::new(longs, shorts, string(arg, "help").unwrap_or(""), argkind))
+    }
+
+    fn command<'a>(&mut self, value: &'a Json, name: &str, path: &[String], globals: &[&'a Json]) -> Command {
+        let mut path = path.to_vec();
+        path.push(name.to_string());
+        let shown = path.join(" ");
+        let mut command = Command::new(name);
+        command.description = string(value, "about").unwrap_or("").to_string();
+
+        let args = list(value, "args");
+        let ids: Vec<&str> = args.iter().filter_map(|a| string(a, "id")).collect();
+        // Globals are usually propagated already, but only if the dump was
+        // taken after the command was built.
+        let inherited = globals.iter().cloned().filter(|g| !string(g, "id").map_or(false, |id| ids.contains(&id)));
+        let mut globals: Vec<&Json> = globals.to_vec();
+
+        for arg in args.iter().chain(inherited) {
+            if flag(arg, "global") && !globals.iter().any(|g| string(g, "id") == string(arg, "id")) {
+                globals.push(arg);
+            }
+            if !flag(arg, "positional") {
+                if let Some(opt) = self.option(arg, &shown) {
+                    command.options.push(opt);
+                }
+                continue;
+            }
+
+            let id = string(arg, "id").unwrap_or("");
+            let name = strings(arg, "value_names").into_iter().next().unwrap_or_else(|| id.to_string());
+            if flag(arg, "hidden") {
+                self.report(format!("`{}`: hidden argument `{}`", shown, name));
+                continue;
+            }
+            let kind = self.kind(arg, &shown, &name);
+            command.arguments.push(Argument::new(&name, kind, !flag(arg, "required")));
+        }
+
+        for subcommand in list(value, "subcommands") {
+            let name = match string(subcommand, "name") {
+                Some(name) => name,
+                None => continue,
+            };
+            if flag(subcommand, "hidden") {
+                self.report(format!("`{}`: hidden subcommand `{}`", shown, name));
+                continue;
+            }
+            // Aliases are completed like the subcommand they stand for.
+            let mut names = vec![name.to_string()];
+            names.extend(strings(subcommand, "aliases"));
+            for name in names {
+                let subcommand = self.command(subcommand, &name, &path, &globals);
+                command.commands.push(subcommand);
+            }
+        }
+        command
+    }
+}
+
+pub fn import(text: &str, name: Option<&str>) -> Result<Import, CompletistError> {
+    let json = try!(Json::from_str(text).map_err(|e| CompletistError::ImportError(format!("{}", e))));
+    let program = match name.or_else(|| string(&json, "name")) {
+        Some(program) => program.to_string(),
+        None => return Err(CompletistError::ImportError("the command has no name".to_string())),
+    };
+
+    let mut importer = Importer { skipped: Vec::new() };
+    let command = importer.command(&json, &program, &[], &[]);
+    let mut meta = Metadata::new();
+    meta.version = string(&json, "version").map(|v| v.to_string());
+
+    Ok(Import {
+        program: Program::new(&program, command, meta),
+        skipped: importer.skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::program::OptKind;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn dump() {
+        let import = import(include_str!("../../../samples/clap/mytool.json"), None).unwrap();
+        let prog = import.program;
+        let base = &prog.base_command;
+        assert_eq!(prog.name, "mytool");
+        assert_eq!(base.description, "Manages remote mirrors");
+        assert_eq!(prog.meta.version, Some("1.2.0".to_string()));
+
+        let options: Vec<String> = base.options.iter().map(|o| o.name()).collect();
+        assert_eq!(options, strings(&["verbose", "config", "help", "version"]));
+        assert_eq!(base.options[1].longs, strings(&["--config", "--conf"]));
+        assert_eq!(base.options[1].shorts, strings(&["-c"]));
+        assert_eq!(base.options[1].argkind, Some(OptKind::File));
+
+        let names: Vec<&str> = base.commands.iter().map(|c| &c.name[..]).collect();
+        assert_eq!(names, vec!["sync", "s", "remote"]);
+        let sync = prog.find(&["s"]).unwrap();
+        assert_eq!(sync.description, "Copy changes to every mirror");
+        assert_eq!(sync.options[0].argkind, Some(OptKind::Command("bash -c 'compgen -A hostname'".to_string())));
+        assert_eq!(sync.options[1].argkind, Some(OptKind::Choices(strings(&["fast", "safe"]))));
+        assert_eq!(sync.arguments[0].name, "PATH");
+        assert_eq!(sync.arguments[0].kind, OptKind::FilePlus);
+        assert!(sync.arguments[0].optional);
+
+        let add = prog.find(&["remote", "add"]).unwrap();
+        assert!(!add.arguments[0].optional);
+        assert_eq!(add.options.len(), 2);
+
+        assert_eq!(import.skipped, strings(&[
+            "`mytool`: hidden option `--debug-dump`",
+            "`mytool remote add`: value hint `Url` of `URL` is completed as a file",
+            "`mytool`: hidden subcommand `internal`",
+        ]));
+    }
+
+    #[test]
+    fn globals() {
+        let json = r#"{
+            "name": "x",
+            "args": [{"id": "quiet", "short": "q", "global": true}],
+            "subcommands": [{"name": "y", "subcommands": [{"name": "z", "args": []}]}]
+        }"#;
+        let import = import(json, Some("renamed")).unwrap();
+        assert_eq!(import.program.name, "renamed");
+        let z = import.program.find(&["y", "z"]).unwrap();
+        assert_eq!(z.options[0].shorts, strings(&["-q"]));
+        assert_eq!(z.options[0].argkind, None);
+
+        assert!(super::import("{", None).is_err());
+    }
+
+    #[test]
+    fn nameless_options() {
+        let json = r#"{"name": "x", "args": [{"id": "token", "takes_value": true}, {"id": "all", "long": "all"}]}"#;
+        let import = import(json, None).unwrap();
+        let options: Vec<String> = import.program.base_command.options.iter().map(|o| o.name()).collect();
+        assert_eq!(options, strings(&["all"]));
+        assert_eq!(import.skipped, strings(&["`x`: option `token` has no long or short name"]));
+        assert!(super::import("{}", None).is_err());
+    }
+}
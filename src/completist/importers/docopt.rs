@@ -0,0 +1,540 @@
+use std::collections::HashMap;
+use std::slice;
+
+use completist::CompletistError;
+use completist::program::{Program, Command, Argument, Opt, OptKind, Metadata};
+use super::{Import, command_at};
+
+#[derive(Debug, PartialEq)]
+enum Element {
+    // A literal word: a subcommand, or a choice when it comes later on.
+    Word(String),
+    Argument(String),
+    // An option, and whether it carries its argument as `--x=<y>`.
+    Option(String, bool),
+    // The `[options]` shortcut.
+    Options,
+    // Alternatives, and whether the group is in square brackets.
+    Group(Vec<Vec<Element>>, bool),
+    Repeat(Box<Element>),
+}
+
+fn tokens(pattern: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => {
+                word.push(c);
+                while let Some(c) = chars.next() {
+                    word.push(c);
+                    if c == '>' {
+                        break;
+                    }
+                }
+                continue;
+            },
+            '[' | ']' | '(' | ')' | '|' | ' ' | '\t' => {},
+            '.' if word.is_empty() || word.ends_with('>') || word.chars().all(|c| c.is_uppercase()) => {
+                if chars.peek() == Some(&'.') {
+                    chars.next();
+                    chars.next();
+                    if !word.is_empty() {
+                        tokens.push(word);
+                        word = String::new();
+                    }
+                    tokens.push("...".to_string());
+                    continue;
+                }
+                word.push(c);
+                continue;
+            },
+            _ => {
+                word.push(c);
+                continue;
+            },
+        }
+        if !word.is_empty() {
+            tokens.push(word);
+            word = String::new();
+        }
+        if !c.is_whitespace() {
+            tokens.push(c.to_string());
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+fn element(token: &str) -> Option<Element> {
+    if token == "options" {
+        Some(Element::Options)
+    } else if token == "-" || token == "--" {
+        None
+    } else if token.starts_with('-') {
+        match token.find('=') {
+            Some(i) => Some(Element::Option(token[..i].to_string(), true)),
+            None => Some(Element::Option(token.to_string(), false)),
+        }
+    } else if token.starts_with('<') && token.ends_with('>') {
+        Some(Element::Argument(token[1..token.len() - 1].to_string()))
+    } else if token.chars().any(|c| c.is_alphabetic()) && !token.chars().any(|c| c.is_lowercase()) {
+        Some(Element::Argument(token.to_string()))
+    } else {
+        Some(Element::Word(token.to_string()))
+    }
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    // Alternatives up to the closing bracket, if there is one.
+    fn alternatives(&mut self, close: Option<&str>) -> Result<Vec<Vec<Element>>, String> {
+        let mut alternatives = vec![Vec::new()];
+        loop {
+            let token = match self.tokens.get(self.pos) {
+                Some(token) => token.clone(),
+                None => return match close {
+                    Some(close) => Err(format!("missing `{}`", close)),
+                    None => Ok(alternatives),
+                },
+            };
+            self.pos += 1;
+            let sequence = alternatives.last_mut().unwrap();
+            match &token[..] {
+                "|" => {
+                    alternatives.push(Vec::new());
+                    continue;
+                },
+                "]" | ")" => return match close {
+                    Some(close) if close == token => Ok(alternatives),
+                    _ => Err(format!("unexpected `{}`", token)),
+                },
+                "[" | "(" => {
+                    let close = if token == "[" { "]" } else { ")" };
+                    let inner = try!(self.alternatives(Some(close)));
+                    // `[options]` is the only group that isn't a group.
+                    if inner.len() == 1 && inner[0] == [Element::Options] {
+                        sequence.push(Element::Options);
+                    } else {
+                        sequence.push(Element::Group(inner, token == "["));
+                    }
+                },
+                "..." => match sequence.pop() {
+                    Some(last) => sequence.push(Element::Repeat(Box::new(last))),
+                    None => return Err("`...` follows nothing".to_string()),
+                },
+                _ => if let Some(element) = element(&token) {
+                    sequence.push(element);
+                },
+            }
+        }
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Result<Vec<Vec<Element>>, String> {
+    Parser { tokens: tokens(pattern), pos: 0 }.alternatives(None)
+}
+
+// Lines of the `Options:` section, such as `-o FILE --output=FILE  Write to FILE.`
+fn parse_option(line: &str) -> Option<Opt> {
+    let line = line.trim();
+    let (names, description) = match line.find("  ") {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    };
+    let mut longs = Vec::new();
+    let mut shorts = Vec::new();
+    let mut argument = false;
+    for name in names.split(|c| c == ',' || c == '=' || c == ' ').filter(|n| !n.is_empty()) {
+        if name.starts_with("--") {
+            longs.push(name.to_string());
+        } else if name.starts_with('-') && name.len() == 2 {
+            shorts.push(name.to_string());
+        } else if name.starts_with('-') {
+            return None;
+        } else {
+            argument = true;
+        }
+    }
+    if longs.is_empty() && shorts.is_empty() {
+        return None;
+    }
+    Some(Opt::new(longs, shorts, description, if argument { Some(OptKind::File) } else { None }))
+}
+
+fn copy_option(opt: &Opt) -> Opt {
+    Opt::new(opt.longs.clone(), opt.shorts.clone(), &opt.description, opt.argkind.clone())
+}
+
+fn has_name(opt: &Opt, name: &str) -> bool {
+    opt.longs.iter().chain(opt.shorts.iter()).any(|n| n == name)
+}
+
+struct Importer {
+    base: Command,
+    definitions: Vec<Opt>,
+    used: Vec<bool>,
+    // How many patterns each subcommand path has had so far.
+    patterns: HashMap<Vec<String>, usize>,
+    skipped: Vec<String>,
+}
+
+impl Importer {
+    // Adds the option to the command, returning whether it takes an argument.
+    fn option(&mut self, path: &[String], name: &str, argument: bool) -> bool {
+        let definition = self.definitions.iter().position(|d| has_name(d, name));
+        let command = command_at(&mut self.base, path);
+        if let Some(opt) = command.options.iter().find(|o| has_name(o, name)) {
+            return opt.argkind.is_some();
+        }
+        let opt = match definition {
+            Some(i) => {
+                self.used[i] = true;
+                copy_option(&self.definitions[i])
+            },
+            None if name.starts_with("--") => Opt::new(vec![name.to_string()], Vec::new(), "", None),
+            None => Opt::new(Vec::new(), vec![name.to_string()], "", None),
+        };
+        let mut opt = opt;
+        if argument && opt.argkind.is_none() {
+            opt.argkind = Some(OptKind::File);
+        }
+        let takes = opt.argkind.is_some();
+        command.options.push(opt);
+        takes
+    }
+
+    fn argument(&mut self, line: usize, path: &[String], index: usize, argument: Argument) {
+        let first = self.patterns.get(path).map_or(true, |&n| n == 0);
+        let command = command_at(&mut self.base, path);
+        if index >= command.arguments.len() {
+            let mut argument = argument;
+            // Arguments that earlier patterns went without are optional.
+            argument.optional = argument.optional || !first;
+            command.arguments.push(argument);
+            return;
+        }
+
+        let existing = &mut command.arguments[index];
+        existing.optional = existing.optional || argument.optional;
+        match (&mut existing.kind, argument.kind) {
+            (&mut OptKind::Choices(ref mut choices), OptKind::Choices(more)) => {
+                for choice in more {
+                    if !choices.contains(&choice) {
+                        choices.push(choice);
+                    }
+                }
+                existing.name = choices.join("|");
+            },
+            (kind, OptKind::FilePlus) if existing.name == argument.name => *kind = OptKind::FilePlus,
+            (_, _) if existing.name == argument.name => {},
+            (_, _) => self.skipped.push(format!("line {}: argument `{}` is in the place of `{}`",
+                line, argument.name, existing.name)),
+        }
+    }
+
+    // Adds a sequence of elements, returning the next argument position.
+    fn sequence(&mut self, line: usize, path: &[String], elements: &[Element], mut index: usize,
+            optional: bool, variadic: bool) -> usize {
+        let mut skip_argument = false;
+        for element in elements {
+            if skip_argument {
+                skip_argument = false;
+                if let Element::Argument(_) = *element {
+                    continue;
+                }
+            }
+            match *element {
+                Element::Option(ref name, argument) => {
+                    let names: Vec<String> = if name.starts_with("--") || name.len() <= 2 {
+                        vec![name.clone()]
+                    } else {
+                        name[1..].chars().map(|c| format!("-{}", c)).collect()
+                    };
+                    for name in names {
+                        skip_argument = self.option(path, &name, argument) && !argument;
+                    }
+                },
+                Element::Options => {
+                    let command = command_at(&mut self.base, path);
+                    for (i, definition) in self.definitions.iter().enumerate() {
+                        let names: Vec<&String> = definition.longs.iter().chain(definition.shorts.iter()).collect();
+                        if !command.options.iter().any(|o| names.iter().any(|n| has_name(o, n))) {
+                            command.options.push(copy_option(definition));
+                        }
+                        self.used[i] = true;
+                    }
+                },
+                Element::Word(ref word) => {
+                    let name = word.clone();
+                    self.argument(line, path, index, Argument::new(&name, OptKind::Choices(vec![name.clone()]), optional));
+                    index += 1;
+                },
+                Element::Argument(ref name) => {
+                    let kind = if variadic { OptKind::FilePlus } else { OptKind::File };
+                    self.argument(line, path, index, Argument::new(name, kind, optional));
+                    index += 1;
+                },
+                Element::Repeat(ref element) => {
+                    index = self.sequence(line, path, slice::from_ref(&**element), index, optional, true);
+                },
+                Element::Group(ref alternatives, group_optional) => {
+                    let optional = optional || group_optional;
+                    let words: Vec<String> = alternatives.iter()
+                        .filter_map(|a| match a.first() {
+                            Some(&Element::Word(ref word)) if a.len() == 1 => Some(word.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    if alternatives.len() > 1 && words.len() == alternatives.len() {
+                        self.argument(line, path, index, Argument::new(&words.join("|"), OptKind::Choices(words), optional));
+                        index += 1;
+                    } else if alternatives.len() == 1 {
+                        index = self.sequence(line, path, &alternatives[0], index, optional, variadic);
+                    } else {
+                        // Only options can be told apart without their position.
+                        for alternative in alternatives {
+                            for element in alternative {
+                                match *element {
+                                    Element::Option(..) | Element::Options =>
+                                        { self.sequence(line, path, slice::from_ref(element), index, true, false); },
+                                    _ => self.skipped.push(format!("line {}: alternative {:?}", line, element)),
+                                }
+                            }
+                        }
+                    }
+                },
+            }
+        }
+        index
+    }
+
+    fn pattern(&mut self, line: usize, elements: &[Element]) {
+        let mut path = Vec::new();
+        for element in elements {
+            match *element {
+                Element::Word(ref word) => path.push(word.clone()),
+                _ => break,
+            }
+        }
+        let rest = &elements[path.len()..];
+        command_at(&mut self.base, &path);
+        let count = self.sequence(line, &path, rest, 0, false, false);
+
+        let command = command_at(&mut self.base, &path);
+        for argument in command.arguments.iter_mut().skip(count) {
+            argument.optional = true;
+        }
+        *self.patterns.entry(path).or_insert(0) += 1;
+    }
+}
+
+pub fn import(text: &str, name: Option<&str>) -> Result<Import, CompletistError> {
+    let mut patterns: Vec<(usize, String)> = Vec::new();
+    let mut definitions: Vec<Opt> = Vec::new();
+    let mut in_usage = false;
+    let mut in_options = false;
+    let mut program: Option<String> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        let lower = trimmed.to_lowercase();
+        let indented = line.starts_with(' ') || line.starts_with('\t');
+        if trimmed.is_empty() {
+            in_usage = false;
+            in_options = false;
+            continue;
+        }
+
+        if let Some(i) = lower.find("usage:") {
+            in_usage = true;
+            in_options = false;
+            let rest = trimmed[i + 6..].trim();
+            if rest.is_empty() {
+                continue;
+            }
+            patterns.push((number + 1, rest.to_string()));
+        } else if lower.ends_with("options:") && !trimmed.starts_with('-') {
+            in_usage = false;
+            in_options = true;
+        } else if in_usage {
+            let first = trimmed.split_whitespace().next().map(|w| w.to_string());
+            let starts_pattern = match program {
+                Some(ref program) => first.as_ref() == Some(program),
+                None => true,
+            };
+            if starts_pattern || patterns.is_empty() {
+                patterns.push((number + 1, trimmed.to_string()));
+            } else {
+                let last = patterns.last_mut().unwrap();
+                last.1.push(' ');
+                last.1.push_str(trimmed);
+            }
+        } else if in_options && trimmed.starts_with('-') {
+            if let Some(opt) = parse_option(trimmed) {
+                definitions.push(opt);
+            }
+        } else if in_options && indented && !definitions.is_empty() {
+            let description = &mut definitions.last_mut().unwrap().description;
+            if !description.is_empty() {
+                description.push(' ');
+            }
+            description.push_str(trimmed);
+        } else {
+            in_options = false;
+        }
+
+        if program.is_none() {
+            program = patterns.first().and_then(|p| p.1.split_whitespace().next()).map(|w| w.to_string());
+        }
+    }
+
+    let program = match (name, program) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(program)) => program,
+        (None, None) => return Err(CompletistError::ImportError("no usage patterns were found".to_string())),
+    };
+
+    let mut importer = Importer {
+        base: Command::new(&program),
+        used: vec![false; definitions.len()],
+        definitions: definitions,
+        patterns: HashMap::new(),
+        skipped: Vec::new(),
+    };
+    for (line, pattern) in patterns {
+        let alternatives = match parse_pattern(&pattern) {
+            Ok(alternatives) => alternatives,
+            Err(message) => {
+                importer.skipped.push(format!("line {}: {}", line, message));
+                continue;
+            },
+        };
+        // Each top-level alternative is a pattern of its own, although only
+        // the first one repeats the program name.
+        for (i, mut elements) in alternatives.into_iter().enumerate() {
+            if i == 0 && !elements.is_empty() {
+                elements.remove(0);
+            }
+            importer.pattern(line, &elements);
+        }
+    }
+
+    // Options that no pattern mentions are still worth completing.
+    for (i, definition) in importer.definitions.iter().enumerate() {
+        if !importer.used[i] {
+            importer.base.options.push(copy_option(definition));
+        }
+    }
+
+    Ok(Import {
+        program: Program::new(&program, importer.base, Metadata::new()),
+        skipped: importer.skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::{Element, parse_pattern};
+    use completist::program::OptKind;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(parse_pattern("prog [-v] (a | b) <x>... [options]").unwrap(), vec![vec![
+            Element::Word("prog".to_string()),
+            Element::Group(vec![vec![Element::Option("-v".to_string(), false)]], true),
+            Element::Group(vec![vec![Element::Word("a".to_string())], vec![Element::Word("b".to_string())]], false),
+            Element::Repeat(Box::new(Element::Argument("x".to_string()))),
+            Element::Options,
+        ]]);
+        assert_eq!(parse_pattern("prog --speed=<kn> FILE...").unwrap(), vec![vec![
+            Element::Word("prog".to_string()),
+            Element::Option("--speed".to_string(), true),
+            Element::Repeat(Box::new(Element::Argument("FILE".to_string()))),
+        ]]);
+        assert_eq!(parse_pattern("prog -h | --help").unwrap().len(), 2);
+        assert!(parse_pattern("prog [<x>").is_err());
+        assert!(parse_pattern("prog <x>)").is_err());
+    }
+
+    #[test]
+    fn naval_fate() {
+        let import = import(include_str!("../../../samples/docopt/naval_fate.txt"), None).unwrap();
+        let prog = import.program;
+        assert_eq!(prog.name, "naval_fate");
+        assert!(import.skipped.is_empty());
+
+        let names: Vec<&str> = prog.base_command.options.iter().map(|o| &o.longs[0][..]).collect();
+        assert_eq!(names, vec!["--help", "--version"]);
+        assert_eq!(prog.base_command.options[0].shorts, strings(&["-h"]));
+
+        let new = prog.find(&["ship", "new"]).unwrap();
+        assert_eq!(new.arguments[0].name, "name");
+        assert_eq!(new.arguments[0].kind, OptKind::FilePlus);
+
+        let ship = prog.find(&["ship"]).unwrap();
+        let arguments: Vec<(&str, bool)> = ship.arguments.iter().map(|a| (&a.name[..], a.optional)).collect();
+        assert_eq!(arguments, vec![("name", false), ("move", false), ("x", false), ("y", false)]);
+        assert_eq!(ship.options[0].longs, strings(&["--speed"]));
+        assert_eq!(ship.options[0].argkind, Some(OptKind::File));
+        assert_eq!(ship.options[0].description, "Speed in knots [default: 10].");
+        let shoot = prog.find(&["ship", "shoot"]).unwrap();
+        assert_eq!(shoot.arguments.len(), 2);
+
+        let mine = prog.find(&["mine"]).unwrap();
+        assert_eq!(mine.arguments[0].kind, OptKind::Choices(strings(&["set", "remove"])));
+        assert_eq!(mine.options.len(), 2);
+        assert_eq!(mine.options[1].description, "Drifting mine.");
+    }
+
+    #[test]
+    fn docstring() {
+        let import = import(include_str!("../../../samples/docopt/backup.py"), None).unwrap();
+        let prog = import.program;
+        let base = &prog.base_command;
+        assert_eq!(prog.name, "backup");
+
+        let arguments: Vec<(&str, bool)> = base.arguments.iter().map(|a| (&a.name[..], a.optional)).collect();
+        assert_eq!(arguments, vec![("source", true), ("dest", true)]);
+        assert_eq!(base.arguments[0].kind, OptKind::FilePlus);
+        let options: Vec<String> = base.options.iter().map(|o| o.name()).collect();
+        assert_eq!(options, strings(&["verbose", "exclude", "c", "to", "keep", "version"]));
+        assert_eq!(base.options[1].shorts, strings(&["-x"]));
+        assert_eq!(base.options[1].description, "Skip files matching PATTERN, can be given more than once.");
+
+        let restore = prog.find(&["restore"]).unwrap();
+        assert_eq!(restore.options.len(), 1);
+        assert_eq!(restore.arguments.len(), 1);
+        assert_eq!(restore.arguments[0].name, "snapshot");
+        let prune = prog.find(&["prune"]).unwrap();
+        assert_eq!(prune.arguments[0].kind, OptKind::Choices(strings(&["daily", "weekly", "all"])));
+        assert!(prog.find(&["list"]).unwrap().arguments.is_empty());
+    }
+
+    #[test]
+    fn reports() {
+        let import = import("Usage: prog (<a> | b <c>)\n       prog <x> [(<y>\n", None).unwrap();
+        assert_eq!(import.skipped, strings(&[
+            "line 1: alternative Argument(\"a\")",
+            "line 1: alternative Word(\"b\")",
+            "line 1: alternative Argument(\"c\")",
+            "line 2: missing `)`",
+        ]));
+        assert!(import.program.base_command.arguments.is_empty());
+
+        let import = super::import("Usage:\n  prog <a>\n  prog <b>\n", None).unwrap();
+        assert_eq!(import.skipped, strings(&["line 3: argument `b` is in the place of `a`"]));
+    }
+}
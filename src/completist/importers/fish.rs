@@ -0,0 +1,632 @@
+use std::collections::HashSet;
+
+use completist::CompletistError;
+use completist::program::{Program, Command, Opt, OptKind, Cache, Metadata};
+use super::{Import, command_at, add_argument, with_functions};
+
+// Splits a fish script into statements of unquoted words, each along with
+// the line it starts on.
+fn statements(script: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut statements = Vec::new();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = script.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if !in_word && words.is_empty() && !"\n; \t#".contains(c) {
+            start = line;
+        }
+        match c {
+            '\n' | ';' | ' ' | '\t' => {
+                if in_word {
+                    words.push(word);
+                    word = String::new();
+                    in_word = false;
+                }
+                if c == '\n' || c == ';' {
+                    if !words.is_empty() {
+                        statements.push((start, words));
+                        words = Vec::new();
+                    }
+                }
+                if c == '\n' {
+                    line += 1;
+                }
+            },
+            '#' if !in_word => while chars.peek().map_or(false, |&c| c != '\n') {
+                chars.next();
+            },
+            '\\' => match chars.next() {
+                Some('\n') => line += 1,
+                Some('n') => { word.push('\n'); in_word = true },
+                Some('t') => { word.push('\t'); in_word = true },
+                Some(c) => { word.push(c); in_word = true },
+                None => {},
+            },
+            '\'' | '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some('\\') => match chars.peek().cloned() {
+                            Some(e) if e == c || e == '\\' || (c == '"' && e == '$') => {
+                                word.push(e);
+                                chars.next();
+                            },
+                            _ => word.push('\\'),
+                        },
+                        Some(q) => {
+                            if q == '\n' {
+                                line += 1;
+                            }
+                            word.push(q);
+                        },
+                        None => return Err(format!("line {}: unterminated quote", start)),
+                    }
+                }
+            },
+            '(' => {
+                // Command substitutions are kept whole, parentheses and all.
+                in_word = true;
+                word.push(c);
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        Some(c) => {
+                            match c {
+                                '(' => depth += 1,
+                                ')' => depth -= 1,
+                                '\n' => line += 1,
+                                _ => {},
+                            }
+                            word.push(c);
+                        },
+                        None => return Err(format!("line {}: unterminated command substitution", start)),
+                    }
+                }
+            },
+            c => {
+                word.push(c);
+                in_word = true;
+            },
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        statements.push((start, words));
+    }
+    Ok(statements)
+}
+
+const LONG_FLAGS: &'static [(&'static str, char)] = &[
+    ("command", 'c'), ("path", 'p'), ("short-option", 's'), ("long-option", 'l'),
+    ("old-option", 'o'), ("description", 'd'), ("arguments", 'a'), ("condition", 'n'),
+    ("require-parameter", 'r'), ("no-files", 'f'), ("force-files", 'F'), ("exclusive", 'x'),
+    ("keep-order", 'k'), ("wraps", 'w'), ("erase", 'e'), ("do-complete", 'C'),
+];
+
+fn takes_value(flag: char) -> bool {
+    "cpslodanw".contains(flag)
+}
+
+struct Entry {
+    command: Option<String>,
+    longs: Vec<String>,
+    shorts: Vec<String>,
+    description: String,
+    arguments: Option<String>,
+    condition: String,
+    takes_value: bool,
+    files: bool,
+}
+
+fn parse_complete(words: &[String]) -> Result<Entry, String> {
+    let mut entry = Entry {
+        command: None,
+        longs: Vec::new(),
+        shorts: Vec::new(),
+        description: String::new(),
+        arguments: None,
+        condition: String::new(),
+        takes_value: false,
+        files: false,
+    };
+    let mut no_files = false;
+
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        let mut flags = Vec::new();
+        if word.starts_with("--") {
+            let (name, value) = match word.find('=') {
+                Some(i) => (&word[2..i], Some(word[i + 1..].to_string())),
+                None => (&word[2..], None),
+            };
+            match LONG_FLAGS.iter().find(|&&(long, _)| long == name) {
+                Some(&(_, flag)) => flags.push((flag, value)),
+                None => return Err(format!("unknown flag `{}`", word)),
+            }
+        } else if word.starts_with('-') && word.len() > 1 {
+            for (i, flag) in word.char_indices().skip(1) {
+                if takes_value(flag) {
+                    let rest = &word[i + flag.len_utf8()..];
+                    flags.push((flag, if rest.is_empty() { None } else { Some(rest.to_string()) }));
+                    break;
+                }
+                flags.push((flag, None));
+            }
+        } else {
+            return Err(format!("unexpected word `{}`", word));
+        }
+
+        for (flag, value) in flags {
+            let value = match value {
+                Some(value) => value,
+                None if takes_value(flag) => match words.next() {
+                    Some(value) => value.clone(),
+                    None => return Err(format!("-{} is missing its value", flag)),
+                },
+                None => String::new(),
+            };
+            match flag {
+                'c' | 'p' => entry.command = value.rsplit('/').next().map(|c| c.to_string()),
+                's' => entry.shorts.push(format!("-{}", value.trim_start_matches('-'))),
+                'l' => entry.longs.push(format!("--{}", value.trim_start_matches('-'))),
+                'o' => entry.longs.push(format!("-{}", value.trim_start_matches('-'))),
+                'd' => entry.description = value,
+                'a' => entry.arguments = Some(value),
+                'n' => entry.condition = value,
+                'r' => entry.takes_value = true,
+                'x' => { entry.takes_value = true; no_files = true },
+                'f' => no_files = true,
+                'F' => entry.files = true,
+                'k' => {},
+                'w' => return Err(format!("wrapping `{}` isn't supported", value)),
+                'e' => return Err("erasing completions isn't supported".to_string()),
+                _ => return Err(format!("unknown flag `-{}`", flag)),
+            }
+        }
+    }
+
+    entry.files = entry.files || (entry.takes_value && !no_files);
+    Ok(entry)
+}
+
+struct Condition {
+    paths: Vec<Vec<String>>,
+    // Whether the candidates are the subcommands of the command at `paths`.
+    listing: bool,
+    children: Vec<String>,
+    ignored: Vec<String>,
+}
+
+// Understands the subcommand tests that fish's own completions use, as well
+// as the `__fish_at_level` helpers that completist's fish scripts define.
+fn parse_condition(condition: &str) -> Result<Condition, String> {
+    let mut result = Condition {
+        paths: vec![Vec::new()],
+        listing: false,
+        children: Vec::new(),
+        ignored: Vec::new(),
+    };
+
+    for (_, words) in try!(statements(condition)) {
+        if words.iter().any(|w| w == "or" || w == "||") {
+            return Err(format!("alternatives in condition `{}` aren't supported", condition));
+        }
+        for clause in words.split(|w| w == "and" || w == "&&") {
+            let negated = clause.first().map_or(false, |w| w == "not" || w == "!");
+            let (function, args) = match clause[if negated { 1 } else { 0 }..].split_first() {
+                Some((function, args)) => (&function[..], args),
+                None => continue,
+            };
+
+            match function {
+                "__fish_seen_subcommand_from" if negated => {
+                    result.listing = true;
+                    result.children.extend(args.iter().cloned());
+                },
+                "__fish_seen_subcommand_from" => {
+                    result.paths = result.paths.iter()
+                        .flat_map(|path| args.iter().map(move |arg| {
+                            let mut path = path.clone();
+                            path.push(arg.clone());
+                            path
+                        }))
+                        .collect();
+                },
+                "__fish_use_subcommand" | "__fish_is_first_arg" | "__fish_is_first_token" if !negated => {
+                    result.listing = true;
+                },
+                _ if function.starts_with("__fish_at_level") && !negated => {
+                    // Commands before the empty argument are the level, and
+                    // those after it are the subcommands that leave it.
+                    let mut parts = args.split(|a| a.is_empty());
+                    let mut level = parts.next().unwrap_or(&[]).to_vec();
+                    if function.starts_with("__fish_at_level_")
+                            && level.first().map(|l| &l[..]) == Some(&function["__fish_at_level_".len()..]) {
+                        level.remove(0);
+                    }
+                    result.children.extend(parts.flat_map(|p| p.iter().cloned()));
+                    for path in &mut result.paths {
+                        path.extend(level.iter().cloned());
+                    }
+                },
+                _ => result.ignored.push(clause.join(" ")),
+            }
+        }
+    }
+    Ok(result)
+}
+
+enum Candidates {
+    Command(String, Option<Cache>),
+    Words(Vec<(String, Option<String>)>),
+}
+
+fn parse_candidates(arguments: &str) -> Result<Candidates, String> {
+    let arguments = arguments.trim();
+    if arguments.starts_with('(') && arguments.ends_with(')') {
+        let inner = &arguments[1..arguments.len() - 1];
+        // Undo the wrapper that completist's own fish scripts call commands
+        // through, recovering the command and its cache.
+        let words = try!(statements(inner)).into_iter().next().map(|(_, w)| w).unwrap_or_else(Vec::new);
+        if words.first().map_or(false, |w| w.starts_with("__fish_completist_run_")) {
+            if let Some(i) = words.iter().position(|w| w == "--") {
+                let settings = &words[1..i];
+                let cache = match settings.first().and_then(|ttl| ttl.parse::<u64>().ok()) {
+                    Some(ttl) if settings.len() >= 2 => {
+                        let mut cache = Cache::new(ttl);
+                        cache.per_directory = settings[1] == "1";
+                        cache.watch = settings[2..].to_vec();
+                        Some(cache)
+                    },
+                    _ => None,
+                };
+                if let Some(cmd) = words.get(i + 1) {
+                    return Ok(Candidates::Command(cmd.clone(), cache));
+                }
+            }
+        }
+        return Err(inner.to_string());
+    }
+
+    let words = try!(statements(arguments)).into_iter()
+        .flat_map(|(_, words)| words.into_iter())
+        .map(|word| {
+            let mut parts = word.splitn(2, '\t');
+            let candidate = parts.next().unwrap().to_string();
+            (candidate, parts.next().map(|d| d.to_string()))
+        })
+        .collect();
+    Ok(Candidates::Words(words))
+}
+
+fn add_option(command: &mut Command, entry: &Entry, argkind: Option<OptKind>, cache: Option<Cache>) {
+    let existing = command.options.iter_mut()
+        .find(|o| o.longs.iter().any(|l| entry.longs.contains(l)) || o.shorts.iter().any(|s| entry.shorts.contains(s)));
+    match existing {
+        Some(opt) => {
+            for long in &entry.longs {
+                if !opt.longs.contains(long) {
+                    opt.longs.push(long.clone());
+                }
+            }
+            for short in &entry.shorts {
+                if !opt.shorts.contains(short) {
+                    opt.shorts.push(short.clone());
+                }
+            }
+            if opt.description.is_empty() {
+                opt.description = entry.description.clone();
+            }
+            if opt.argkind.is_none() {
+                opt.argkind = argkind;
+                opt.cache = cache;
+            }
+        },
+        None => {
+            let mut opt = Opt::new(entry.longs.clone(), entry.shorts.clone(), &entry.description, argkind);
+            opt.cache = cache;
+            command.options.push(opt);
+        },
+    }
+}
+
+pub fn import(script: &str, name: Option<&str>) -> Result<Import, CompletistError> {
+    let mut skipped = Vec::new();
+    let mut entries = Vec::new();
+    let mut depth = 0;
+    // The functions of the script with their source, and the one being read.
+    let lines: Vec<&str> = script.lines().collect();
+    let mut functions = Vec::new();
+    let mut function = None;
+
+    for (line, words) in try!(statements(script).map_err(CompletistError::ImportError)) {
+        let first = &words[0][..];
+        if depth > 0 {
+            match first {
+                "function" | "if" | "for" | "while" | "switch" | "begin" => depth += 1,
+                "end" => depth -= 1,
+                _ => {},
+            }
+            if depth == 0 {
+                if let Some((name, start)) = function.take() {
+                    functions.push((name, start, lines[start - 1..line].join("\n")));
+                }
+            }
+            continue;
+        }
+        match first {
+            "complete" => match parse_complete(&words[1..]).and_then(|e| parse_condition(&e.condition).map(|c| (e, c))) {
+                Ok(entry) => entries.push((line, entry)),
+                Err(message) => skipped.push(format!("line {}: {}", line, message)),
+            },
+            "function" => {
+                depth += 1;
+                let name = words.get(1).map(|f| &f[..]).unwrap_or("");
+                if !name.starts_with("__fish_at_level") && !name.starts_with("__fish_completist_run_") {
+                    function = Some((name.to_string(), line));
+                }
+            },
+            "if" | "for" | "while" | "switch" | "begin" => {
+                depth += 1;
+                skipped.push(format!("line {}: `{}` block", line, first));
+            },
+            _ => skipped.push(format!("line {}: `{}` isn't a complete command", line, words.join(" "))),
+        }
+    }
+
+    let program = match name.map(|n| n.to_string()).or_else(|| entries.iter().filter_map(|e| (e.1).0.command.clone()).next()) {
+        Some(program) => program,
+        None => return Err(CompletistError::ImportError("no complete commands to take the program name from".to_string())),
+    };
+
+    // Words that conditions treat as subcommands, keyed by their parent.
+    let mut known = HashSet::new();
+    for &(_, (_, ref condition)) in &entries {
+        for path in &condition.paths {
+            for (i, name) in path.iter().enumerate() {
+                known.insert((path[..i].to_vec(), name.clone()));
+            }
+            for child in &condition.children {
+                known.insert((path.clone(), child.clone()));
+            }
+        }
+    }
+
+    let sources: Vec<(String, String)> = functions.iter().map(|&(ref name, _, ref source)| (name.clone(), source.clone())).collect();
+    let mut used = HashSet::new();
+    let mut base = Command::new(&program);
+    for (line, (entry, condition)) in entries {
+        if entry.command.as_ref().map_or(false, |c| *c != program) {
+            skipped.push(format!("line {}: completions for `{}`", line, entry.command.unwrap()));
+            continue;
+        }
+        for clause in &condition.ignored {
+            skipped.push(format!("line {}: condition `{}`", line, clause));
+        }
+
+        let candidates = match entry.arguments {
+            Some(ref arguments) => match parse_candidates(arguments) {
+                Ok(candidates) => Some(candidates),
+                Err(code) => {
+                    let (command, calls) = with_functions("fish", &code, &sources);
+                    if calls.is_empty() {
+                        skipped.push(format!("line {}: `{}` is fish code, kept as a command that sh will run", line, code));
+                    } else {
+                        skipped.push(format!("line {}: `{}` is fish code, kept as a command that fish will run \
+                            with the functions it calls", line, code));
+                    }
+                    used.extend(calls);
+                    Some(Candidates::Command(command, None))
+                },
+            },
+            None => None,
+        };
+
+        for path in &condition.paths {
+            let command = command_at(&mut base, path);
+            if !entry.longs.is_empty() || !entry.shorts.is_empty() {
+                let (argkind, cache) = match candidates {
+                    Some(Candidates::Command(ref cmd, ref cache)) => (Some(OptKind::Command(cmd.clone())), cache.clone()),
+                    Some(Candidates::Words(ref words)) =>
+                        (Some(OptKind::Choices(words.iter().map(|w| w.0.clone()).collect())), None),
+                    None if entry.takes_value => (Some(OptKind::File), None),
+                    None => (None, None),
+                };
+                add_option(command, &entry, argkind, cache);
+                continue;
+            }
+
+            let result = match candidates {
+                Some(Candidates::Command(ref cmd, ref cache)) =>
+                    add_argument(command, OptKind::Command(cmd.clone()), cache.clone()),
+                Some(Candidates::Words(ref words)) => {
+                    let mut choices = Vec::new();
+                    for &(ref word, ref description) in words {
+                        if word.starts_with('-') {
+                            skipped.push(format!("line {}: candidate `{}` looks like an option", line, word));
+                        } else if condition.listing || known.contains(&(path.clone(), word.clone())) {
+                            let sub = command_at(command, &[word.clone()]);
+                            if sub.description.is_empty() {
+                                sub.description = description.clone().unwrap_or_else(|| entry.description.clone());
+                            }
+                        } else {
+                            if description.is_some() {
+                                skipped.push(format!("line {}: description of `{}`", line, word));
+                            }
+                            choices.push(word.clone());
+                        }
+                    }
+                    if !choices.is_empty() && !entry.description.is_empty() {
+                        skipped.push(format!("line {}: description `{}`", line, entry.description));
+                    }
+                    if choices.is_empty() { Ok(()) } else { add_argument(command, OptKind::Choices(choices), None) }
+                },
+                None if entry.files => add_argument(command, OptKind::FilePlus, None),
+                None => Ok(()),
+            };
+            if let Err(message) = result {
+                skipped.push(format!("line {}: {}", line, message));
+            }
+        }
+    }
+
+    for (name, line, _) in functions {
+        if !used.contains(&name) {
+            skipped.push(format!("line {}: function `{}`", line, name));
+        }
+    }
+
+    Ok(Import {
+        program: Program::new(&program, base, Metadata::new()),
+        skipped: skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::program::OptKind;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn tokenising() {
+        let script = "complete -c 'my prog' -d \"it's \\\"here\\\"\" # comment\n\
+            complete -c x \\\n    -a '(ls \\'a b\\')'; end\n\
+            foo \"\" a\\ b (echo (x))";
+        let statements = statements(script).unwrap();
+        assert_eq!(statements, vec![
+            (1, strings(&["complete", "-c", "my prog", "-d", "it's \"here\""])),
+            (2, strings(&["complete", "-c", "x", "-a", "(ls 'a b')"])),
+            (3, strings(&["end"])),
+            (4, strings(&["foo", "", "a b", "(echo (x))"])),
+        ]);
+        assert!(super::statements("complete -d 'oops").is_err());
+    }
+
+    #[test]
+    fn conditions() {
+        let condition = parse_condition("__fish_seen_subcommand_from a b; and __fish_seen_subcommand_from c").unwrap();
+        assert_eq!(condition.paths, vec![strings(&["a", "c"]), strings(&["b", "c"])]);
+        let condition = parse_condition("__fish_at_level_cargo cargo build '' x").unwrap();
+        assert_eq!(condition.paths, vec![strings(&["build"])]);
+        assert_eq!(condition.children, strings(&["x"]));
+        let condition = parse_condition("not __fish_seen_subcommand_from a b && test -n x").unwrap();
+        assert!(condition.listing);
+        assert_eq!(condition.ignored, strings(&["test -n x"]));
+        assert!(parse_condition("a; or b").is_err());
+    }
+
+    #[test]
+    fn flags() {
+        let entry = parse_complete(&strings(&["-c", "prog", "-sA", "--long-option=all", "-rfd", "desc"])).unwrap();
+        assert_eq!(entry.command, Some("prog".to_string()));
+        assert_eq!(entry.shorts, strings(&["-A"]));
+        assert_eq!(entry.longs, strings(&["--all"]));
+        assert_eq!(entry.description, "desc");
+        assert!(entry.takes_value && !entry.files);
+        assert!(parse_complete(&strings(&["-c", "prog", "-w", "git"])).is_err());
+    }
+
+    #[test]
+    fn generated() {
+        let import = import(include_str!("../../../samples/cat.fish"), None).unwrap();
+        let base = &import.program.base_command;
+        assert_eq!(import.program.name, "cat");
+        assert_eq!(base.options.len(), 2);
+        assert_eq!(base.options[0].longs, strings(&["--show-all"]));
+        assert_eq!(base.options[0].shorts, strings(&["-A"]));
+        assert_eq!(base.options[1].description, "number nonempty output lines, overrides -n");
+        assert!(base.arguments.is_empty());
+        assert_eq!(import.skipped, strings(&["line 1: candidate `--` looks like an option"]));
+    }
+
+    #[test]
+    fn levels() {
+        let import = import(include_str!("../../../samples/fish_completion.fish"), None).unwrap();
+        let prog = import.program;
+        assert_eq!(prog.name, "myprog");
+        let opt = &prog.base_command.options[0];
+        assert_eq!(opt.longs, strings(&["--my-opt"]));
+        assert_eq!(opt.argkind, Some(OptKind::Choices(strings(&["open", "closed"]))));
+
+        let names: Vec<&str> = prog.base_command.commands.iter().map(|c| &c.name[..]).collect();
+        assert_eq!(names, vec!["foo", "bar"]);
+        let foo = prog.find(&["foo"]).unwrap();
+        assert_eq!(foo.description, "foo command");
+        assert_eq!(foo.arguments[0].kind, OptKind::Choices(strings(&["on", "off"])));
+        let subfoo = prog.find(&["foo", "subfoo"]).unwrap();
+        assert_eq!(subfoo.arguments[0].kind, OptKind::FilePlus);
+        assert_eq!(subfoo.options[0].argkind, Some(OptKind::Choices(strings(&["x", "y"]))));
+        assert_eq!(prog.find(&["bar", "mitzvah"]).unwrap().description, "do mitzvah");
+        assert_eq!(import.skipped, strings(&["line 63: description `turn myprog foo on/off`"]));
+    }
+
+    #[test]
+    fn hand_written() {
+        let import = import(include_str!("../../../samples/fish/svc.fish"), None).unwrap();
+        let prog = import.program;
+        let base = &prog.base_command;
+        assert_eq!(prog.name, "svc");
+        assert!(base.arguments.is_empty());
+        assert_eq!(base.options.len(), 5);
+        assert_eq!(base.options[2].argkind, Some(OptKind::File));
+        assert_eq!(base.options[3].argkind, Some(OptKind::Choices(strings(&["debug", "info", "warn"]))));
+        assert_eq!(base.options[4].argkind, Some(OptKind::Command("printf '%s\\n' auto always never".to_string())));
+
+        let commands: Vec<(&str, &str)> = base.commands.iter().map(|c| (&c.name[..], &c.description[..])).collect();
+        assert_eq!(commands, vec![("start", "Start a service"), ("stop", "Stop a service"),
+            ("logs", "Show the log of a service")]);
+        let start = prog.find(&["start"]).unwrap();
+        assert_eq!(start.arguments[0].kind, OptKind::Command("fish -c 'function __svc_units\n    \
+            systemctl list-units --plain --no-legend | string replace -r '\\'' .*'\\'' '\\'''\\''\n\
+            end\n__svc_units'".to_string()));
+        assert_eq!(start.options[0].longs, strings(&["--wait"]));
+        let logs = prog.find(&["logs"]).unwrap();
+        assert_eq!(logs.options[0].shorts, strings(&["-n"]));
+        assert_eq!(logs.options[0].argkind, Some(OptKind::File));
+        assert_eq!(logs.arguments[0].kind, OptKind::Choices(strings(&["today", "yesterday"])));
+
+        assert_eq!(import.skipped, strings(&[
+            "line 27: wrapping `svc` isn't supported",
+            "line 28: `set -l svc_cache ~/.cache/svc` isn't a complete command",
+            "line 12: `printf '%s\\n' auto always never` is fish code, kept as a command that sh will run",
+            "line 19: `__svc_units` is fish code, kept as a command that fish will run with the functions it calls",
+            "line 21: condition `__fish_contains_opt follow`",
+            "line 24: description `When from`",
+            "line 25: candidate `-` looks like an option",
+        ]));
+    }
+
+    #[test]
+    fn unused_functions() {
+        let script = "function __x\n    echo x\nend\ncomplete -c x -a '(ls)'";
+        let import = import(script, None).unwrap();
+        assert_eq!(import.program.base_command.arguments[0].kind, OptKind::Command("ls".to_string()));
+        assert_eq!(import.skipped, strings(&[
+            "line 4: `ls` is fish code, kept as a command that sh will run",
+            "line 1: function `__x`",
+        ]));
+    }
+
+    #[test]
+    fn cached_commands() {
+        let script = "complete -c cargo -n '__fish_at_level_cargo cargo run' -l bin -x \
+            -a '(__fish_completist_run_cargo 60 1 Cargo.toml -- \\'ls src/bin\\')'";
+        let import = import(script, None).unwrap();
+        let opt = &import.program.find(&["run"]).unwrap().options[0];
+        assert_eq!(opt.argkind, Some(OptKind::Command("ls src/bin".to_string())));
+        let cache = opt.cache.as_ref().unwrap();
+        assert_eq!((cache.ttl, cache.per_directory, &cache.watch[..]), (60, true, &strings(&["Cargo.toml"])[..]));
+        assert!(import.skipped.is_empty());
+    }
+}
@@ -0,0 +1,364 @@
+extern crate regex;
+
+use completist::CompletistError;
+use completist::program::{Program, Command, Opt, OptKind, Metadata};
+use super::Import;
+use super::shell;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Takes {
+    Nothing,
+    Required,
+    Optional,
+}
+
+// `ab:c::`, skipping the leading `+`, `-` and `:` that only change how
+// getopt behaves.
+fn parse_optstring(optstring: &str) -> Vec<(char, Takes)> {
+    let chars: Vec<char> = optstring.trim_start_matches(|c| "+-:".contains(c)).chars().collect();
+    let mut shorts = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        let mut takes = Takes::Nothing;
+        if chars.get(i) == Some(&':') {
+            takes = Takes::Required;
+            i += 1;
+            if chars.get(i) == Some(&':') {
+                takes = Takes::Optional;
+                i += 1;
+            }
+        } else if chars.get(i) == Some(&';') {
+            i += 1;
+        }
+        shorts.push((c, takes));
+    }
+    shorts
+}
+
+// getopt(1) long options, as in `verbose,output:,color::`.
+fn parse_longs(list: &str) -> Vec<(String, Takes)> {
+    list.split(|c| c == ',' || c == ' ')
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let takes = if name.ends_with("::") {
+                Takes::Optional
+            } else if name.ends_with(':') {
+                Takes::Required
+            } else {
+                Takes::Nothing
+            };
+            (name.trim_end_matches(':').to_string(), takes)
+        })
+        .collect()
+}
+
+struct Long {
+    line: usize,
+    name: String,
+    takes: Takes,
+    // The short option that getopt_long returns for it, if any.
+    short: Option<char>,
+}
+
+struct Source {
+    optstring: Option<String>,
+    longs: Vec<Long>,
+    name: Option<String>,
+    skipped: Vec<String>,
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count() + 1
+}
+
+// Adjacent C string literals, joined together.
+fn literal(text: &str) -> String {
+    let re = regex::Regex::new(r#""((?:[^"\\]|\\.)*)""#).unwrap();
+    re.captures_iter(text).filter_map(|c| c.at(1)).collect()
+}
+
+// The entries of the initializer starting at `start`, with their offsets.
+fn entries(text: &str, start: usize) -> Vec<(usize, &str)> {
+    let mut entries = Vec::new();
+    let mut depth = 0;
+    let mut begin = start;
+    for (i, c) in text[start..].char_indices() {
+        let i = start + i;
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => {
+                entries.push((begin, &text[begin..i]));
+                break;
+            },
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                entries.push((begin, &text[begin..i]));
+                begin = i + 1;
+            },
+            _ => {},
+        }
+    }
+    entries.into_iter()
+        .map(|(offset, entry)| (offset + entry.len() - entry.trim_start().len(), entry.trim()))
+        .filter(|&(_, entry)| !entry.is_empty())
+        .collect()
+}
+
+fn c_source(text: &str) -> Source {
+    let string = r#"(?:"(?:[^"\\]|\\.)*"\s*)+"#;
+    let callre = regex::Regex::new(&format!(
+        r"\bgetopt(?:_long(?:_only)?)?\s*\(\s*[^,]+,\s*[^,]+,\s*({}|\w+)", string)).unwrap();
+    let tablere = regex::Regex::new(r"struct\s+option\s+(?:const\s+)?\w+\s*\[\s*\]\s*=\s*\{").unwrap();
+    let entryre = regex::Regex::new(
+        r#"^\{\s*"([^"]+)"\s*,\s*(\w+)\s*,\s*([^,]+?)\s*,\s*([^,]+?)\s*\}$"#).unwrap();
+    let usagere = regex::Regex::new(r"[Uu]sage:\s*([A-Za-z][\w.-]*)").unwrap();
+
+    let mut source = Source { optstring: None, longs: Vec::new(), name: None, skipped: Vec::new() };
+    for (i, call) in callre.captures_iter(text).enumerate() {
+        let line = line_of(text, call.pos(0).unwrap().0);
+        if i > 0 {
+            source.skipped.push(format!("line {}: another getopt call", line));
+            continue;
+        }
+        let argument = call.at(1).unwrap();
+        if argument.starts_with('"') {
+            source.optstring = Some(literal(argument));
+            continue;
+        }
+        // The optstring is usually a variable or a macro.
+        let definition = regex::Regex::new(&format!(
+            r"\b{}\s*(?:\[\s*\]\s*=|=)?\s*({})", argument, string)).unwrap();
+        match definition.captures(text).and_then(|c| c.at(1)) {
+            Some(value) => source.optstring = Some(literal(value)),
+            None => source.skipped.push(format!("line {}: optstring `{}` isn't defined here", line, argument)),
+        }
+    }
+
+    if let Some((_, start)) = tablere.find(text) {
+        for (offset, entry) in entries(text, start) {
+            let line = line_of(text, offset);
+            let captures = match entryre.captures(entry) {
+                Some(captures) => captures,
+                None => {
+                    let fields: Vec<&str> = entry.trim_matches(|c| c == '{' || c == '}').split(',').map(|f| f.trim()).collect();
+                    if !fields.iter().all(|f| ["0", "NULL", "nullptr"].contains(f)) {
+                        source.skipped.push(format!("line {}: option entry `{}`", line, entry));
+                    }
+                    continue;
+                },
+            };
+            let takes = match captures.at(2).unwrap() {
+                "no_argument" | "0" => Takes::Nothing,
+                "required_argument" | "1" => Takes::Required,
+                "optional_argument" | "2" => Takes::Optional,
+                other => {
+                    source.skipped.push(format!("line {}: argument type `{}`", line, other));
+                    Takes::Nothing
+                },
+            };
+            let flag = captures.at(3).unwrap();
+            let val = captures.at(4).unwrap();
+            // With a flag pointer, `val` is stored there instead of returned.
+            let short = if ["NULL", "0", "nullptr"].contains(&flag) && val.len() == 3 && val.starts_with('\'') {
+                val.chars().nth(1)
+            } else {
+                None
+            };
+            source.longs.push(Long { line: line, name: captures.at(1).unwrap().to_string(), takes: takes, short: short });
+        }
+    }
+
+    source.name = usagere.captures(text).and_then(|c| c.at(1)).map(|n| n.to_string());
+    source
+}
+
+// A getopt(1) command line, or just an optstring followed by long options.
+fn command_line(text: &str) -> Source {
+    let mut source = Source { optstring: None, longs: Vec::new(), name: None, skipped: Vec::new() };
+    let (line, start) = match text.lines().enumerate().filter_map(|(i, l)| l.find("getopt ").map(|s| (i + 1, &l[s..]))).next() {
+        Some(found) => found,
+        None => {
+            for (i, line) in text.lines().enumerate() {
+                for list in line.split_whitespace() {
+                    if source.optstring.is_none() {
+                        source.optstring = Some(list.to_string());
+                        continue;
+                    }
+                    source.longs.extend(parse_longs(list).into_iter()
+                        .map(|(name, takes)| Long { line: i + 1, name: name, takes: takes, short: None }));
+                }
+            }
+            return source;
+        },
+    };
+
+    let words = match shell::words(start) {
+        Ok(words) => words,
+        Err(message) => {
+            source.skipped.push(format!("line {}: {}", line, message));
+            return source;
+        },
+    };
+    let mut words = words.into_iter().skip(1);
+    while let Some(word) = words.next() {
+        let (flag, value) = match word.find('=') {
+            Some(i) if word.starts_with("--") => (word[..i].to_string(), Some(word[i + 1..].to_string())),
+            _ if !word.starts_with("--") && word.len() > 2 => (word[..2].to_string(), Some(word[2..].to_string())),
+            _ => (word.clone(), None),
+        };
+        let takes_value = ["-o", "--options", "-l", "--long", "--longoptions", "-n", "--name", "-s", "--shell"];
+        if flag == "--" {
+            break;
+        } else if !takes_value.contains(&&flag[..]) {
+            continue;
+        }
+        let value = match value.or_else(|| words.next()) {
+            Some(value) => value,
+            None => break,
+        };
+        match &flag[..] {
+            "-o" | "--options" => source.optstring = Some(value),
+            "-l" | "--long" | "--longoptions" => source.longs.extend(parse_longs(&value).into_iter()
+                .map(|(name, takes)| Long { line: line, name: name, takes: takes, short: None })),
+            "-n" | "--name" => source.name = Some(value),
+            _ => {},
+        }
+    }
+    source
+}
+
+// Optional arguments have to be attached, as in `-cVALUE` or `--color=VALUE`,
+// so a separate word is never their value and they are completed as flags.
+fn argkind(takes: Takes) -> Option<OptKind> {
+    match takes {
+        Takes::Nothing | Takes::Optional => None,
+        Takes::Required => Some(OptKind::File),
+    }
+}
+
+pub fn import(text: &str, name: Option<&str>) -> Result<Import, CompletistError> {
+    let is_c = regex::Regex::new(r"\bgetopt(?:_long(?:_only)?)?\s*\(|struct\s+option").unwrap();
+    let source = if is_c.is_match(text) { c_source(text) } else { command_line(text) };
+    let mut skipped = source.skipped;
+
+    let program = match name.map(|n| n.to_string()).or(source.name) {
+        Some(program) => program,
+        None => return Err(CompletistError::ImportError(
+            "the program name has to be given with --name".to_string())),
+    };
+    let optstring = source.optstring.unwrap_or_else(String::new);
+    let shorts = parse_optstring(&optstring);
+    if shorts.is_empty() && source.longs.is_empty() {
+        return Err(CompletistError::ImportError("no options were found".to_string()));
+    }
+
+    let mut command = Command::new(&program);
+    for &(c, takes) in &shorts {
+        if c == 'W' && optstring.contains("W;") {
+            skipped.push("`-W foo` is the same as `--foo`, so it isn't completed".to_string());
+            continue;
+        }
+        if takes == Takes::Optional {
+            skipped.push(format!("`-{}` takes an optional argument, which isn't completed", c));
+        }
+        command.options.push(Opt::new(Vec::new(), vec![format!("-{}", c)], "", argkind(takes)));
+    }
+
+    for long in source.longs {
+        let name = format!("--{}", long.name);
+        if long.takes == Takes::Optional {
+            skipped.push(format!("line {}: `{}` takes an optional argument, which isn't completed", long.line, name));
+        }
+        let short = long.short.map(|c| format!("-{}", c));
+        match command.options.iter_mut().find(|o| short.as_ref().map_or(false, |s| o.shorts.contains(s))) {
+            Some(opt) => {
+                if opt.argkind != argkind(long.takes) {
+                    skipped.push(format!("line {}: `{}` and `{}` take different arguments",
+                        long.line, name, short.as_ref().unwrap()));
+                }
+                opt.longs.push(name);
+            },
+            None => command.options.push(Opt::new(vec![name], Vec::new(), "", argkind(long.takes))),
+        }
+    }
+
+    Ok(Import {
+        program: Program::new(&program, command, Metadata::new()),
+        skipped: skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::{Takes, parse_optstring, parse_longs};
+    use completist::program::OptKind;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn optstrings() {
+        assert_eq!(parse_optstring("+:ab:c::"), vec![('a', Takes::Nothing), ('b', Takes::Required), ('c', Takes::Optional)]);
+        assert_eq!(parse_longs("all,output:,color::"), vec![
+            ("all".to_string(), Takes::Nothing),
+            ("output".to_string(), Takes::Required),
+            ("color".to_string(), Takes::Optional),
+        ]);
+
+        let import = import("ab:c:: all,output:\ncolor::", Some("x")).unwrap();
+        let names: Vec<String> = import.program.base_command.options.iter().map(|o| o.name()).collect();
+        assert_eq!(names, strings(&["a", "b", "c", "all", "output", "color"]));
+        assert_eq!(import.program.base_command.options[1].argkind, Some(OptKind::File));
+        assert_eq!(import.program.base_command.options[2].argkind, None);
+        assert_eq!(import.program.base_command.options[5].argkind, None);
+        assert_eq!(import.skipped, strings(&[
+            "`-c` takes an optional argument, which isn't completed",
+            "line 2: `--color` takes an optional argument, which isn't completed",
+        ]));
+        assert!(super::import("ab", None).is_err());
+    }
+
+    #[test]
+    fn c_source() {
+        let import = import(include_str!("../../../samples/getopt/wcount.c"), None).unwrap();
+        let prog = import.program;
+        assert_eq!(prog.name, "wcount");
+        let options: Vec<(Vec<String>, Vec<String>, bool)> = prog.base_command.options.iter()
+            .map(|o| (o.longs.clone(), o.shorts.clone(), o.argkind.is_some()))
+            .collect();
+        assert_eq!(options, vec![
+            (strings(&["--bytes"]), strings(&["-c"]), false),
+            (strings(&["--lines"]), strings(&["-l"]), false),
+            (strings(&["--chars"]), strings(&["-m"]), false),
+            (strings(&["--words"]), strings(&["-w"]), false),
+            (strings(&["--max-line-length"]), strings(&["-L"]), true),
+            (vec![], strings(&["-z"]), false),
+            (strings(&["--files0-from"]), vec![], true),
+            (strings(&["--total"]), vec![], false),
+            (strings(&["--version"]), vec![], false),
+        ]);
+        assert_eq!(import.skipped, strings(&[
+            "line 20: option entry `LONG_OPTION_HELP`",
+            "`-z` takes an optional argument, which isn't completed",
+            "line 18: `--total` takes an optional argument, which isn't completed",
+        ]));
+    }
+
+    #[test]
+    fn getopt_command() {
+        let import = import(include_str!("../../../samples/getopt/backup.sh"), None).unwrap();
+        let prog = import.program;
+        assert_eq!(prog.name, "backup");
+        let names: Vec<String> = prog.base_command.options.iter().map(|o| o.name()).collect();
+        assert_eq!(names, strings(&["v", "x", "c", "verbose", "exclude", "compress", "dry-run"]));
+        assert_eq!(import.skipped, strings(&[
+            "`-c` takes an optional argument, which isn't completed",
+            "`-W foo` is the same as `--foo`, so it isn't completed",
+            "line 3: `--compress` takes an optional argument, which isn't completed",
+        ]));
+    }
+}
@@ -0,0 +1,508 @@
+
+use completist::CompletistError;
+use completist::program::{Program, Command, Argument, Opt, OptKind, Metadata};
+use super::Import;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Section {
+    Intro,
+    Usage,
+    Options,
+    Commands,
+    Arguments,
+    Other,
+}
+
+fn section_header(header: &str) -> Section {
+    let header = header.to_lowercase();
+    if header.contains("usage") {
+        Section::Usage
+    } else if header.contains("command") {
+        Section::Commands
+    } else if header.contains("option") || header.contains("flag") {
+        Section::Options
+    } else if header.contains("argument") || header.contains("args") {
+        Section::Arguments
+    } else {
+        Section::Other
+    }
+}
+
+// Splits `-a, --all     do everything` at the first run of spaces.
+fn columns(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.find("  ") {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// These run for every line of the help text, so they are plain string checks
+// rather than regexes.
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => chars.all(|c| is_word(c) || ".-".contains(c)),
+        _ => false,
+    }
+}
+
+fn is_flag(flag: &str) -> bool {
+    let name = if flag.starts_with("--") {
+        &flag[2..]
+    } else if flag.starts_with('-') {
+        &flag[1..]
+    } else {
+        return false;
+    };
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphanumeric() || c == '?' => chars.all(|c| is_word(c) || ".?+-".contains(c)),
+        _ => false,
+    }
+}
+
+// `{a,b,c}` placeholders (argparse) list their choices; anything else is
+// assumed to be a path, which is what most placeholders turn out to be.
+fn placeholder_kind(placeholder: &str) -> OptKind {
+    let inner = placeholder.trim_matches(|c| "[]<>= ".contains(c)).trim_end_matches("...");
+    if inner.starts_with('{') && inner.ends_with('}') {
+        OptKind::Choices(inner[1..inner.len() - 1].split(',').map(|c| c.to_string()).collect())
+    } else {
+        OptKind::File
+    }
+}
+
+// clap lists choices in the description as `[possible values: a, b]`.
+fn possible_values(description: &str) -> Option<OptKind> {
+    let start = match description.find("[possible values: ") {
+        Some(i) => i + "[possible values: ".len(),
+        None => return None,
+    };
+    description[start..].find(']').map(|end| {
+        let values = &description[start..start + end];
+        OptKind::Choices(values.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect())
+    })
+}
+
+fn parse_opt(names: &str, description: &str) -> Option<Opt> {
+    let mut longs = Vec::new();
+    let mut shorts = Vec::new();
+    let mut placeholder = None;
+
+    for entry in names.split(", ") {
+        let mut words = entry.split_whitespace();
+        let mut flag = match words.next() {
+            Some(flag) => flag.trim_end_matches("..."),
+            None => continue,
+        };
+        let rest: Vec<&str> = words.collect();
+        if !rest.is_empty() {
+            placeholder = Some(rest.join(" "));
+        }
+        if let Some(i) = flag.find("[=").or_else(|| flag.find('=')) {
+            placeholder = Some(flag[i..].to_string());
+            flag = &flag[..i];
+        }
+
+        if !is_flag(flag) {
+            return None;
+        } else if flag.starts_with("--") || flag.len() > 2 {
+            longs.push(flag.to_string());
+        } else {
+            shorts.push(flag.to_string());
+        }
+    }
+
+    if longs.is_empty() && shorts.is_empty() {
+        return None;
+    }
+    Some(Opt::new(longs, shorts, description, placeholder.map(|p| placeholder_kind(&p))))
+}
+
+enum Positional {
+    Skip,
+    Invalid,
+    Commands(Vec<String>),
+    Argument(Argument),
+}
+
+// A word of a usage line, or a name from an arguments section, such as
+// `[FILE]...`, `<name>`, `[units ...]` or `{start,stop}`.
+fn parse_positional(word: &str) -> Positional {
+    let mut word = word.trim();
+    let mut variadic = false;
+    if word.ends_with("...") {
+        variadic = true;
+        word = word[..word.len() - 3].trim();
+    }
+    let optional = word.starts_with('[') && word.ends_with(']');
+    if optional {
+        word = word[1..word.len() - 1].trim();
+    }
+    if word.ends_with("...") {
+        variadic = true;
+        word = word[..word.len() - 3].trim();
+    }
+    if word.starts_with('<') && word.ends_with('>') {
+        word = &word[1..word.len() - 1];
+    }
+
+    let lower = word.to_lowercase();
+    if word.is_empty() || word.starts_with('-') {
+        Positional::Skip
+    } else if word.starts_with('{') && word.ends_with('}') {
+        Positional::Commands(word[1..word.len() - 1].split(',').map(|c| c.to_string()).collect())
+    } else if ["option", "options", "flags", "command", "subcommand", "cmd"].contains(&&lower[..]) {
+        Positional::Skip
+    } else if !is_name(word) {
+        Positional::Invalid
+    } else {
+        let kind = if variadic { OptKind::FilePlus } else { OptKind::File };
+        Positional::Argument(Argument::new(word, kind, optional))
+    }
+}
+
+// Splits a usage line on spaces that aren't inside brackets.
+fn usage_words(usage: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut depth = 0;
+    for c in usage.chars() {
+        match c {
+            '[' | '{' | '(' | '<' => depth += 1,
+            ']' | '}' | ')' | '>' if depth > 0 => depth -= 1,
+            ' ' if depth == 0 => {
+                if !word.is_empty() {
+                    words.push(word);
+                    word = String::new();
+                }
+                continue;
+            },
+            _ => {},
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn add_command(commands: &mut Vec<Command>, name: &str) -> usize {
+    match commands.iter().position(|c| c.name == name) {
+        Some(i) => i,
+        None => {
+            commands.push(Command::new(name));
+            commands.len() - 1
+        },
+    }
+}
+
+enum Last {
+    Nothing,
+    Opt(usize),
+    Command(usize),
+}
+
+pub fn import(text: &str, name: Option<&str>) -> Result<Import, CompletistError> {
+    let mut section = Section::Intro;
+    let mut usage: Option<String> = None;
+    let mut usage_open = false;
+    let mut description = Vec::new();
+    let mut description_done = false;
+    let mut options: Vec<Opt> = Vec::new();
+    let mut commands: Vec<Command> = Vec::new();
+    let mut arguments: Vec<Argument> = Vec::new();
+    let mut skipped = Vec::new();
+    let mut last = Last::Nothing;
+    let mut last_indent = 0;
+
+    for line in text.lines() {
+        let line = line.replace('\t', "        ");
+        let trimmed = line.trim();
+        let indent = line.len() - line.trim_start().len();
+        if trimmed.is_empty() {
+            usage_open = false;
+            description_done = description_done || !description.is_empty();
+            last = Last::Nothing;
+            continue;
+        }
+
+        if indent == 0 && trimmed.to_lowercase().starts_with("usage:") {
+            let rest = trimmed[6..].trim();
+            if rest.is_empty() {
+                section = Section::Usage;
+            } else if usage.is_none() {
+                usage = Some(rest.to_string());
+                usage_open = true;
+            }
+            continue;
+        }
+        if usage_open && indent > 0 {
+            // Lines starting with the program again are alternative usages.
+            let usage = usage.as_mut().unwrap();
+            if trimmed.split_whitespace().next() == usage.split_whitespace().next() {
+                usage_open = false;
+            } else {
+                usage.push(' ');
+                usage.push_str(trimmed);
+            }
+            continue;
+        }
+        if section == Section::Usage && indent > 0 {
+            if usage.is_none() {
+                usage = Some(trimmed.to_string());
+            }
+            continue;
+        }
+
+        if indent == 0 {
+            if trimmed.ends_with(':') && !trimmed.starts_with('-') {
+                section = section_header(trimmed);
+                last = Last::Nothing;
+            } else if section == Section::Intro && !description_done {
+                description.push(trimmed.to_string());
+            }
+            continue;
+        }
+
+        if trimmed.starts_with('-') && section != Section::Commands {
+            let (names, text) = columns(trimmed);
+            match parse_opt(names, text) {
+                Some(opt) => {
+                    options.push(opt);
+                    last = Last::Opt(options.len() - 1);
+                    last_indent = indent;
+                },
+                None => skipped.push(format!("option `{}`", trimmed)),
+            }
+            continue;
+        }
+
+        if indent > last_indent {
+            let target = match last {
+                Last::Opt(i) => Some(&mut options[i].description),
+                Last::Command(i) => Some(&mut commands[i].description),
+                Last::Nothing => None,
+            };
+            if let Some(target) = target {
+                if !target.is_empty() {
+                    target.push(' ');
+                }
+                target.push_str(trimmed);
+                continue;
+            }
+        }
+
+        match section {
+            Section::Commands => {
+                let (names, text) = columns(trimmed);
+                let name = names.split(|c| c == ',' || c == ' ').next().unwrap_or("");
+                if is_name(name) {
+                    let i = add_command(&mut commands, name);
+                    commands[i].description = text.to_string();
+                    last = Last::Command(i);
+                    last_indent = indent;
+                } else {
+                    skipped.push(format!("command `{}`", trimmed));
+                }
+            },
+            Section::Arguments => {
+                let (names, text) = columns(trimmed);
+                last = Last::Nothing;
+                match parse_positional(names) {
+                    Positional::Commands(names) => for name in names {
+                        add_command(&mut commands, &name);
+                    },
+                    Positional::Argument(arg) => {
+                        if let Some(i) = commands.iter().position(|c| c.name == arg.name) {
+                            commands[i].description = text.to_string();
+                            last = Last::Command(i);
+                            last_indent = indent;
+                        } else if !arguments.iter().any(|a| a.name == arg.name) {
+                            arguments.push(arg);
+                        }
+                    },
+                    Positional::Skip => {},
+                    Positional::Invalid => skipped.push(format!("argument `{}`", trimmed)),
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Possible values often only turn up on a description's later lines.
+    for opt in &mut options {
+        if opt.argkind.is_some() {
+            if let Some(kind) = possible_values(&opt.description) {
+                opt.argkind = Some(kind);
+            }
+        }
+    }
+
+    let words = usage_words(usage.as_ref().map(|u| &u[..]).unwrap_or(""));
+    let program = match (name, words.first()) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(first)) => first.rsplit('/').next().unwrap().to_string(),
+        (None, None) => return Err(CompletistError::ImportError(
+            "no usage line to take the program name from".to_string())),
+    };
+
+    // Plain lowercase words straight after the program are the subcommand
+    // that this help text belongs to, as in `cargo bench [OPTIONS]`.
+    let mut path = Vec::new();
+    let mut usage_arguments = Vec::new();
+    for word in words.iter().skip(1) {
+        if usage_arguments.is_empty() && is_name(word) && word.to_lowercase() == *word {
+            path.push(word.clone());
+            continue;
+        }
+        match parse_positional(word) {
+            Positional::Commands(names) => for name in names {
+                add_command(&mut commands, &name);
+            },
+            Positional::Argument(arg) => usage_arguments.push(arg),
+            Positional::Skip => {},
+            Positional::Invalid => skipped.push(format!("usage word `{}`", word)),
+        }
+    }
+    for arg in arguments {
+        if !usage_arguments.iter().any(|a| a.name == arg.name) {
+            usage_arguments.push(arg);
+        }
+    }
+
+    let mut command = Command::new(path.last().unwrap_or(&program));
+    command.description = description.join(" ");
+    command.arguments = usage_arguments;
+    command.options = options;
+    command.commands = commands;
+    for name in path.iter().rev().skip(1) {
+        let mut parent = Command::new(name);
+        parent.commands.push(command);
+        command = parent;
+    }
+    if !path.is_empty() {
+        let mut base = Command::new(&program);
+        base.commands.push(command);
+        command = base;
+    }
+
+    Ok(Import {
+        program: Program::new(&program, command, Metadata::new()),
+        skipped: skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::program::OptKind;
+
+    fn names(opts: &[Opt]) -> Vec<String> {
+        opts.iter().map(|o| o.longs.iter().chain(o.shorts.iter()).cloned().collect::<Vec<_>>().join(" ")).collect()
+    }
+
+    #[test]
+    fn positionals() {
+        match parse_positional("[FILE]...") {
+            Positional::Argument(arg) => {
+                assert_eq!(arg.name, "FILE");
+                assert_eq!(arg.kind, OptKind::FilePlus);
+                assert!(arg.optional);
+            },
+            _ => panic!("not an argument"),
+        }
+        match parse_positional("<name>") {
+            Positional::Argument(arg) => assert!(arg.name == "name" && !arg.optional && arg.kind == OptKind::File),
+            _ => panic!("not an argument"),
+        }
+        assert!(match parse_positional("[OPTION]...") { Positional::Skip => true, _ => false });
+        assert!(match parse_positional("[+toolchain]") { Positional::Invalid => true, _ => false });
+        assert_eq!(usage_words("cargo [--bin <BIN>] [-- [ARGS]...]"), vec!["cargo", "[--bin <BIN>]", "[-- [ARGS]...]"]);
+    }
+
+    #[test]
+    fn gnu() {
+        let import = import(include_str!("../../../samples/help/cat.txt"), None).unwrap();
+        let prog = import.program;
+        assert_eq!(prog.name, "cat");
+        assert_eq!(prog.base_command.description, "Concatenate FILE(s) to standard output.");
+        assert_eq!(prog.base_command.arguments.len(), 1);
+        assert_eq!(prog.base_command.arguments[0].kind, OptKind::FilePlus);
+        assert_eq!(names(&prog.base_command.options), vec!["--show-all -A", "--number-nonblank -b", "-e",
+            "--show-ends -E", "--number -n", "--squeeze-blank -s", "-t", "--show-tabs -T", "-u",
+            "--show-nonprinting -v", "--help", "--version"]);
+        assert_eq!(prog.base_command.options[0].description, "equivalent to -vET");
+        assert!(prog.base_command.commands.is_empty());
+        assert!(import.skipped.is_empty());
+    }
+
+    #[test]
+    fn clap() {
+        let import = import(include_str!("../../../samples/help/cargo.txt"), None).unwrap();
+        let base = &import.program.base_command;
+        assert_eq!(base.description, "Rust's package manager");
+        assert_eq!(base.commands.len(), 16);
+        assert_eq!(base.commands[0].name, "build");
+        assert_eq!(base.commands[0].description, "Compile the current package");
+        let color = base.options.iter().find(|o| o.longs == vec!["--color"]).unwrap();
+        assert_eq!(color.argkind, Some(OptKind::Choices(vec!["auto".to_string(), "always".to_string(),
+            "never".to_string()])));
+        let unstable = base.options.iter().find(|o| o.shorts == vec!["-Z"]).unwrap();
+        assert_eq!(unstable.description, "Unstable (nightly-only) flags to Cargo, see 'cargo -Z help' for details");
+        assert_eq!(import.skipped, vec!["command `...         See all commands with --list`",
+            "usage word `[+toolchain]`"]);
+    }
+
+    #[test]
+    fn clap_subcommand() {
+        let import = import(include_str!("../../../samples/help/cargo-bench.txt"), None).unwrap();
+        let prog = import.program;
+        assert_eq!(prog.name, "cargo");
+        let bench = prog.find(&["bench"]).unwrap();
+        assert_eq!(bench.description, "Execute all benchmarks of a local package");
+        let args: Vec<(&str, bool)> = bench.arguments.iter().map(|a| (&a.name[..], a.kind == OptKind::FilePlus)).collect();
+        assert_eq!(args, vec![("BENCHNAME", false), ("ARGS", true)]);
+        assert!(bench.options.iter().any(|o| o.longs == vec!["--lib"]));
+        let package = bench.options.iter().find(|o| o.longs == vec!["--package"]).unwrap();
+        assert_eq!(package.argkind, Some(OptKind::File));
+        let format = bench.options.iter().find(|o| o.longs == vec!["--message-format"]).unwrap();
+        match format.argkind {
+            Some(OptKind::Choices(ref choices)) => assert_eq!(choices[..2], ["human", "short"]),
+            ref kind => panic!("unexpected kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn argparse() {
+        let import = import(include_str!("../../../samples/help/argparse.txt"), None).unwrap();
+        let base = &import.program.base_command;
+        assert_eq!(import.program.name, "svc");
+        assert_eq!(base.description, "Manage services on this machine.");
+        let commands: Vec<(&str, &str)> = base.commands.iter().map(|c| (&c.name[..], &c.description[..])).collect();
+        assert_eq!(commands, vec![("start", "start a service"), ("stop", "stop a service")]);
+        assert_eq!(base.arguments.len(), 1);
+        assert_eq!(base.arguments[0].name, "units");
+        assert_eq!(base.arguments[0].kind, OptKind::FilePlus);
+        assert_eq!(names(&base.options), vec!["--help -h", "--verbose -v", "--config -c", "--log-level"]);
+        assert_eq!(base.options[2].description,
+            "read settings from FILE instead of the default configuration file");
+        assert_eq!(base.options[3].argkind, Some(OptKind::Choices(vec!["debug".to_string(), "info".to_string(),
+            "warn".to_string()])));
+        assert!(import.skipped.is_empty());
+    }
+
+    #[test]
+    fn explicit_name() {
+        let import = import("Options:\n  -a  all\n", Some("thing")).unwrap();
+        assert_eq!(import.program.name, "thing");
+        assert_eq!(names(&import.program.base_command.options), vec!["-a"]);
+        assert!(super::import("no usage here", None).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use completist::CompletistError;
+use completist::program::{Program, Command, Argument, OptKind, Cache};
+
+pub mod help;
+pub mod fish;
+pub mod zsh;
+pub mod bash;
+pub mod docopt;
+pub mod getopt;
+pub mod clap;
+pub mod shell;
+
+// An imported program, along with everything in the input that could not be
+// mapped onto it.
+pub struct Import {
+    pub program: Program,
+    pub skipped: Vec<String>,
+}
+
+// Importers take the input text and, optionally, the name to give the program.
+pub type ImportFn = fn(&str, Option<&str>) -> Result<Import, CompletistError>;
+
+pub fn find_importer(format: &str) -> Option<ImportFn> {
+    match format {
+        "help" => Some(help::import),
+        "fish" => Some(fish::import),
+        "zsh" => Some(zsh::import),
+        "bash" => Some(bash::import),
+        "docopt" => Some(docopt::import),
+        "getopt" => Some(getopt::import),
+        "clap" => Some(clap::import),
+        _ => None,
+    }
+}
+
+// The subcommand at `path` below `command`, creating any that are missing.
+pub fn command_at<'a>(command: &'a mut Command, path: &[String]) -> &'a mut Command {
+    match path.split_first() {
+        None => command,
+        Some((name, rest)) => {
+            let i = match command.commands.iter().position(|c| &c.name == name) {
+                Some(i) => i,
+                None => {
+                    command.commands.push(Command::new(name));
+                    command.commands.len() - 1
+                },
+            };
+            command_at(&mut command.commands[i], rest)
+        },
+    }
+}
+
+// Shells that offer every positional candidate at every position get a single
+// optional argument that collects them all.
+pub fn add_argument(command: &mut Command, kind: OptKind, cache: Option<Cache>) -> Result<(), String> {
+    if command.arguments.is_empty() {
+        let name = if kind == OptKind::FilePlus { "FILE" } else { "ARG" };
+        let mut arg = Argument::new(name, kind, true);
+        arg.cache = cache;
+        command.arguments.push(arg);
+        return Ok(());
+    }
+
+    let arg = &mut command.arguments[0];
+    match (&mut arg.kind, kind) {
+        (&mut OptKind::Choices(ref mut choices), OptKind::Choices(more)) => {
+            choices.extend(more);
+            Ok(())
+        },
+        (existing, kind) => if *existing == kind {
+            Ok(())
+        } else {
+            Err(format!("arguments of `{}` are already completed by another rule", command.name))
+        },
+    }
+}
+
+// Candidate code that calls functions defined in the imported script runs in
+// `shell` along with their definitions, in script order, since a command
+// never sees the functions of the session that completes. `functions` holds
+// each function's name and source; the names of those used are returned with
+// the command.
+pub fn with_functions(shell: &str, code: &str, functions: &[(String, String)]) -> (String, Vec<String>) {
+    let calls = |text: &str, name: &str| text.split(|c: char| c.is_whitespace() || ";|&()$`'\"{}".contains(c))
+        .any(|word| word == name);
+    let mut needed: Vec<bool> = functions.iter().map(|&(ref name, _)| calls(code, name)).collect();
+    loop {
+        let more: Vec<usize> = (0..functions.len())
+            .filter(|&i| !needed[i] && (0..functions.len()).any(|j| needed[j] && calls(&functions[j].1, &functions[i].0)))
+            .collect();
+        if more.is_empty() {
+            break;
+        }
+        for i in more {
+            needed[i] = true;
+        }
+    }
+    if !needed.iter().any(|&n| n) {
+        return (code.to_string(), Vec::new());
+    }
+
+    let mut script = String::new();
+    let mut used = Vec::new();
+    for (&(ref name, ref source), _) in functions.iter().zip(&needed).filter(|&(_, &n)| n) {
+        script.push_str(source);
+        script.push('\n');
+        used.push(name.clone());
+    }
+    script.push_str(code);
+    (format!("{} -c '{}'", shell, script.replace("'", "'\\''")), used)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_definitions() {
+        let functions = vec![
+            ("_units".to_string(), "_units() { _list | cut -d' ' -f1; }".to_string()),
+            ("_list".to_string(), "_list() { systemctl list-units; }".to_string()),
+            ("_other".to_string(), "_other() { :; }".to_string()),
+        ];
+        assert_eq!(with_functions("bash", "ls /etc", &functions), ("ls /etc".to_string(), vec![]));
+        let (command, used) = with_functions("bash", "_units", &functions);
+        assert_eq!(command,
+            "bash -c '_units() { _list | cut -d'\\'' '\\'' -f1; }\n_list() { systemctl list-units; }\n_units'");
+        assert_eq!(used, vec!["_units", "_list"]);
+    }
+}
@@ -0,0 +1,588 @@
+// A parser for the subset of POSIX shell and zsh that completion scripts are
+// written in: simple commands, functions, array assignments, `if` and `case`
+// statements. Loops are flattened away, and quotes are removed except
+// inside `$(...)`, `${...}` and backticks, which are kept verbatim.
+
+#[derive(Debug, PartialEq)]
+pub enum Node {
+    Command(usize, Vec<String>),
+    // An array assignment, and whether it appends with `+=`.
+    Assign(usize, String, Vec<String>, bool),
+    // A function, with the line its closing brace is on.
+    Function(usize, usize, String, Vec<Node>),
+    Case(usize, String, Vec<(Vec<String>, Vec<Node>)>),
+    // Each condition with its body, then the `else` body.
+    If(usize, Vec<(Vec<Node>, Vec<Node>)>, Vec<Node>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Word(String),
+    Op(&'static str),
+}
+
+// Expands unquoted `{a,b}` braces, as zsh and bash do.
+fn expand(word: &[(char, bool)]) -> Vec<String> {
+    for open in 0..word.len() {
+        if word[open] != ('{', true) {
+            continue;
+        }
+        let mut depth = 0;
+        let mut bounds = vec![open];
+        let mut close = None;
+        for (j, &(c, unquoted)) in word.iter().enumerate().skip(open) {
+            match c {
+                _ if !unquoted => {},
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(j);
+                        break;
+                    }
+                },
+                ',' if depth == 1 => bounds.push(j),
+                _ => {},
+            }
+        }
+        if let Some(close) = close {
+            if bounds.len() > 1 {
+                bounds.push(close);
+                let mut words = Vec::new();
+                for pair in bounds.windows(2) {
+                    let mut alternative = word[..open].to_vec();
+                    alternative.extend_from_slice(&word[pair[0] + 1..pair[1]]);
+                    alternative.extend_from_slice(&word[close + 1..]);
+                    words.extend(expand(&alternative));
+                }
+                return words;
+            }
+        }
+    }
+    vec![word.iter().map(|&(c, _)| c).collect()]
+}
+
+// Copies a `$(...)`, `${...}` or backtick expansion verbatim, starting just
+// after its opening character.
+fn raw(chars: &[char], mut i: usize, open: char, close: char, word: &mut Vec<(char, bool)>, line: &mut usize)
+        -> Result<usize, String> {
+    let mut depth = 1;
+    let mut quote = None;
+    while i < chars.len() {
+        let c = chars[i];
+        word.push((c, false));
+        i += 1;
+        match (quote, c) {
+            (_, '\n') => *line += 1,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {},
+            (None, '\\') if i < chars.len() => {
+                word.push((chars[i], false));
+                i += 1;
+            },
+            (None, '\'') | (None, '"') if open != close => quote = Some(c),
+            (None, c) if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            },
+            (None, c) if c == open => depth += 1,
+            _ => {},
+        }
+    }
+    Err(format!("line {}: unterminated `{}`", line, open))
+}
+
+fn tokens(script: &str) -> Result<Vec<(usize, Token)>, String> {
+    let chars: Vec<char> = script.chars().collect();
+    let mut tokens = Vec::new();
+    let mut word = Vec::new();
+    let mut in_word = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut i = 0;
+
+    macro_rules! finish {
+        () => {
+            if in_word {
+                for w in expand(&word) {
+                    tokens.push((start, Token::Word(w)));
+                }
+                word.clear();
+                in_word = false;
+            }
+        }
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).cloned();
+        if !in_word {
+            start = line;
+        }
+        i += 1;
+        match c {
+            ' ' | '\t' => finish!(),
+            '\n' => {
+                finish!();
+                tokens.push((line, Token::Op("\n")));
+                line += 1;
+            },
+            '#' if !in_word => while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            },
+            '\\' => {
+                match next {
+                    Some('\n') => line += 1,
+                    Some(n) => {
+                        word.push((n, false));
+                        in_word = true;
+                    },
+                    None => {},
+                }
+                i += 1;
+            },
+            '\'' => {
+                in_word = true;
+                while i < chars.len() && chars[i] != '\'' {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    word.push((chars[i], false));
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err(format!("line {}: unterminated quote", start));
+                }
+                i += 1;
+            },
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.get(i).cloned() {
+                        None => return Err(format!("line {}: unterminated quote", start)),
+                        Some('"') => break,
+                        Some('\\') if chars.get(i + 1).map_or(false, |c| "\"\\$`\n".contains(*c)) => {
+                            if chars[i + 1] != '\n' {
+                                word.push((chars[i + 1], false));
+                            }
+                            i += 2;
+                        },
+                        Some('$') if chars.get(i + 1).map_or(false, |&c| c == '(' || c == '{') => {
+                            let open = chars[i + 1];
+                            word.push(('$', false));
+                            word.push((open, false));
+                            i = try!(raw(&chars, i + 2, open, if open == '(' { ')' } else { '}' }, &mut word, &mut line));
+                        },
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            word.push((c, false));
+                            i += 1;
+                        },
+                    }
+                }
+                i += 1;
+            },
+            '$' if next == Some('\'') => {
+                in_word = true;
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    let mut c = chars[i];
+                    if c == '\\' && i + 1 < chars.len() {
+                        i += 1;
+                        c = match chars[i] {
+                            'n' => '\n',
+                            't' => '\t',
+                            'e' => '\x1b',
+                            c => c,
+                        };
+                    }
+                    word.push((c, false));
+                    i += 1;
+                }
+                i += 1;
+            },
+            '$' if next == Some('(') || next == Some('{') => {
+                in_word = true;
+                let open = next.unwrap();
+                word.push(('$', false));
+                word.push((open, false));
+                i = try!(raw(&chars, i + 1, open, if open == '(' { ')' } else { '}' }, &mut word, &mut line));
+            },
+            '`' => {
+                in_word = true;
+                word.push(('`', false));
+                i = try!(raw(&chars, i, '`', '`', &mut word, &mut line));
+            },
+            ';' | '&' | '|' => {
+                finish!();
+                let op = match (c, next) {
+                    (';', Some(';')) | (';', Some('&')) | (';', Some('|')) => { i += 1; ";;" },
+                    ('&', Some('&')) => { i += 1; "&&" },
+                    ('|', Some('|')) => { i += 1; "||" },
+                    (';', _) => ";",
+                    ('&', _) => "&",
+                    _ => "|",
+                };
+                tokens.push((line, Token::Op(op)));
+            },
+            '(' | ')' => {
+                finish!();
+                tokens.push((line, Token::Op(if c == '(' { "(" } else { ")" })));
+            },
+            c => {
+                word.push((c, true));
+                in_word = true;
+            },
+        }
+    }
+    if in_word {
+        for w in expand(&word) {
+            tokens.push((start, Token::Word(w)));
+        }
+    }
+    Ok(tokens)
+}
+
+// The words of `text`, ignoring any operators between them.
+pub fn words(text: &str) -> Result<Vec<String>, String> {
+    Ok(try!(tokens(text)).into_iter()
+        .filter_map(|(_, token)| match token {
+            Token::Word(word) => Some(word),
+            Token::Op(_) => None,
+        })
+        .collect())
+}
+
+// The command inside the first `$(...)` or backticks in `text`.
+pub fn command_substitution(text: &str) -> Option<String> {
+    let (start, open, close) = match (text.find("$("), text.find('`')) {
+        (Some(i), _) => (i + 2, '(', ')'),
+        (None, Some(i)) => (i + 1, '`', '`'),
+        (None, None) => return None,
+    };
+    let chars: Vec<char> = text[start..].chars().collect();
+    let mut inner = Vec::new();
+    let mut line = 0;
+    match raw(&chars, 0, open, close, &mut inner, &mut line) {
+        Ok(_) => {
+            inner.pop();
+            Some(inner.into_iter().map(|(c, _)| c).collect::<String>().trim().to_string())
+        },
+        Err(_) => None,
+    }
+}
+
+const KEYWORDS: &'static [&'static str] = &["while", "until", "do", "done", "!"];
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + n).map(|&(_, ref token)| token)
+    }
+
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn last_line(&self) -> usize {
+        self.tokens.get(self.pos - 1).map_or(1, |&(line, _)| line)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos).or_else(|| self.tokens.last()).map_or(1, |&(line, _)| line)
+    }
+
+    fn skip_separators(&mut self) {
+        while let Some(&Token::Op(op)) = self.peek(0) {
+            if ["\n", ";", "&", "&&", "||", "|"].contains(&op) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn at_end(&self, ends: &[&str]) -> bool {
+        match self.peek(0) {
+            Some(&Token::Op(op)) => ends.contains(&op),
+            Some(&Token::Word(ref word)) => ends.contains(&&word[..]),
+            None => true,
+        }
+    }
+
+    // Parses commands up to, but not including, one of `ends`.
+    fn list(&mut self, ends: &[&str]) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_separators();
+            if self.peek(0).is_none() && !ends.is_empty() {
+                return Err(format!("line {}: missing `{}`", self.line(), ends[0]));
+            } else if self.at_end(ends) {
+                return Ok(nodes);
+            }
+            try!(self.command(&mut nodes));
+        }
+    }
+
+    fn expect(&mut self, end: &str) -> Result<(), String> {
+        let line = self.line();
+        match self.next() {
+            Some((_, Token::Op(op))) if op == end => Ok(()),
+            Some((_, Token::Word(ref word))) if word == end => Ok(()),
+            _ => Err(format!("line {}: expected `{}`", line, end)),
+        }
+    }
+
+    fn body(&mut self) -> Result<Vec<Node>, String> {
+        self.skip_separators();
+        if self.peek(0) == Some(&Token::Op("(")) {
+            self.pos += 1;
+            let body = try!(self.list(&[")"]));
+            try!(self.expect(")"));
+            Ok(body)
+        } else {
+            try!(self.expect("{"));
+            let body = try!(self.list(&["}"]));
+            try!(self.expect("}"));
+            Ok(body)
+        }
+    }
+
+    fn case(&mut self, line: usize) -> Result<Node, String> {
+        let subject = match self.next() {
+            Some((_, Token::Word(subject))) => subject,
+            _ => return Err(format!("line {}: `case` without a subject", line)),
+        };
+        while self.peek(0) == Some(&Token::Op("\n")) {
+            self.pos += 1;
+        }
+        try!(self.expect("in"));
+
+        let mut branches = Vec::new();
+        loop {
+            self.skip_separators();
+            if self.at_end(&["esac"]) {
+                try!(self.expect("esac"));
+                return Ok(Node::Case(line, subject, branches));
+            }
+            if self.peek(0) == Some(&Token::Op("(")) {
+                self.pos += 1;
+            }
+            let mut patterns = Vec::new();
+            loop {
+                match self.next() {
+                    Some((_, Token::Word(pattern))) => patterns.push(pattern),
+                    _ => return Err(format!("line {}: bad `case` pattern", self.line())),
+                }
+                match self.next() {
+                    Some((_, Token::Op("|"))) => continue,
+                    Some((_, Token::Op(")"))) => break,
+                    _ => return Err(format!("line {}: bad `case` pattern", self.line())),
+                }
+            }
+            let body = try!(self.list(&[";;", "esac"]));
+            if self.peek(0) == Some(&Token::Op(";;")) {
+                self.pos += 1;
+            }
+            branches.push((patterns, body));
+        }
+    }
+
+    fn if_(&mut self, line: usize) -> Result<Node, String> {
+        let mut branches = Vec::new();
+        loop {
+            let condition = try!(self.list(&["then"]));
+            try!(self.expect("then"));
+            let body = try!(self.list(&["elif", "else", "fi"]));
+            branches.push((condition, body));
+            match self.next() {
+                Some((_, Token::Word(ref word))) if word == "elif" => continue,
+                Some((_, Token::Word(ref word))) if word == "else" => {
+                    let otherwise = try!(self.list(&["fi"]));
+                    try!(self.expect("fi"));
+                    return Ok(Node::If(line, branches, otherwise));
+                },
+                _ => return Ok(Node::If(line, branches, Vec::new())),
+            }
+        }
+    }
+
+    fn command(&mut self, nodes: &mut Vec<Node>) -> Result<(), String> {
+        let (line, token) = self.next().unwrap();
+        let first = match token {
+            Token::Op("(") => {
+                let body = try!(self.list(&[")"]));
+                try!(self.expect(")"));
+                nodes.extend(body);
+                return Ok(());
+            },
+            Token::Op(op) => return Err(format!("line {}: unexpected `{}`", line, op)),
+            Token::Word(word) => word,
+        };
+
+        match &first[..] {
+            _ if KEYWORDS.contains(&&first[..]) => return Ok(()),
+            "{" => {
+                let body = try!(self.list(&["}"]));
+                try!(self.expect("}"));
+                nodes.extend(body);
+                return Ok(());
+            },
+            "for" | "select" => {
+                // Only the body of a loop is interesting.
+                while let Some(&Token::Word(_)) = self.peek(0) {
+                    self.pos += 1;
+                }
+                return Ok(());
+            },
+            "case" => {
+                let case = try!(self.case(line));
+                nodes.push(case);
+                return Ok(());
+            },
+            "if" => {
+                let node = try!(self.if_(line));
+                nodes.push(node);
+                return Ok(());
+            },
+            "function" => {
+                let name = match self.next() {
+                    Some((_, Token::Word(name))) => name,
+                    _ => return Err(format!("line {}: `function` without a name", line)),
+                };
+                if self.peek(0) == Some(&Token::Op("(")) && self.peek(1) == Some(&Token::Op(")")) {
+                    self.pos += 2;
+                }
+                let body = try!(self.body());
+                nodes.push(Node::Function(line, self.last_line(), name, body));
+                return Ok(());
+            },
+            _ if self.peek(0) == Some(&Token::Op("(")) && self.peek(1) == Some(&Token::Op(")")) => {
+                self.pos += 2;
+                let body = try!(self.body());
+                nodes.push(Node::Function(line, self.last_line(), first, body));
+                return Ok(());
+            },
+            _ => {},
+        }
+
+        let mut words = Vec::new();
+        let mut word = Some(first);
+        while let Some(w) = word.take() {
+            if w.ends_with('=') && self.peek(0) == Some(&Token::Op("(")) {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    match self.next() {
+                        Some((_, Token::Word(item))) => items.push(item),
+                        Some((_, Token::Op("\n"))) => {},
+                        Some((_, Token::Op(")"))) => break,
+                        _ => return Err(format!("line {}: bad array assignment", line)),
+                    }
+                }
+                let append = w.ends_with("+=");
+                let name = w.trim_end_matches('=').trim_end_matches('+').to_string();
+                nodes.push(Node::Assign(line, name, items, append));
+            } else {
+                words.push(w);
+            }
+            // zsh lets a closing brace end the command before it.
+            match self.peek(0) {
+                Some(&Token::Word(ref w)) if w == "}" => {},
+                Some(&Token::Word(_)) => if let Some((_, Token::Word(w))) = self.next() {
+                    word = Some(w);
+                },
+                _ => {},
+            }
+        }
+        if !words.is_empty() {
+            nodes.push(Node::Command(line, words));
+        }
+        Ok(())
+    }
+}
+
+pub fn parse(script: &str) -> Result<Vec<Node>, String> {
+    let mut parser = Parser {
+        tokens: try!(tokens(script)),
+        pos: 0,
+    };
+    parser.list(&[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(words("a 'b c' \"d \\\"e\\\"\" f\\ g $'h\\ti' # comment").unwrap(),
+            strings(&["a", "b c", "d \"e\"", "f g", "h\ti"]));
+        assert_eq!(words("'(-a --all)'{-a,--all}'[all of {them,it}]' x{1,2{a,b}}").unwrap(),
+            strings(&["(-a --all)-a[all of {them,it}]", "(-a --all)--all[all of {them,it}]", "x1", "x2a", "x2b"]));
+        assert_eq!(words("x=(${(f)\"$(ls -d ')' )\"}) `a b` \"$(c \"d\")\"").unwrap(),
+            strings(&["x=", "${(f)\"$(ls -d ')' )\"}", "`a b`", "$(c \"d\")"]));
+        assert_eq!(command_substitution("${(f)\"$(ls -d ')' )\"}"), Some("ls -d ')'".to_string()));
+        assert_eq!(command_substitution("`ls`"), Some("ls".to_string()));
+        assert!(words("'oops").is_err());
+    }
+
+    #[test]
+    fn structure() {
+        let script = "
+            _f() {
+              local -a xs
+              xs=(a 'b c'
+                d)
+              if (( CURRENT == 2 )); then
+                case $words[1] in
+                  (a|b) one ;;
+                  c)
+                    two && three
+                    ;;
+                esac
+              fi
+            }
+            function g { xs+=(e); }
+            _f \"$@\"
+        ";
+        assert_eq!(parse(script).unwrap(), vec![
+            Node::Function(2, 14, "_f".to_string(), vec![
+                Node::Command(3, strings(&["local", "-a", "xs"])),
+                Node::Assign(4, "xs".to_string(), strings(&["a", "b c", "d"]), false),
+                Node::If(6, vec![(vec![Node::Command(6, strings(&["CURRENT", "==", "2"]))], vec![
+                    Node::Case(7, "$words[1]".to_string(), vec![
+                        (strings(&["a", "b"]), vec![Node::Command(8, strings(&["one"]))]),
+                        (strings(&["c"]), vec![
+                            Node::Command(10, strings(&["two"])),
+                            Node::Command(10, strings(&["three"])),
+                        ]),
+                    ]),
+                ])], vec![]),
+            ]),
+            Node::Function(15, 15, "g".to_string(), vec![Node::Assign(15, "xs".to_string(), strings(&["e"]), true)]),
+            Node::Command(16, strings(&["_f", "$@"])),
+        ]);
+        assert!(parse("case x in a) b").is_err());
+        assert_eq!(parse("if a; then b; elif c; then d; else e; fi").unwrap(), vec![
+            Node::If(1, vec![
+                (vec![Node::Command(1, strings(&["a"]))], vec![Node::Command(1, strings(&["b"]))]),
+                (vec![Node::Command(1, strings(&["c"]))], vec![Node::Command(1, strings(&["d"]))]),
+            ], vec![Node::Command(1, strings(&["e"]))]),
+        ]);
+    }
+}
@@ -0,0 +1,674 @@
+use std::collections::{HashMap, HashSet};
+
+use completist::CompletistError;
+use completist::program::{Program, Command, Argument, Opt, OptKind, Metadata};
+use super::{Import, command_at};
+use super::shell::{self, Node};
+
+#[derive(Clone)]
+enum Slot {
+    // An argument's name, its position (None for `*` specs), whether it is
+    // optional, and whether its candidates are really subcommands.
+    Argument(String, Option<usize>, bool, bool),
+    Option(Vec<String>),
+}
+
+// Where the candidates that a spec's action produces end up.
+#[derive(Clone)]
+struct Target {
+    path: Vec<String>,
+    slot: Slot,
+}
+
+enum Candidates {
+    Files,
+    Words(Vec<String>),
+    Described(Vec<(String, String)>),
+    Command(String),
+}
+
+fn unescaped_colon(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            ':' if !escaped => return Some(i),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+// Splits `message:action:...` into the message and the action.
+fn message_action(s: &str) -> (&str, &str) {
+    match unescaped_colon(s) {
+        Some(i) => {
+            let action = &s[i + 1..];
+            (&s[..i], &action[..unescaped_colon(action).unwrap_or(action.len())])
+        },
+        None => (s, ""),
+    }
+}
+
+// Splits a `name:description` item, as `_describe` does.
+fn described(item: &str) -> (String, String) {
+    match unescaped_colon(item) {
+        Some(i) => (item[..i].replace("\\:", ":"), item[i + 1..].to_string()),
+        None => (item.replace("\\:", ":"), String::new()),
+    }
+}
+
+fn collect<'a>(nodes: &'a [Node], functions: &mut HashMap<String, &'a [Node]>) {
+    for node in nodes {
+        match *node {
+            Node::Function(_, _, ref name, ref body) => {
+                functions.insert(name.clone(), &body[..]);
+                collect(body, functions);
+            },
+            Node::Case(_, _, ref branches) => for &(_, ref body) in branches {
+                collect(body, functions);
+            },
+            Node::If(_, ref branches, ref otherwise) => {
+                for &(ref condition, ref body) in branches {
+                    collect(condition, functions);
+                    collect(body, functions);
+                }
+                collect(otherwise, functions);
+            },
+            _ => {},
+        }
+    }
+}
+
+// Walks the script much as zsh would when completing, except that every
+// branch is taken, and records what each completion function offers.
+struct Importer<'a> {
+    functions: HashMap<String, &'a [Node]>,
+    arrays: HashMap<String, Vec<String>>,
+    states: HashMap<String, Vec<Target>>,
+    handled: HashSet<String>,
+    running: Vec<String>,
+    visited: HashSet<String>,
+    positions: HashMap<Vec<String>, Vec<usize>>,
+    base: Command,
+    skipped: Vec<String>,
+}
+
+impl<'a> Importer<'a> {
+    fn run(&mut self, nodes: &'a [Node], path: &[String], target: Option<&Target>) {
+        for node in nodes {
+            match *node {
+                Node::Function(..) => {},
+                Node::Assign(_, ref name, ref items, append) => {
+                    let array = self.arrays.entry(name.clone()).or_insert_with(Vec::new);
+                    if !append {
+                        array.clear();
+                    }
+                    array.extend(items.iter().cloned());
+                },
+                Node::Command(line, ref words) => self.command(line, words, path, target),
+                Node::Case(line, ref subject, ref branches) => self.case(line, subject, branches, path),
+                Node::If(_, ref branches, ref otherwise) => {
+                    for &(ref condition, ref body) in branches {
+                        self.run(condition, path, target);
+                        self.run(body, path, target);
+                    }
+                    self.run(otherwise, path, target);
+                },
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, path: &[String], target: Option<&Target>) {
+        if self.running.iter().any(|r| r == name) {
+            return;
+        }
+        let body = self.functions[name];
+        self.running.push(name.to_string());
+        self.visited.insert(name.to_string());
+        self.run(body, path, target);
+        self.running.pop();
+    }
+
+    fn command(&mut self, line: usize, words: &[String], path: &[String], target: Option<&Target>) {
+        let args = &words[1..];
+        match &words[0][..] {
+            "_arguments" => self.arguments(line, args, path),
+            "_describe" => {
+                let candidates = self.describe(args);
+                self.offer(line, candidates, path, target);
+            },
+            "compadd" => {
+                let candidates = self.compadd(args);
+                self.offer(line, candidates, path, target);
+            },
+            "_files" | "_path_files" | "_directories" | "_default" => self.offer(line, Candidates::Files, path, target),
+            "_wanted" | "_requested" => {
+                // `_wanted [-x] [-C name] [-12VJ] tag expl description command...`
+                let mut i = 0;
+                while i < args.len() && args[i].starts_with('-') {
+                    i += if args[i] == "-C" { 2 } else { 1 };
+                }
+                if i + 3 < args.len() {
+                    self.command(line, &args[i + 3..], path, target);
+                }
+            },
+            "_message" => {},
+            name if self.functions.contains_key(name) => self.call(name, path, target),
+            name if name.starts_with('_') => self.skipped.push(format!("line {}: `{}` isn't supported", line, name)),
+            _ => {},
+        }
+    }
+
+    fn case(&mut self, line: usize, subject: &str, branches: &'a [(Vec<String>, Vec<Node>)], path: &[String]) {
+        match subject {
+            "$state" | "${state}" => for &(ref patterns, ref body) in branches {
+                for pattern in patterns {
+                    self.handled.insert(pattern.clone());
+                    let targets = self.states.get(pattern).cloned().unwrap_or_else(Vec::new);
+                    for target in &targets {
+                        self.run(body, &target.path, Some(target));
+                    }
+                }
+            },
+            "$line[1]" | "${line[1]}" | "$words[1]" | "${words[1]}" => for &(ref patterns, ref body) in branches {
+                for pattern in patterns {
+                    if pattern.contains(|c| "*?[]".contains(c)) {
+                        if pattern != "*" {
+                            self.skipped.push(format!("line {}: case pattern `{}`", line, pattern));
+                        }
+                        continue;
+                    }
+                    let mut sub = path.to_vec();
+                    sub.push(pattern.clone());
+                    command_at(&mut self.base, &sub);
+                    self.run(body, &sub, None);
+                }
+            },
+            _ => self.skipped.push(format!("line {}: case on `{}`", line, subject)),
+        }
+    }
+
+    fn arguments(&mut self, line: usize, specs: &[String], path: &[String]) {
+        // _arguments' own flags come before any of the specs.
+        let mut specs = specs;
+        loop {
+            match specs.first().map(|s| &s[..]) {
+                Some("-s") | Some("-S") | Some("-C") | Some("-R") | Some("-w") | Some("-W") | Some("-n") | Some(":") =>
+                    specs = &specs[1..],
+                Some("-A") | Some("-O") | Some("-M") => specs = &specs[if specs.len() > 1 { 2 } else { 1 }..],
+                _ => break,
+            }
+        }
+
+        // `*::` specs shift the words, so the first argument is a subcommand.
+        let dispatch = specs.iter().any(|s| s.starts_with("*::"));
+        let mut position = 1;
+        for spec in specs {
+            if let Err(message) = self.spec(line, spec, path, dispatch, &mut position) {
+                self.skipped.push(format!("line {}: {}", line, message));
+            }
+        }
+    }
+
+    fn spec(&mut self, line: usize, spec: &str, path: &[String], dispatch: bool, next: &mut usize) -> Result<(), String> {
+        let mut rest = spec;
+        let mut group = Vec::new();
+        if rest.starts_with('(') {
+            let close = try!(rest.find(')').ok_or_else(|| format!("bad spec `{}`", spec)));
+            group = rest[1..close].split_whitespace().map(|n| n.to_string()).collect();
+            rest = &rest[close + 1..];
+        }
+
+        if rest.starts_with("*:") || rest.starts_with(':') || rest.starts_with(|c: char| c.is_digit(10)) {
+            let position = if rest.starts_with('*') {
+                rest = &rest[1..];
+                None
+            } else if rest.starts_with(':') {
+                Some(*next)
+            } else {
+                let digits = rest.find(':').unwrap_or(rest.len());
+                let position = try!(rest[..digits].parse::<usize>().map_err(|_| format!("bad spec `{}`", spec)));
+                rest = &rest[digits..];
+                Some(position)
+            };
+            if let Some(position) = position {
+                *next = position + 1;
+            }
+            let optional = position.is_none() || rest.starts_with("::");
+            let (message, action) = message_action(rest.trim_start_matches(':'));
+            let name = message.trim().replace(' ', "-");
+            let commands = dispatch && position == Some(1);
+            return self.action(line, action, Target {
+                path: path.to_vec(),
+                slot: Slot::Argument(name, position, optional, commands),
+            });
+        }
+
+        let repeatable = rest.starts_with('*');
+        if repeatable {
+            rest = &rest[1..];
+        }
+        if rest == "-" || rest == "--" || rest.starts_with("--:") {
+            return Err(format!("`{}` specs aren't supported", rest));
+        } else if !rest.starts_with('-') {
+            return Err(format!("spec `{}` isn't supported", spec));
+        }
+
+        let end = rest.find(|c| c == '[' || c == ':').unwrap_or(rest.len());
+        let mut name = &rest[..end];
+        rest = &rest[end..];
+        for suffix in &["=-", "=", "+", "-"] {
+            if name.len() > 2 && name.ends_with(suffix) {
+                name = &name[..name.len() - suffix.len()];
+                break;
+            }
+        }
+        let mut description = String::new();
+        if rest.starts_with('[') {
+            let mut escaped = false;
+            let close = rest.char_indices().position(|(_, c)| {
+                let close = c == ']' && !escaped;
+                escaped = c == '\\' && !escaped;
+                close
+            });
+            let close = try!(close.ok_or_else(|| format!("bad spec `{}`", spec)));
+            description = rest[1..close].replace("\\]", "]").replace("\\[", "[");
+            rest = &rest[close + 1..];
+        }
+
+        // Besides option names, the list can hold `-` for every option, `*`
+        // for the remaining arguments and argument positions.
+        for excluded in group.iter().filter(|g| !g.starts_with('-') || g.len() == 1) {
+            self.skipped.push(format!("line {}: `{}` excludes `{}`, which isn't supported", line, name, excluded));
+        }
+        self.add_option(path, name, &group, &description, repeatable);
+        if rest.starts_with(':') {
+            let (_, action) = message_action(rest[1..].trim_start_matches(':'));
+            return self.action(line, action, Target {
+                path: path.to_vec(),
+                slot: Slot::Option(vec![name.to_string()]),
+            });
+        }
+        Ok(())
+    }
+
+    // Options that exclude each other and share a description are usually
+    // the short and long names of a single option. Whatever else the group
+    // lists can't be given along with the option.
+    fn add_option(&mut self, path: &[String], name: &str, group: &[String], description: &str, repeatable: bool) {
+        let command = command_at(&mut self.base, path);
+        let long = name.starts_with("--") || name.len() > 2;
+        let existing = command.options.iter().position(|o| {
+            o.description == description && group.iter().any(|g| g == name)
+                && o.longs.iter().chain(o.shorts.iter()).any(|n| group.contains(n))
+        });
+        let opt = match existing {
+            Some(i) => {
+                let opt = &mut command.options[i];
+                if long { opt.longs.push(name.to_string()) } else { opt.shorts.push(name.to_string()) }
+                opt
+            },
+            None => {
+                let (longs, shorts) = if long { (vec![name.to_string()], vec![]) } else { (vec![], vec![name.to_string()]) };
+                command.options.push(Opt::new(longs, shorts, description, None));
+                command.options.last_mut().unwrap()
+            },
+        };
+
+        opt.repeatable = repeatable;
+        for excluded in group.iter().filter(|g| g.starts_with('-') && g.len() > 1) {
+            if !opt.excludes.contains(excluded) {
+                opt.excludes.push(excluded.clone());
+            }
+        }
+        let names: Vec<String> = opt.longs.iter().chain(opt.shorts.iter()).cloned().collect();
+        opt.excludes.retain(|e| !names.contains(e));
+    }
+
+    fn action(&mut self, line: usize, action: &str, target: Target) -> Result<(), String> {
+        let action = action.trim();
+        if action.starts_with("->") {
+            self.states.entry(action[2..].to_string()).or_insert_with(Vec::new).push(target);
+            return Ok(());
+        }
+
+        let candidates = if action.starts_with("((") && action.ends_with("))") {
+            let items = try!(shell::words(&action[2..action.len() - 2]));
+            Candidates::Described(items.iter().map(|i| described(i)).collect())
+        } else if action.starts_with('(') && action.ends_with(')') {
+            Candidates::Words(try!(shell::words(&action[1..action.len() - 1])))
+        } else if action.starts_with('{') {
+            self.skipped.push(format!("line {}: code `{}` isn't supported, completing files instead", line, action));
+            Candidates::Files
+        } else {
+            let words = try!(shell::words(action));
+            match words.first().map(|w| &w[..]) {
+                None | Some("_files") | Some("_path_files") | Some("_directories") | Some("_default") => Candidates::Files,
+                Some(function) if self.functions.contains_key(function) => {
+                    self.call(function, &target.path, Some(&target));
+                    return Ok(());
+                },
+                Some(function) => {
+                    self.skipped.push(format!("line {}: `{}` isn't supported, completing files instead", line, function));
+                    Candidates::Files
+                },
+            }
+        };
+        let path = target.path.clone();
+        self.offer(line, candidates, &path, Some(&target));
+        Ok(())
+    }
+
+    // Items that come from a command substitution are completed by running
+    // that command instead.
+    fn items(items: Vec<String>, descriptions: bool) -> Candidates {
+        for item in &items {
+            if let Some(command) = shell::command_substitution(item) {
+                return Candidates::Command(command);
+            }
+        }
+        if descriptions {
+            Candidates::Described(items.iter().map(|i| described(i)).collect())
+        } else {
+            Candidates::Words(items)
+        }
+    }
+
+    fn array(&self, name: &str) -> Vec<String> {
+        let name = name.trim_start_matches('$').trim_matches(|c| c == '{' || c == '}')
+            .trim_end_matches("[@]").trim_end_matches("[*]");
+        self.arrays.get(name).cloned().unwrap_or_else(Vec::new)
+    }
+
+    // `_describe [-12JVx] [-oO | -t tag] description array...`
+    fn describe(&self, args: &[String]) -> Candidates {
+        let mut args = args.iter().peekable();
+        while args.peek().map_or(false, |a| a.starts_with('-')) {
+            if args.next().unwrap() == "-t" {
+                args.next();
+            }
+        }
+        args.next();
+        let items = args.take_while(|a| !a.starts_with('-'))
+            .flat_map(|a| self.array(a).into_iter())
+            .collect();
+        Self::items(items, true)
+    }
+
+    fn compadd(&self, args: &[String]) -> Candidates {
+        let mut items = Vec::new();
+        let mut arrays = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "-" | "--" => {
+                    items.extend(args.by_ref().cloned());
+                },
+                "-a" | "-k" => arrays = true,
+                "-J" | "-V" | "-X" | "-x" | "-P" | "-S" | "-p" | "-s" | "-i" | "-I" | "-W" | "-d" | "-M" | "-F"
+                    | "-r" | "-R" | "-D" | "-O" | "-A" | "-E" => {
+                    args.next();
+                },
+                _ if arg.starts_with('-') => {},
+                _ => items.push(arg.clone()),
+            }
+        }
+        let items = items.into_iter()
+            .flat_map(|item| if arrays || (item.starts_with('$') && !item.starts_with("$(")) {
+                self.array(&item)
+            } else {
+                vec![item]
+            })
+            .collect();
+        Self::items(items, false)
+    }
+
+    fn add_commands(&mut self, path: &[String], items: Vec<(String, String)>) {
+        let command = command_at(&mut self.base, path);
+        for (name, description) in items {
+            let sub = command_at(command, &[name]);
+            if sub.description.is_empty() {
+                sub.description = description;
+            }
+        }
+    }
+
+    fn offer(&mut self, line: usize, candidates: Candidates, path: &[String], target: Option<&Target>) {
+        let target = match target {
+            Some(target) => target,
+            // Outside of _arguments, _describe is almost always listing the
+            // subcommands.
+            None => return match candidates {
+                Candidates::Described(items) => self.add_commands(path, items),
+                _ => self.skipped.push(format!("line {}: candidates that no argument or option offers", line)),
+            },
+        };
+
+        let command = command_at(&mut self.base, &target.path);
+        let (files, kind) = match candidates {
+            Candidates::Files => (true, OptKind::File),
+            Candidates::Words(ref words) => (false, OptKind::Choices(words.clone())),
+            Candidates::Described(ref items) => (false, OptKind::Choices(items.iter().map(|i| i.0.clone()).collect())),
+            Candidates::Command(ref command) => (false, OptKind::Command(command.clone())),
+        };
+
+        match target.slot {
+            Slot::Option(ref names) => {
+                let opt = command.options.iter_mut()
+                    .find(|o| o.longs.iter().chain(o.shorts.iter()).any(|n| names.contains(n)));
+                if let Some(opt) = opt {
+                    opt.argkind = Some(kind);
+                }
+            },
+            Slot::Argument(_, _, _, true) => {
+                let items = match candidates {
+                    Candidates::Described(items) => items,
+                    Candidates::Words(words) => words.into_iter().map(|w| (w, String::new())).collect(),
+                    _ => return self.skipped.push(format!("line {}: subcommands that aren't listed", line)),
+                };
+                for (name, description) in items {
+                    let sub = command_at(command, &[name]);
+                    if sub.description.is_empty() {
+                        sub.description = description;
+                    }
+                }
+            },
+            Slot::Argument(ref name, position, optional, false) => {
+                let kind = if files && position.is_none() { OptKind::FilePlus } else { kind };
+                let name = match &name[..] {
+                    "" if files => "FILE",
+                    "" => "ARG",
+                    name => name,
+                };
+                if let Some(arg) = command.arguments.iter_mut().find(|a| a.name == name) {
+                    if let (&mut OptKind::Choices(ref mut choices), OptKind::Choices(more)) = (&mut arg.kind, kind) {
+                        choices.extend(more);
+                    }
+                    return;
+                }
+                // Keep the arguments in the order of their positions.
+                let position = position.unwrap_or(usize::max_value());
+                let positions = self.positions.entry(target.path.clone()).or_insert_with(Vec::new);
+                let i = positions.iter().filter(|&&p| p <= position).count();
+                positions.insert(i, position);
+                command.arguments.insert(i, Argument::new(name, kind, optional));
+            },
+        }
+    }
+}
+
+pub fn import(script: &str, name: Option<&str>) -> Result<Import, CompletistError> {
+    let nodes = try!(shell::parse(script).map_err(CompletistError::ImportError));
+    let compdef = script.lines().next()
+        .filter(|line| line.starts_with("#compdef"))
+        .and_then(|line| line.split_whitespace().skip(1).find(|w| !w.starts_with('-') && !w.contains('=')));
+    let program = match name.or(compdef) {
+        Some(program) => program.to_string(),
+        None => return Err(CompletistError::ImportError("no #compdef line to take the program name from".to_string())),
+    };
+
+    let mut functions = HashMap::new();
+    collect(&nodes, &mut functions);
+    let mut importer = Importer {
+        functions: functions,
+        arrays: HashMap::new(),
+        states: HashMap::new(),
+        handled: HashSet::new(),
+        running: Vec::new(),
+        visited: HashSet::new(),
+        positions: HashMap::new(),
+        base: Command::new(&program),
+        skipped: Vec::new(),
+    };
+    importer.run(&nodes, &[], None);
+    // Autoloaded files often only define the main function.
+    let main = format!("_{}", program);
+    if importer.functions.contains_key(&main) && !importer.visited.contains(&main) {
+        importer.call(&main, &[], None);
+    }
+
+    let mut unused: Vec<String> = importer.functions.keys()
+        .filter(|f| !importer.visited.contains(*f))
+        .map(|f| format!("function `{}` is never used", f))
+        .collect();
+    let mut states: Vec<String> = importer.states.keys()
+        .filter(|s| !importer.handled.contains(*s))
+        .map(|s| format!("state `{}` is never handled", s))
+        .collect();
+    unused.sort();
+    states.sort();
+    importer.skipped.extend(unused);
+    importer.skipped.extend(states);
+
+    Ok(Import {
+        program: Program::new(&program, importer.base, Metadata::new()),
+        skipped: importer.skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::program::OptKind;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn splitting() {
+        assert_eq!(message_action("config file:_files -g '*.toml':more"), ("config file", "_files -g '*.toml'"));
+        assert_eq!(message_action(" "), (" ", ""));
+        assert_eq!(described("a\\:b:the a\\:b thing"), ("a:b".to_string(), "the a\\:b thing".to_string()));
+    }
+
+    #[test]
+    fn states() {
+        let import = import(include_str!("../../../samples/zsh/_svc"), None).unwrap();
+        let prog = import.program;
+        let base = &prog.base_command;
+        assert_eq!(prog.name, "svc");
+        assert!(base.arguments.is_empty());
+
+        let options: Vec<(Vec<String>, Vec<String>)> = base.options.iter().map(|o| (o.longs.clone(), o.shorts.clone())).collect();
+        assert_eq!(options, vec![
+            (strings(&["--help"]), strings(&["-h"])),
+            (strings(&["--verbose"]), strings(&["-v"])),
+            (strings(&["--config"]), strings(&["-c"])),
+            (strings(&["--log-level"]), vec![]),
+            (strings(&["--color"]), vec![]),
+        ]);
+        assert_eq!(base.options[0].description, "show help and exit");
+        assert_eq!(base.options[0].argkind, None);
+        assert_eq!(base.options[2].argkind, Some(OptKind::File));
+        assert_eq!(base.options[3].argkind, Some(OptKind::Choices(strings(&["debug", "info", "warn"]))));
+        assert_eq!(base.options[4].argkind, Some(OptKind::Choices(strings(&["auto", "always", "never"]))));
+
+        let commands: Vec<(&str, &str)> = base.commands.iter().map(|c| (&c.name[..], &c.description[..])).collect();
+        assert_eq!(commands, vec![("start", "start a service"), ("stop", "stop a service"),
+            ("logs", "show the log of a service")]);
+        for name in &["start", "stop"] {
+            let command = prog.find(&[name]).unwrap();
+            assert_eq!(command.options[0].longs, strings(&["--wait"]));
+            assert_eq!(command.arguments[0].name, "unit");
+            assert_eq!(command.arguments[0].kind,
+                OptKind::Command("systemctl list-units --plain --no-legend | cut -d' ' -f1".to_string()));
+        }
+
+        let logs = prog.find(&["logs"]).unwrap();
+        assert_eq!(logs.options[1].shorts, strings(&["-n"]));
+        assert_eq!(logs.options[1].argkind, Some(OptKind::File));
+        let arguments: Vec<(&str, bool)> = logs.arguments.iter().map(|a| (&a.name[..], a.optional)).collect();
+        assert_eq!(arguments, vec![("when", false), ("unit", false)]);
+        assert_eq!(logs.arguments[0].kind, OptKind::Choices(strings(&["today", "yesterday"])));
+
+        assert_eq!(import.skipped, strings(&["line 43: `_hosts` isn't supported, completing files instead"]));
+    }
+
+    #[test]
+    fn functions() {
+        let import = import(include_str!("../../../samples/zsh/_pkg"), None).unwrap();
+        let prog = import.program;
+        let base = &prog.base_command;
+        let commands: Vec<(&str, &str)> = base.commands.iter().map(|c| (&c.name[..], &c.description[..])).collect();
+        assert_eq!(commands, vec![("install", "install packages"), ("remove", "remove packages"),
+            ("search", "search for packages"), ("rm", "")]);
+        assert_eq!(base.options.len(), 2);
+
+        let install = prog.find(&["install"]).unwrap();
+        assert_eq!(install.options[0].longs, strings(&["--yes"]));
+        assert_eq!(install.options[1].argkind, Some(OptKind::File));
+        assert_eq!(install.arguments[0].name, "package");
+        assert!(install.arguments[0].optional);
+        assert_eq!(install.arguments[0].kind, OptKind::Command("pkg list --available 2>/dev/null".to_string()));
+        for name in &["remove", "rm"] {
+            let remove = prog.find(&[name]).unwrap();
+            assert_eq!(remove.arguments[0].kind, OptKind::Command("pkg list --installed".to_string()));
+        }
+        assert!(prog.find(&["search"]).unwrap().arguments.is_empty());
+        assert!(import.skipped.is_empty());
+    }
+
+    #[test]
+    fn reports() {
+        let script = "#compdef y x\n\
+            _y() { _arguments '+x[plus]' '--' '1:f:{_foo}' '2:g:->nowhere' }\n\
+            _unused() { }\n\
+            case $service in y) ;; esac";
+        let import = import(script, None).unwrap();
+        assert_eq!(import.program.name, "y");
+        assert_eq!(import.skipped, strings(&[
+            "line 4: case on `$service`",
+            "line 2: spec `+x[plus]` isn't supported",
+            "line 2: `--` specs aren't supported",
+            "line 2: code `{_foo}` isn't supported, completing files instead",
+            "function `_unused` is never used",
+            "state `nowhere` is never handled",
+        ]));
+        assert!(super::import("_arguments '-a[all]'", None).is_err());
+        assert_eq!(super::import("_arguments '-a[all]'", Some("x")).unwrap().program.base_command.options.len(), 1);
+    }
+
+    #[test]
+    fn exclusions() {
+        let script = "_arguments '(-a --all -n --none)'{-a,--all}'[all]' '(-a --all)-n[none]' '*-v[more]' \
+            '(- *)--help[help]'";
+        let import = import(script, Some("x")).unwrap();
+        let options = &import.program.base_command.options;
+        let found: Vec<(String, Vec<String>, bool)> = options.iter()
+            .map(|o| (o.name(), o.excludes.clone(), o.repeatable))
+            .collect();
+        assert_eq!(found, vec![
+            ("all".to_string(), strings(&["-n", "--none"]), false),
+            ("n".to_string(), strings(&["-a", "--all"]), false),
+            ("v".to_string(), vec![], true),
+            ("help".to_string(), vec![], false),
+        ]);
+        assert_eq!(import.skipped, strings(&[
+            "line 1: `--help` excludes `-`, which isn't supported",
+            "line 1: `--help` excludes `*`, which isn't supported",
+        ]));
+        assert_eq!(super::import("_arguments '-a[all]'", Some("x")).unwrap().program.base_command.options.len(), 1);
+    }
+}
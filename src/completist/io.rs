@@ -3,6 +3,8 @@ use std::io::{BufReader, BufWriter};
 pub use std::io::{Read, Write};
 use std::io::Result;
 use std::fs::File;
+use std::rc::Rc;
+use std::cell::RefCell;
 
 pub type Input = BufReader<Box<Read>>;
 
@@ -26,8 +28,39 @@ pub fn open_output(path: &str) -> Result<Output> {
         }))
 }
 
+#[derive(Clone)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub fn capture<F>(f: F) -> Result<String> where F: FnOnce(&mut Output) -> Result<()> {
+    let buffer = Buffer(Rc::new(RefCell::new(Vec::new())));
+    {
+        let mut output = BufWriter::new(Box::new(buffer.clone()) as Box<Write>);
+        try!(f(&mut output));
+        try!(output.flush());
+    }
+
+    let bytes = buffer.0.borrow();
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     // TODO: work out how to test that correct inputs and outputs are opened
+
+    #[test]
+    fn capture_output() {
+        let text = capture(|out| out.write_all(b"hello\nworld")).unwrap();
+        assert_eq!(text, "hello\nworld");
+    }
 }
@@ -0,0 +1,202 @@
+use completist::argv::{self, ArgvError};
+use completist::importers::shell::{self, Node};
+use completist::program::Program;
+
+// Fenced blocks in these languages are read as shell commands.
+const SHELLS: &'static [&'static str] = &["", "sh", "bash", "zsh", "fish", "shell", "console", "shell-session"];
+
+// A code block's text with its first line number. Prompts are removed, and
+// in `console` blocks the lines without one are output and are blanked out so
+// the line numbers still match.
+fn code_blocks(markdown: &str) -> Vec<(usize, String)> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(String, usize, bool, Vec<String>)> = None;
+    for (i, line) in markdown.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let fence = if trimmed.starts_with("```") { Some("```") } else if trimmed.starts_with("~~~") { Some("~~~") } else { None };
+
+        if let Some((ref marker, start, session, ref mut lines)) = open {
+            if fence == Some(&marker[..]) {
+                blocks.push((start, lines.join("\n")));
+            } else {
+                let command = trimmed.trim_start_matches(|c| c == '$' || c == '%');
+                if command.len() < trimmed.len() && command.starts_with(' ') {
+                    lines.push(command.to_string());
+                } else if session {
+                    lines.push(String::new());
+                } else {
+                    lines.push(line.to_string());
+                }
+                continue;
+            }
+        } else if let Some(fence) = fence {
+            let language = trimmed[3..].trim().split(|c: char| c.is_whitespace() || c == ',').next().unwrap_or("").to_lowercase();
+            if SHELLS.contains(&&language[..]) {
+                let session = language == "console" || language == "shell-session";
+                open = Some((fence.to_string(), i + 2, session, Vec::new()));
+                continue;
+            }
+            // Other languages are skipped over, up to their closing fence.
+            open = Some((fence.to_string(), 0, true, Vec::new()));
+            continue;
+        } else {
+            continue;
+        }
+        open = None;
+    }
+    blocks.into_iter().filter(|&(start, _)| start > 0).collect()
+}
+
+fn commands(nodes: &[Node], into: &mut Vec<(usize, Vec<String>)>) {
+    for node in nodes {
+        match *node {
+            Node::Command(line, ref words) => into.push((line, words.clone())),
+            Node::Function(_, _, _, ref body) => commands(body, into),
+            Node::Case(_, _, ref branches) => for &(_, ref body) in branches {
+                commands(body, into);
+            },
+            Node::If(_, ref branches, ref otherwise) => {
+                for &(ref condition, ref body) in branches {
+                    commands(condition, into);
+                    commands(body, into);
+                }
+                commands(otherwise, into);
+            },
+            Node::Assign(..) => {},
+        }
+    }
+}
+
+// Docs write `<file>`, `[options]` and `...` where the reader fills in
+// something of their own.
+fn is_placeholder(word: &str) -> bool {
+    word == "..." || word.starts_with('<') || word.starts_with('[') || word.starts_with('{')
+}
+
+fn is_variable(word: &str) -> bool {
+    word.contains('$') || word.contains('*')
+}
+
+// Every invocation of the program in the markdown's shell code blocks, with
+// its line number and the words after the program name.
+pub fn invocations(markdown: &str, program: &str) -> Vec<(usize, Vec<String>)> {
+    let mut found = Vec::new();
+    for (start, block) in code_blocks(markdown) {
+        let nodes = match shell::parse(&block) {
+            Ok(nodes) => nodes,
+            Err(_) => continue,
+        };
+        let mut all = Vec::new();
+        commands(&nodes, &mut all);
+        for (line, words) in all {
+            // Skip any `VAR=value` prefixes and wrappers in front of it.
+            let position = words.iter().position(|w| w == program || w.ends_with(&format!("/{}", program)));
+            let position = match position {
+                Some(i) if words[..i].iter().all(|w| w.contains('=') || ["sudo", "env", "exec", "time"].contains(&&w[..])) => i,
+                _ => continue,
+            };
+            let args = words[position + 1..].iter().filter(|w| !is_placeholder(w)).cloned().collect();
+            found.push((start + line - 1, args));
+        }
+    }
+    found
+}
+
+// Errors that come from a value the reader is meant to fill in are not
+// really errors.
+fn is_real(error: &ArgvError) -> bool {
+    match *error {
+        ArgvError::InvalidChoice(_, ref value, _) => !is_variable(value),
+        ArgvError::MissingArgument(_) | ArgvError::MissingValue(_) | ArgvError::MissingCommand(_) => false,
+        _ => true,
+    }
+}
+
+pub fn check_markdown(prog: &Program, markdown: &str) -> Vec<(usize, Vec<String>, ArgvError)> {
+    invocations(markdown, &prog.name).into_iter()
+        .filter_map(|(line, args)| match argv::parse(prog, &args) {
+            Err(failure) if is_real(&failure.error) => Some((line, args, failure.error)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::argv::ArgvError;
+    use completist::parse_program;
+
+    const README: &'static str = "# svc
+
+Start everything with:
+
+```sh
+svc start fast
+sudo svc -v start slow unit-a \\
+    unit-b
+svc stop  # gone since 2.0
+```
+
+```console
+$ svc --colour=always start fast
+svc: unknown option
+$ svc start $MODE <units>...
+```
+
+```python
+svc('start')
+```
+
+~~~
+FOO=1 /usr/bin/svc start medium | grep x && svc --verbose
+~~~
+";
+
+    const SPEC: &'static str = "
+        name = 'svc'
+        [[option]]
+        long = '--verbose'
+        short = '-v'
+        description = 'say more'
+        [[command]]
+        name = 'start'
+        [[command.argument]]
+        name = 'MODE'
+        kind = 'choice(fast|slow)'
+        [[command.argument]]
+        name = 'UNITS'
+        kind = 'file+'
+        optional = true
+    ";
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn find_invocations() {
+        assert_eq!(invocations(README, "svc"), vec![
+            (6, strings(&["start", "fast"])),
+            (7, strings(&["-v", "start", "slow", "unit-a", "unit-b"])),
+            (9, strings(&["stop"])),
+            (13, strings(&["--colour=always", "start", "fast"])),
+            (15, strings(&["start", "$MODE"])),
+            (23, strings(&["start", "medium"])),
+            (23, strings(&["--verbose"])),
+        ]);
+    }
+
+    #[test]
+    fn check() {
+        let prog = parse_program(SPEC).unwrap();
+        let errors: Vec<(usize, ArgvError)> = check_markdown(&prog, README).into_iter()
+            .map(|(line, _, error)| (line, error))
+            .collect();
+        assert_eq!(errors, vec![
+            (9, ArgvError::UnknownCommand("stop".to_string())),
+            (13, ArgvError::UnknownOption("--colour".to_string())),
+            (23, ArgvError::InvalidChoice("MODE".to_string(), "medium".to_string(), strings(&["fast", "slow"]))),
+        ]);
+    }
+}
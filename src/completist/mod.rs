@@ -1,6 +1,16 @@
 pub mod io;
 pub mod program;
 pub mod formatter;
+pub mod engine;
+pub mod argv;
+pub mod lint;
+pub mod cases;
+pub mod dynamic;
+pub mod cli;
+pub mod usage;
+pub mod help;
+pub mod backends;
+pub mod importers;
 mod utils;
 
 extern crate toml;
@@ -9,6 +19,48 @@ extern crate toml;
 pub enum CompletistError {
     ParserError(Vec<toml::ParserError>),
     InvalidConfig(()),
+    IoError(std::io::Error),
+    UsageError(String),
+    ImportError(String),
+    ArgvError(argv::ArgvError),
+    InvalidExamples(usize),
+    FailedCases(usize, usize),
+}
+
+impl std::fmt::Display for CompletistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            CompletistError::ParserError(ref errors) => {
+                for error in errors {
+                    try!(writeln!(f, "{}", error));
+                }
+                Ok(())
+            },
+            CompletistError::InvalidConfig(()) => write!(f, "invalid spec"),
+            CompletistError::IoError(ref err) => write!(f, "{}", err),
+            CompletistError::UsageError(ref usage) => write!(f, "{}", usage),
+            CompletistError::ImportError(ref message) => write!(f, "cannot import: {}", message),
+            CompletistError::ArgvError(ref error) => write!(f, "{}", error),
+            CompletistError::InvalidExamples(count) => write!(f, "{} invalid command lines", count),
+            CompletistError::FailedCases(failed, total) => write!(f, "{} of {} cases failed", failed, total),
+        }
+    }
+}
+
+impl From<std::io::Error> for CompletistError {
+    fn from(err: std::io::Error) -> Self {
+        CompletistError::IoError(err)
+    }
+}
+
+pub fn parse_program(string: &str) -> Result<program::Program, CompletistError> {
+    let mut parser = toml::Parser::new(string);
+    let toml = try!(parser.parse()
+        .ok_or_else(move || parser.errors)
+        .map_err(CompletistError::ParserError));
+
+    program::Program::from_toml(&toml)
+        .map_err(CompletistError::InvalidConfig)
 }
 
 pub struct Completist {
@@ -30,14 +82,7 @@ impl Completist {
 
     pub fn parse_string(&mut self, string: &str)
                         -> Result<&mut Self, CompletistError> {
-        let mut parser = toml::Parser::new(string);
-        let toml = try!(parser.parse()
-            .ok_or_else(move || parser.errors)
-            .map_err(CompletistError::ParserError));
-
-        let prog = try!(program::Program::from_toml(&toml)
-            .map_err(CompletistError::InvalidConfig));
-
+        let prog = try!(parse_program(string));
         self.programs.push(prog);
         Ok(self)
     }
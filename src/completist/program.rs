@@ -3,12 +3,13 @@ use std::ascii::AsciiExt;
 extern crate toml;
 extern crate regex;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum OptKind {
     File,
     FilePlus,
     Command(String),
     Function(String),
+    Choices(Vec<String>),
 }
 
 pub fn construct_optkind(inp: &str) -> Option<OptKind> {
@@ -20,6 +21,10 @@ pub fn construct_optkind(inp: &str) -> Option<OptKind> {
         ^(?i:command)\(
             ( (?: [^\\)(] | \\\( | \\\) | \\\\ )* )
         \)$").unwrap();
+    let choicere = regex::Regex::new(r"(?xs)
+        ^(?i:choice)\(
+            ( (?: [^\\)(] | \\\( | \\\) | \\\\ | \\\| )* )
+        \)$").unwrap();
 
     if inp.eq_ignore_ascii_case("file") {
         Some(OptKind::File)
@@ -37,22 +42,90 @@ pub fn construct_optkind(inp: &str) -> Option<OptKind> {
             .replace("\\)", ")")
             .replace("\\\\", "\\")
             .to_string()))
+    } else if let Some(capture) = choicere.captures(inp).and_then(|i| i.at(1)) {
+        Some(OptKind::Choices(split_choices(capture)))
     } else {
         None
     }
 }
 
+// Choices are separated by `|`, which is written `\|` inside a choice.
+fn split_choices(inp: &str) -> Vec<String> {
+    let mut choices = Vec::new();
+    let mut choice = String::new();
+    let mut chars = inp.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => choice.extend(chars.next()),
+            '|' => choices.push(::std::mem::replace(&mut choice, String::new())),
+            c => choice.push(c),
+        }
+    }
+    choices.push(choice);
+    choices.into_iter()
+        .map(|choice| choice.trim().to_string())
+        .filter(|choice| !choice.is_empty())
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cache {
+    pub ttl: u64,
+    pub per_directory: bool,
+    pub watch: Vec<String>,
+}
+
+impl Cache {
+    pub fn new(ttl: u64) -> Self {
+        Cache {
+            ttl: ttl,
+            per_directory: true,
+            watch: Vec::new(),
+        }
+    }
+
+    // Either a bare TTL in seconds, or a table with `ttl`, `per-directory`
+    // and `watch` keys.
+    fn from_toml(value: &toml::Value) -> Option<Self> {
+        if let Some(ttl) = value.as_integer() {
+            return if ttl >= 0 { Some(Self::new(ttl as u64)) } else { None };
+        }
+
+        let table = match value.as_table() {
+            Some(table) => table,
+            None => return None,
+        };
+        let mut cache = match table.get("ttl").and_then(|a| a.as_integer()) {
+            Some(ttl) if ttl >= 0 => Self::new(ttl as u64),
+            _ => return None,
+        };
+        if let Some(per_directory) = table.get("per-directory").and_then(|a| a.as_bool()) {
+            cache.per_directory = per_directory;
+        }
+        if let Some(watch) = table.get("watch").and_then(|a| a.as_slice()) {
+            cache.watch = watch.iter()
+                .filter_map(|w| w.as_str())
+                .map(|w| w.to_string())
+                .collect();
+        }
+
+        Some(cache)
+    }
+}
+
 pub struct Command {
-    name: String,
-    arguments: Vec<Argument>,
-    options: Vec<Opt>,
-    commands: Vec<Command>,
+    pub name: String,
+    pub description: String,
+    pub arguments: Vec<Argument>,
+    pub options: Vec<Opt>,
+    pub commands: Vec<Command>,
 }
 
 impl Command {
-    fn new(name: &str) -> Self {
+    pub fn new(name: &str) -> Self {
         Command {
             name: name.to_string(),
+            description: String::new(),
             arguments: Vec::new(),
             options: Vec::new(),
             commands: Vec::new(),
@@ -66,6 +139,9 @@ impl Command {
         };
 
         let mut command = Self::new(name);
+        if let Some(description) = data.get("description").and_then(|d| d.as_str()) {
+            command.description = description.to_string();
+        }
 
         if let Some(arguments) = data.get("argument").and_then(|a| a.as_slice()) {
             for argument in arguments {
@@ -99,17 +175,19 @@ impl Command {
 }
 
 pub struct Argument {
-    name: String,
-    kind: OptKind,
-    optional: bool,
+    pub name: String,
+    pub kind: OptKind,
+    pub optional: bool,
+    pub cache: Option<Cache>,
 }
 
 impl Argument {
-    fn new(name: &str, kind: OptKind, optional: bool) -> Self {
+    pub fn new(name: &str, kind: OptKind, optional: bool) -> Self {
         Argument {
             name: name.to_string(),
             kind: kind,
             optional: optional,
+            cache: None,
         }
     }
 
@@ -122,7 +200,9 @@ impl Argument {
         let optional = table.get("optional")
             .and_then(|a| a.as_bool());
         if name.is_some() && kind.is_some() {
-            Some(Argument::new(name.unwrap(), kind.unwrap(), optional.unwrap_or(false)))
+            let mut argument = Argument::new(name.unwrap(), kind.unwrap(), optional.unwrap_or(false));
+            argument.cache = table.get("cache").and_then(|a| Cache::from_toml(a));
+            Some(argument)
         } else {
             None
         }
@@ -134,19 +214,33 @@ pub struct Opt {
     pub shorts: Vec<String>,
     pub description: String,
     pub argkind: Option<OptKind>,
+    pub cache: Option<Cache>,
+    // Names of the options that can't be given along with this one.
+    pub excludes: Vec<String>,
+    pub repeatable: bool,
 }
 
 impl Opt {
-    fn new(longs: Vec<String>, shorts: Vec<String>,
+    pub fn new(longs: Vec<String>, shorts: Vec<String>,
             description: &str, argkind: Option<OptKind>) -> Self {
         Opt {
             longs: longs,
             shorts: shorts,
             description: description.to_string(),
             argkind: argkind,
+            cache: None,
+            excludes: Vec::new(),
+            repeatable: true,
         }
     }
 
+    pub fn name(&self) -> String {
+        self.longs.iter().chain(self.shorts.iter())
+            .next()
+            .map(|name| name.trim_start_matches('-').to_string())
+            .unwrap_or_else(String::new)
+    }
+
     fn normalize_long(s: String) -> String {
         if s.starts_with("-") {
             s
@@ -191,24 +285,103 @@ impl Opt {
         }
 
         if description.is_some() && (short_vec.len() > 0 || long_vec.len() > 0) {
-            Some(Self::new(long_vec, short_vec, description.unwrap(), argkind))
+            let mut opt = Self::new(long_vec, short_vec, description.unwrap(), argkind);
+            opt.cache = table.get("cache").and_then(|a| Cache::from_toml(a));
+            if let Some(excludes) = table.get("excludes").and_then(|a| a.as_slice()) {
+                opt.excludes = excludes.iter().filter_map(|e| e.as_str()).map(|e| e.to_string()).collect();
+            }
+            opt.repeatable = table.get("repeatable").and_then(|a| a.as_bool()).unwrap_or(true);
+            Some(opt)
         } else {
             None
         }
     }
 }
 
+// Top-level details that only matter for documentation.
+pub struct Metadata {
+    pub section: String,
+    pub version: Option<String>,
+    pub date: Option<String>,
+    pub authors: Vec<String>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Metadata {
+            section: "1".to_string(),
+            version: None,
+            date: None,
+            authors: Vec::new(),
+        }
+    }
+
+    fn from_toml(data: &toml::Table) -> Self {
+        let section = match data.get("section") {
+            Some(&toml::Value::Integer(section)) => section.to_string(),
+            Some(&toml::Value::String(ref section)) => section.clone(),
+            _ => "1".to_string(),
+        };
+
+        let mut authors = Vec::new();
+        if let Some(author) = data.get("author").and_then(|a| a.as_str()) {
+            authors.push(author.to_string());
+        } else if let Some(list) = data.get("authors").and_then(|a| a.as_slice()) {
+            authors.extend(list.iter().filter_map(|a| a.as_str()).map(|a| a.to_string()));
+        }
+
+        Metadata {
+            section: section,
+            version: data.get("version").and_then(|v| v.as_str()).map(|v| v.to_string()),
+            date: data.get("date").and_then(|d| d.as_str()).map(|d| d.to_string()),
+            authors: authors,
+        }
+    }
+}
+
 pub struct Program {
     pub name: String,
-    base_command: Command,
+    pub base_command: Command,
+    pub meta: Metadata,
 }
 
 impl Program {
-    fn new(name: &str, base_command: Command) -> Self {
+    pub fn new(name: &str, base_command: Command, meta: Metadata) -> Self {
         Program {
             name: name.to_string(),
             base_command: base_command,
+            meta: meta,
+        }
+    }
+
+    // Every command in the tree, parents before children, along with the
+    // names leading to it (starting with the program name).
+    pub fn walk(&self) -> Vec<(Vec<&str>, &Command)> {
+        fn visit<'a>(command: &'a Command, path: &mut Vec<&'a str>,
+                     into: &mut Vec<(Vec<&'a str>, &'a Command)>) {
+            path.push(&command.name);
+            into.push((path.clone(), command));
+            for sub in &command.commands {
+                visit(sub, path, into);
+            }
+            path.pop();
         }
+
+        let mut commands = Vec::new();
+        visit(&self.base_command, &mut Vec::new(), &mut commands);
+        commands
+    }
+
+    // The command reached by following subcommand names from the base command.
+    pub fn find<S: AsRef<str>>(&self, names: &[S]) -> Option<&Command> {
+        let mut command = &self.base_command;
+        for name in names {
+            match command.commands.iter().find(|c| c.name == name.as_ref()) {
+                Some(sub) => command = sub,
+                None => return None,
+            }
+        }
+        Some(command)
     }
 
     pub fn from_toml(data: &toml::Table) -> Result<Self, ()> {
@@ -216,7 +389,7 @@ impl Program {
 
         let name = try!(data.get("name").ok_or(()));
         let name = try!(name.as_str().ok_or(()));
-        let prog = Self::new(name, base_command);
+        let prog = Self::new(name, base_command, Metadata::from_toml(data));
         Ok(prog)
     }
 }
@@ -235,6 +408,35 @@ mod tests {
         assert_eq!(prog.base_command.name, "test-command");
     }
 
+    #[test]
+    fn program_metadata() {
+        let toml = toml::Parser::new("
+            name = 'cat'
+            description = 'concatenate files'
+            section = 1
+            version = '9.4'
+            date = '2026-10-18'
+            authors = ['Torbjorn Granlund', 'Richard M. Stallman']
+            [[command]]
+            name = 'sub'
+            description = 'a subcommand'
+        ").parse().unwrap();
+        let prog = Program::from_toml(&toml).unwrap();
+
+        assert_eq!(prog.base_command.description, "concatenate files");
+        assert_eq!(prog.base_command.commands[0].description, "a subcommand");
+        assert_eq!(prog.meta.section, "1");
+        assert_eq!(prog.meta.version, Some("9.4".to_string()));
+        assert_eq!(prog.meta.date, Some("2026-10-18".to_string()));
+        assert_eq!(prog.meta.authors, vec!["Torbjorn Granlund", "Richard M. Stallman"]);
+
+        let toml = toml::Parser::new("name = 'cat'\nsection = '8'\nauthor = 'me'").parse().unwrap();
+        let prog = Program::from_toml(&toml).unwrap();
+        assert_eq!(prog.meta.section, "8");
+        assert_eq!(prog.meta.version, None);
+        assert_eq!(prog.meta.authors, vec!["me"]);
+    }
+
     #[test]
     fn create_program_with_arguments() {
         let toml = toml::Parser::new("
@@ -338,6 +540,17 @@ mod tests {
         let prog = Program::from_toml(&toml).unwrap();
         assert_eq!(prog.base_command.arguments.len(), 0);
 
+        let toml = toml::Parser::new(r"
+            name = 'test-command'
+            [[argument]]
+            name = 'ACTION'
+            kind = 'choice(start | stop|status \(default\)|a\|b)'
+        ").parse().unwrap();
+        let prog = Program::from_toml(&toml).unwrap();
+        assert_eq!(prog.base_command.arguments.len(), 1);
+        assert_eq!(prog.base_command.arguments[0].kind, OptKind::Choices(vec![
+            "start".to_string(), "stop".to_string(), "status (default)".to_string(), "a|b".to_string()]));
+
         let toml = toml::Parser::new(r"
             name = 'test-command'
             [[argument]]
@@ -384,6 +597,50 @@ mod tests {
         assert_eq!(prog.base_command.options[0].shorts[0], "-o");
         assert_eq!(prog.base_command.options[0].description, "desc");
         assert_eq!(prog.base_command.options[0].argkind, Some(OptKind::File));
+        assert!(prog.base_command.options[0].excludes.is_empty());
+        assert!(prog.base_command.options[0].repeatable);
+
+        let toml = toml::Parser::new("
+            name = 'test-command'
+            [[option]]
+            long = '--all'
+            description = 'desc'
+            excludes = ['--none', '-n']
+            repeatable = false
+        ").parse().unwrap();
+        let prog = Program::from_toml(&toml).unwrap();
+
+        assert_eq!(prog.base_command.options[0].excludes, vec!["--none", "-n"]);
+        assert!(!prog.base_command.options[0].repeatable);
+    }
+
+    #[test]
+    fn cache_metadata() {
+        let toml = toml::Parser::new("
+            name = 'test-command'
+            [[argument]]
+            name = 'POD'
+            kind = 'command(kubectl get pods)'
+            cache = 30
+            [[option]]
+            long = '--bin'
+            argkind = 'command(ls target/debug)'
+            description = 'desc'
+            cache = { ttl = 60, per-directory = false, watch = ['Cargo.toml'] }
+            [[option]]
+            long = '--other'
+            description = 'desc'
+            cache = { per-directory = false }
+        ").parse().unwrap();
+        let prog = Program::from_toml(&toml).unwrap();
+
+        assert_eq!(prog.base_command.arguments[0].cache, Some(Cache {
+            ttl: 30, per_directory: true, watch: vec![],
+        }));
+        assert_eq!(prog.base_command.options[0].cache, Some(Cache {
+            ttl: 60, per_directory: false, watch: vec!["Cargo.toml".to_string()],
+        }));
+        assert_eq!(prog.base_command.options[1].cache, None);
     }
 
     #[test]
@@ -433,4 +690,30 @@ mod tests {
         assert_eq!(prog.base_command.commands[0].arguments.len(), 1);
         assert_eq!(prog.base_command.commands[0].options.len(), 1);
     }
+
+    #[test]
+    fn walk_commands() {
+        let toml = toml::Parser::new("
+            name = 'test-command'
+            [[command]]
+            name = 'one'
+            [[command.command]]
+            name = 'nested'
+            [[command]]
+            name = 'two'
+        ").parse().unwrap();
+        let prog = Program::from_toml(&toml).unwrap();
+
+        let paths: Vec<Vec<&str>> = prog.walk().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(paths, vec![
+            vec!["test-command"],
+            vec!["test-command", "one"],
+            vec!["test-command", "one", "nested"],
+            vec!["test-command", "two"],
+        ]);
+
+        assert_eq!(prog.find::<&str>(&[]).map(|c| &c.name[..]), Some("test-command"));
+        assert_eq!(prog.find(&["one", "nested"]).map(|c| &c.name[..]), Some("nested"));
+        assert!(prog.find(&["nested"]).is_none());
+    }
 }
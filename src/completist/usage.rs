@@ -0,0 +1,245 @@
+use completist::program::{Command, Opt, OptKind};
+use completist::utils::wrap;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Style {
+    Gnu,     // cat [OPTION]... [FILE]...
+    Docopt,  // cat [options] [<file>...]
+    Clap,    // cat [-A|--show-all] [FILE]...
+}
+
+impl Style {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gnu" => Some(Style::Gnu),
+            "docopt" => Some(Style::Docopt),
+            "clap" => Some(Style::Clap),
+            _ => None,
+        }
+    }
+}
+
+// Pieces of a usage line, so that formats with markup (man pages) can
+// highlight literals and placeholders differently.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Part {
+    Literal(String),
+    Placeholder(String),
+    Syntax(&'static str),
+}
+
+// A group of parts that is never split across lines, like `[--bin <BIN>]`.
+pub type Element = Vec<Part>;
+
+fn placeholder(name: &str, style: Style, optional: bool) -> Element {
+    match style {
+        Style::Gnu if optional => vec![Part::Syntax("["), Part::Placeholder(name.to_uppercase()), Part::Syntax("]")],
+        Style::Gnu => vec![Part::Placeholder(name.to_uppercase())],
+        Style::Docopt if optional => vec![Part::Syntax("[<"), Part::Placeholder(name.to_lowercase()), Part::Syntax(">]")],
+        Style::Docopt => vec![Part::Syntax("<"), Part::Placeholder(name.to_lowercase()), Part::Syntax(">")],
+        Style::Clap if optional => vec![Part::Syntax("["), Part::Placeholder(name.to_uppercase()), Part::Syntax("]")],
+        Style::Clap => vec![Part::Syntax("<"), Part::Placeholder(name.to_uppercase()), Part::Syntax(">")],
+    }
+}
+
+fn option(opt: &Opt) -> Element {
+    let mut element = vec![Part::Syntax("[")];
+    let names: Vec<&String> = opt.shorts.iter().chain(opt.longs.iter()).collect();
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            element.push(Part::Syntax("|"));
+        }
+        element.push(Part::Literal(name.to_string()));
+    }
+    if opt.argkind.is_some() {
+        element.push(Part::Syntax(" "));
+        element.extend(placeholder(&opt.name(), Style::Clap, false));
+    }
+    element.push(Part::Syntax("]"));
+    element
+}
+
+fn subcommands(command: &Command, style: Style) -> Element {
+    let optional = !command.arguments.is_empty();
+    match style {
+        Style::Docopt => {
+            let mut element = vec![Part::Syntax(if optional { "[" } else { "(" })];
+            for (i, sub) in command.commands.iter().enumerate() {
+                if i > 0 {
+                    element.push(Part::Syntax(" | "));
+                }
+                element.push(Part::Literal(sub.name.clone()));
+            }
+            element.push(Part::Syntax(if optional { "]" } else { ")" }));
+            element
+        },
+        Style::Gnu => placeholder("COMMAND", style, optional),
+        Style::Clap => placeholder("SUBCOMMAND", style, optional),
+    }
+}
+
+// The usage of a command as unbreakable elements, starting with its path.
+pub fn elements(path: &[&str], command: &Command, style: Style) -> Vec<Element> {
+    let mut elements = vec![vec![Part::Literal(path.join(" "))]];
+
+    if !command.options.is_empty() {
+        match style {
+            Style::Gnu => elements.push(vec![Part::Syntax("["), Part::Placeholder("OPTION".to_string()),
+                Part::Syntax("]...")]),
+            Style::Docopt => elements.push(vec![Part::Syntax("["), Part::Literal("options".to_string()),
+                Part::Syntax("]")]),
+            Style::Clap => elements.extend(command.options.iter().map(option)),
+        }
+    }
+
+    if !command.commands.is_empty() {
+        elements.push(subcommands(command, style));
+    }
+
+    for arg in &command.arguments {
+        let variadic = arg.kind == OptKind::FilePlus;
+        let mut element = if variadic && style == Style::Docopt && arg.optional {
+            vec![Part::Syntax("[<"), Part::Placeholder(arg.name.to_lowercase()), Part::Syntax(">...]")]
+        } else {
+            placeholder(&arg.name, style, arg.optional)
+        };
+        if variadic && !(style == Style::Docopt && arg.optional) {
+            element.push(Part::Syntax("..."));
+        }
+        elements.push(element);
+    }
+    elements
+}
+
+pub fn render(element: &Element) -> String {
+    element.iter()
+        .map(|part| match *part {
+            Part::Literal(ref s) | Part::Placeholder(ref s) => &s[..],
+            Part::Syntax(s) => s,
+        })
+        .collect()
+}
+
+// Kinds as a reader would describe them, with code spans marked by backticks.
+pub fn describe_kind(kind: &OptKind, optional: bool) -> String {
+    let description = match *kind {
+        OptKind::File => "a file".to_string(),
+        OptKind::FilePlus if optional => "zero or more files".to_string(),
+        OptKind::FilePlus => "one or more files".to_string(),
+        OptKind::Command(ref cmd) => format!("one of the lines printed by `{}`", cmd),
+        OptKind::Function(ref func) => format!("a value suggested by `{}`", func),
+        OptKind::Choices(ref choices) => {
+            let choices: Vec<String> = choices.iter().map(|c| format!("`{}`", c)).collect();
+            format!("one of {}", choices.join(", "))
+        },
+    };
+    if optional && *kind != OptKind::FilePlus {
+        format!("{} (optional)", description)
+    } else {
+        description
+    }
+}
+
+// The usage as plain text wrapped to `width` columns, with continuation lines
+// lined up after the command path (or indented a little when that is too far).
+pub fn usage(path: &[&str], command: &Command, style: Style, width: usize) -> String {
+    let words: Vec<String> = elements(path, command, style).iter().map(render).collect();
+    let mut indent = words[0].chars().count() + 1;
+    if indent > width / 2 {
+        indent = 4;
+    }
+    wrap(&words, width, 0, indent).join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use completist::parse_program;
+    use completist::program::Program;
+
+    fn program() -> Program {
+        parse_program("
+            name = 'cargo'
+            [[command]]
+            name = 'bench'
+            [[command.argument]]
+            name = 'BENCHNAME'
+            kind = 'choice(a|b)'
+            optional = true
+            [[command.argument]]
+            name = 'args'
+            kind = 'file+'
+            optional = true
+            [[command.option]]
+            long = '--lib'
+            description = 'only the library'
+            [[command.option]]
+            long = '--bin'
+            argkind = 'command(ls target/debug)'
+            description = 'only the binary'
+            [[command.option]]
+            long = '--verbose'
+            short = '-v'
+            description = 'say more'
+            [[command]]
+            name = 'build'
+            [[command.argument]]
+            name = 'FILE'
+            kind = 'file+'
+        ").unwrap()
+    }
+
+    #[test]
+    fn styles() {
+        let prog = program();
+        let bench = prog.find(&["bench"]).unwrap();
+        assert_eq!(usage(&["cargo", "bench"], bench, Style::Gnu, 80),
+            "cargo bench [OPTION]... [BENCHNAME] [ARGS]...");
+        assert_eq!(usage(&["cargo", "bench"], bench, Style::Docopt, 80),
+            "cargo bench [options] [<benchname>] [<args>...]");
+        assert_eq!(usage(&["cargo", "bench"], bench, Style::Clap, 80),
+            "cargo bench [--lib] [--bin <BIN>] [-v|--verbose] [BENCHNAME] [ARGS]...");
+
+        let build = prog.find(&["build"]).unwrap();
+        assert_eq!(usage(&["cargo", "build"], build, Style::Gnu, 80), "cargo build FILE...");
+        assert_eq!(usage(&["cargo", "build"], build, Style::Docopt, 80), "cargo build <file>...");
+        assert_eq!(usage(&["cargo", "build"], build, Style::Clap, 80), "cargo build <FILE>...");
+
+        assert_eq!(usage(&["cargo"], &prog.base_command, Style::Gnu, 80), "cargo COMMAND");
+        assert_eq!(usage(&["cargo"], &prog.base_command, Style::Docopt, 80), "cargo (bench | build)");
+        assert_eq!(usage(&["cargo"], &prog.base_command, Style::Clap, 80), "cargo <SUBCOMMAND>");
+    }
+
+    #[test]
+    fn wrapping() {
+        let prog = program();
+        let bench = prog.find(&["bench"]).unwrap();
+        assert_eq!(usage(&["cargo", "bench"], bench, Style::Clap, 40),
+            "cargo bench [--lib] [--bin <BIN>]\n            [-v|--verbose] [BENCHNAME]\n            [ARGS]...");
+        assert_eq!(usage(&["cargo", "bench"], bench, Style::Clap, 20),
+            "cargo bench [--lib]\n    [--bin <BIN>]\n    [-v|--verbose]\n    [BENCHNAME]\n    [ARGS]...");
+    }
+
+    #[test]
+    fn human_kinds() {
+        assert_eq!(describe_kind(&OptKind::File, false), "a file");
+        assert_eq!(describe_kind(&OptKind::File, true), "a file (optional)");
+        assert_eq!(describe_kind(&OptKind::FilePlus, false), "one or more files");
+        assert_eq!(describe_kind(&OptKind::FilePlus, true), "zero or more files");
+        assert_eq!(describe_kind(&OptKind::Command("git branch".to_string()), false),
+            "one of the lines printed by `git branch`");
+        assert_eq!(describe_kind(&OptKind::Choices(vec!["a".to_string(), "b".to_string()]), false),
+            "one of `a`, `b`");
+    }
+
+    #[test]
+    fn parts() {
+        let prog = program();
+        let bench = prog.find(&["bench"]).unwrap();
+        let elements = elements(&["cargo", "bench"], bench, Style::Clap);
+        assert_eq!(elements[2], vec![Part::Syntax("["), Part::Literal("--bin".to_string()), Part::Syntax(" "),
+            Part::Syntax("<"), Part::Placeholder("BIN".to_string()), Part::Syntax(">"), Part::Syntax("]")]);
+        assert_eq!(Style::from_name("docopt"), Some(Style::Docopt));
+        assert_eq!(Style::from_name("posix"), None);
+    }
+}
@@ -6,10 +6,64 @@ pub fn normalise_extension(ext: String) -> String {
     }
 }
 
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash = (hash ^ (*byte as u64)).wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Fills lines greedily up to `width` columns. The first line starts after
+// `first` columns that are already taken, later ones are indented by `indent`
+// spaces. Words wider than a line get a line to themselves.
+pub fn wrap<S: AsRef<str>>(words: &[S], width: usize, first: usize, indent: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut used = first;
+    let mut empty = true;
+    for word in words {
+        let word = word.as_ref();
+        let len = word.chars().count();
+        if !empty && used + 1 + len > width {
+            lines.push(line);
+            line = " ".repeat(indent);
+            used = indent;
+            empty = true;
+        }
+        if !empty {
+            line.push(' ');
+            used += 1;
+        }
+        line.push_str(word);
+        used += len;
+        empty = false;
+    }
+    lines.push(line);
+    lines
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_wrap() {
+        let words = ["one", "two", "three", "four"];
+        assert_eq!(wrap(&words, 80, 0, 0), vec!["one two three four"]);
+        assert_eq!(wrap(&words, 9, 0, 2), vec!["one two", "  three", "  four"]);
+        assert_eq!(wrap(&words, 12, 4, 4), vec!["one two", "    three", "    four"]);
+        assert_eq!(wrap(&["supercalifragilistic", "x"], 5, 0, 1), vec!["supercalifragilistic", " x"]);
+        assert_eq!(wrap::<&str>(&[], 10, 0, 0), vec![""]);
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert!(fnv1a(b"ls target/debug") != fnv1a(b"ls target/release"));
+    }
+
     #[test]
     fn test_normalise_extension() {
         assert_eq!(normalise_extension("hello".to_string()), ".hello".to_string());
@@ -0,0 +1,198 @@
+// Loads the scripts that `completist generate` writes for fish, bash and zsh
+// into real shells, and checks that each shell offers what `completist
+// complete` does. Everything runs in a scratch cargo project, so that command
+// kinds such as `cargo bench --bin` have binaries to list.
+//
+// A shell that isn't installed is skipped with a note on stderr, so the tests
+// pass on machines that only have some of them.
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+
+const LINES: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("cat.ct", "cat -", "-A"),
+    ("cat.ct", "cat --show-", "--show-tabs"),
+    ("cat.ct", "cat -A -", "-A"),
+    ("cargo.ct", "cargo be", "bench"),
+    ("cargo.ct", "cargo bench --", "--bin"),
+    ("cargo.ct", "cargo bench --bin ", "completist"),
+];
+
+// Integration tests are built into target/<profile>/deps, next to the binary's
+// own directory.
+fn bin_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    let dir = exe.parent().unwrap();
+    if dir.ends_with("deps") { dir.parent().unwrap().to_path_buf() } else { dir.to_path_buf() }
+}
+
+fn spec(name: &str) -> String {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("samples").join(name).to_string_lossy().into_owned()
+}
+
+// A directory with a built `completist` binary in it, removed when dropped.
+struct Project(PathBuf);
+
+impl Project {
+    fn new(name: &str) -> Project {
+        let dir = env::temp_dir().join(format!("completist-{}-{}", name, process::id()));
+        fs::create_dir_all(dir.join("target").join("debug")).unwrap();
+        File::create(dir.join("target").join("debug").join("completist")).unwrap();
+        Project(dir)
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+fn require(shell: &str) -> bool {
+    Command::new(shell).arg("-c").arg("true")
+        .stdout(Stdio::null()).stderr(Stdio::null())
+        .status().map(|s| s.success()).unwrap_or(false)
+}
+
+fn completist(project: &Project, args: &[&str]) -> String {
+    let output = Command::new(bin_dir().join("completist")).args(args)
+        .current_dir(&project.0)
+        .output().unwrap();
+    assert!(output.status.success(), "completist {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn sorted(lines: &str) -> Vec<String> {
+    let mut values: Vec<String> = lines.lines()
+        .map(|line| line.trim_end_matches('\r').split('\t').next().unwrap().to_string())
+        .filter(|value| !value.is_empty())
+        .collect();
+    values.sort();
+    values.dedup();
+    values
+}
+
+// The candidates the engine gives for the line, with the cursor on its last
+// word.
+fn expected(project: &Project, spec: &str, line: &str) -> Vec<String> {
+    let mut args = vec!["complete", spec, "--"];
+    args.extend(line.split(' '));
+    sorted(&completist(project, &args))
+}
+
+fn run(project: &Project, shell: &str, flags: &[&str], script: &str) -> String {
+    let output = Command::new(shell).args(flags).arg("-c").arg(script)
+        .current_dir(&project.0)
+        .stdin(Stdio::null())
+        .output().unwrap();
+    assert!(output.status.success(), "{}: {}", shell, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn check_shell<F>(shell: &str, complete: F) where F: Fn(&Project, &str, &str) -> String {
+    if !require(shell) {
+        eprintln!("skipping the {} tests: {} is not installed", shell, shell);
+        return;
+    }
+    let project = Project::new(shell);
+    for &(name, line, sure) in LINES {
+        let spec = spec(name);
+        let script = project.0.join(format!("{}.{}", name, shell));
+        let text = completist(&project, &["generate", &spec, "--format", shell]);
+        File::create(&script).and_then(|mut f| f.write_all(text.as_bytes())).unwrap();
+        let offered = sorted(&complete(&project, &script.to_string_lossy(), line));
+
+        assert!(offered.iter().any(|v| v == sure), "{}: `{}` doesn't offer {}: {:?}", shell, line, sure, offered);
+        assert_eq!(offered, expected(&project, &spec, line), "{}: `{}`", shell, line);
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace("'", "'\\''"))
+}
+
+// COMP_WORDS is split the way bash splits it, at `=` as well as whitespace.
+#[test]
+fn bash() {
+    check_shell("bash", |project, script, line| {
+        let program = line.split(' ').next().unwrap();
+        run(project, "bash", &["--norc", "--noprofile"], &format!("
+            source {script}
+            COMP_LINE={line}
+            COMP_POINT=${{#COMP_LINE}}
+            IFS=$' \\t=' read -ra COMP_WORDS <<< \"$COMP_LINE\"
+            [[ $COMP_LINE == *[\\ =] ]] && COMP_WORDS+=('')
+            COMP_CWORD=$(( ${{#COMP_WORDS[@]}} - 1 ))
+            $(complete -p {program} | sed 's/.*-F \\([^ ]*\\).*/\\1/')
+            printf '%s\\n' \"${{COMPREPLY[@]}}\"
+        ", script = quote(script), line = quote(line), program = quote(program)))
+    });
+}
+
+#[test]
+fn fish() {
+    check_shell("fish", |project, script, line| {
+        // Keep fish's own completions for the same commands out of the way.
+        run(project, "fish", &["--no-config"], &format!("
+            set -g fish_complete_path
+            source {script}
+            complete -C {line}
+        ", script = quote(script), line = quote(line)))
+    });
+}
+
+// zsh only completes inside the line editor, so the completion is typed into
+// an interactive zsh on a pseudo-terminal. `compadd` is wrapped to print every
+// match it adds, and the shell exits once completion is done.
+#[test]
+fn zsh() {
+    check_shell("zsh", |project, script, line| {
+        let setup = format!("
+            PROMPT=''
+            autoload -U compinit && compinit -u -D
+            compadd () {{
+                if [[ ${{@[1,(i)(-|--)]}} == *-(O|A|D)\\ * ]]; then
+                    builtin compadd \"$@\"
+                    return $?
+                fi
+                local -a __hits
+                builtin compadd -A __hits \"$@\"
+                (( $#__hits )) || return 1
+                print -n -- $'\\n'
+                print -rl -- \"${{__hits[@]/#/<HIT>}}\"
+                builtin compadd \"$@\"
+            }}
+            __done () {{ print -n -- $'\\n<DONE>\\n'; exit }}
+            compprefuncs=()
+            comppostfuncs=(__done)
+            bindkey '^I' complete-word
+            source {script}
+        ", script = quote(script));
+
+        let output = run(project, "zsh", &["-f"], &format!("
+            zmodload zsh/zpty
+            zpty z zsh -f -i
+            zpty -w z {setup}
+            zpty -n -w z {line}$'\\t'
+            while zpty -r z chunk; do
+                print -rn -- \"$chunk\"
+                [[ $chunk == *'<DONE>'* ]] && break
+            done
+            zpty -d z
+        ", setup = quote(&setup), line = quote(line)));
+        output.lines()
+            .filter_map(|l| l.trim_end_matches('\r').find("<HIT>").map(|i| l[i + 5..].trim_end_matches('\r').to_string()))
+            .collect::<Vec<String>>()
+            .join("\n")
+    });
+}
+
+// Cases that run commands need a cargo project with a built binary.
+#[test]
+fn cases_that_run_commands() {
+    let project = Project::new("cases");
+    let cases = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("samples").join("cargo.cases");
+    completist(&project, &["test", &spec("cargo.ct"), &cases.to_string_lossy()]);
+}